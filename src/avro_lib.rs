@@ -0,0 +1,174 @@
+/// Avro targets, so pipeline outputs can be consumed by tools in the Kafka and Hadoop
+/// ecosystems.
+pub mod avro {
+    use std::{
+        fs,
+        marker::PhantomData,
+        path::{Path, PathBuf},
+    };
+
+    use anyhow::Result;
+    use apache_avro::{from_value, Reader, Schema, Writer};
+    use serde::{de::DeserializeOwned, Serialize};
+
+    use crate::tasks::Target;
+
+    /// AvroTarget stores a Vec of records in an Avro container file. The writer schema
+    /// is embedded in the file, and records are resolved against the target's schema on
+    /// read, so files written with an older compatible schema (e.g., one without a field
+    /// that now has a default) can still be read.
+    ///
+    /// As a Target the bytes are the JSON serialization of the Vec of records, so tasks
+    /// can keep using serde_json in compute_output. Use write_records and read_records
+    /// to skip the JSON step.
+    pub struct AvroTarget<T> {
+        filename: PathBuf,
+        schema: Schema,
+        records: PhantomData<fn() -> T>,
+    }
+
+    impl<T: Serialize + DeserializeOwned> AvroTarget<T> {
+        /// Create a target using the schema given as Avro JSON
+        pub fn new(cache_dir: &str, local_filename: &str, schema: &str) -> Result<Self> {
+            Ok(AvroTarget {
+                filename: Path::new(cache_dir).join(local_filename),
+                schema: Schema::parse_str(schema)?,
+                records: PhantomData,
+            })
+        }
+
+        /// Cache full filename
+        pub fn filename(&self) -> &Path {
+            &self.filename
+        }
+
+        /// Schema used to write records and to resolve them on read
+        pub fn schema(&self) -> &Schema {
+            &self.schema
+        }
+
+        /// Write the records to the container file, replacing any existing file
+        pub fn write_records(&self, records: &[T]) -> Result<()> {
+            let mut writer = Writer::new(&self.schema, Vec::new())?;
+            for record in records {
+                writer.append_ser(record)?;
+            }
+            fs::write(&self.filename, writer.into_inner()?)?;
+            Ok(())
+        }
+
+        /// Read the records from the container file
+        pub fn read_records(&self) -> Result<Vec<T>> {
+            let file = fs::File::open(&self.filename)?;
+            let reader = Reader::builder(file).reader_schema(&self.schema).build()?;
+            let mut records = Vec::new();
+            for value in reader {
+                records.push(from_value(&value?)?);
+            }
+            Ok(records)
+        }
+    }
+
+    impl<T: Serialize + DeserializeOwned> Target for AvroTarget<T> {
+        fn read(&self) -> Result<Vec<u8>> {
+            Ok(serde_json::to_vec(&self.read_records()?)?)
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            let records: Vec<T> = serde_json::from_slice(s)?;
+            self.write_records(&records)
+        }
+
+        fn delete(&self) -> Result<()> {
+            if self.exists()? {
+                fs::remove_file(&self.filename)?;
+            }
+            Ok(())
+        }
+
+        fn exists(&self) -> Result<bool> {
+            Ok(self.filename.is_file())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use serde::{Deserialize, Serialize};
+
+        use super::AvroTarget;
+        use crate::tasks::Target;
+
+        const SCHEMA: &str = r#"{
+            "type": "record",
+            "name": "Reading",
+            "fields": [
+                {"name": "sensor", "type": "string"},
+                {"name": "value", "type": "double"}
+            ]
+        }"#;
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        struct Reading {
+            sensor: String,
+            value: f64,
+        }
+
+        fn readings() -> Vec<Reading> {
+            vec![
+                Reading {
+                    sensor: "a".to_string(),
+                    value: 1.5,
+                },
+                Reading {
+                    sensor: "b".to_string(),
+                    value: -2.0,
+                },
+            ]
+        }
+
+        #[test]
+        fn round_trip() {
+            let target =
+                AvroTarget::<Reading>::new("/tmp", "test_avro_round_trip.avro", SCHEMA).unwrap();
+            target.delete().unwrap();
+            assert!(!target.exists().unwrap());
+
+            target.write_records(&readings()).unwrap();
+            assert!(target.exists().unwrap());
+            assert_eq!(target.read_records().unwrap(), readings());
+
+            // through the Target interface the records are JSON
+            target
+                .write(&serde_json::to_vec(&readings()[..1]).unwrap())
+                .unwrap();
+            let read: Vec<Reading> = serde_json::from_slice(&target.read().unwrap()).unwrap();
+            assert_eq!(read, readings()[..1].to_vec());
+        }
+
+        #[test]
+        fn schema_evolution() {
+            #[derive(Debug, PartialEq, Deserialize, Serialize)]
+            struct ReadingWithUnit {
+                sensor: String,
+                value: f64,
+                unit: String,
+            }
+            let old =
+                AvroTarget::<Reading>::new("/tmp", "test_avro_evolution.avro", SCHEMA).unwrap();
+            old.write_records(&readings()).unwrap();
+
+            let new_schema = SCHEMA.replace(
+                r#"{"name": "value", "type": "double"}"#,
+                r#"{"name": "value", "type": "double"},
+                {"name": "unit", "type": "string", "default": "celsius"}"#,
+            );
+            let new =
+                AvroTarget::<ReadingWithUnit>::new("/tmp", "test_avro_evolution.avro", &new_schema)
+                    .unwrap();
+            let records = new.read_records().unwrap();
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[1].value, -2.0);
+            assert!(records.iter().all(|r| r.unit == "celsius"));
+        }
+    }
+}
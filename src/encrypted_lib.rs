@@ -0,0 +1,126 @@
+/// Encrypted targets, for sensitive outputs cached on shared disks.
+pub mod encrypted {
+    use aes_gcm::{
+        aead::{Aead, Generate, KeyInit, Nonce},
+        Aes256Gcm,
+    };
+    use anyhow::{anyhow, Result};
+
+    use crate::tasks::Target;
+
+    /// Bytes of the random nonce stored in front of each ciphertext
+    const NONCE_LEN: usize = 12;
+
+    /// EncryptedTarget encrypts data written to an inner target with AES-256-GCM and
+    /// decrypts it on read. Every write uses a fresh random nonce, stored in front of
+    /// the ciphertext, and data that has been tampered with (or was encrypted with
+    /// another key) fails to read. exists and delete are delegated to the inner target
+    /// unchanged.
+    pub struct EncryptedTarget<T: Target> {
+        inner: T,
+        cipher: Aes256Gcm,
+    }
+
+    impl<T: Target> EncryptedTarget<T> {
+        pub fn new(inner: T, key: &[u8; 32]) -> Self {
+            EncryptedTarget {
+                inner,
+                cipher: Aes256Gcm::new(&(*key).into()),
+            }
+        }
+
+        pub fn inner(&self) -> &T {
+            &self.inner
+        }
+    }
+
+    impl<T: Target> Target for EncryptedTarget<T> {
+        fn read(&self) -> Result<Vec<u8>> {
+            let data = self.inner.read()?;
+            if data.len() < NONCE_LEN {
+                return Err(anyhow!("Encrypted data is too short"));
+            }
+            let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+            let nonce = Nonce::<Aes256Gcm>::try_from(nonce)?;
+            self.cipher
+                .decrypt(&nonce, ciphertext)
+                .map_err(|_| anyhow!("Failed to decrypt: wrong key or tampered data"))
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            let nonce = Nonce::<Aes256Gcm>::generate();
+            let ciphertext = self
+                .cipher
+                .encrypt(&nonce, s)
+                .map_err(|_| anyhow!("Failed to encrypt"))?;
+            let mut data = nonce.to_vec();
+            data.extend(ciphertext);
+            self.inner.write(&data)
+        }
+
+        fn delete(&self) -> Result<()> {
+            self.inner.delete()
+        }
+
+        fn exists(&self) -> Result<bool> {
+            self.inner.exists()
+        }
+
+        fn version(&self) -> Result<Option<String>> {
+            self.inner.version()
+        }
+
+        fn last_modified(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+            self.inner.last_modified()
+        }
+
+        fn size(&self) -> Result<Option<u64>> {
+            self.inner.size()
+        }
+
+        fn sidecar(&self, suffix: &str) -> Option<Box<dyn Target>> {
+            self.inner.sidecar(suffix)
+        }
+
+        fn uri(&self) -> Option<String> {
+            self.inner.uri()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::EncryptedTarget;
+        use crate::tasks::{FileTarget, Target};
+
+        #[test]
+        fn round_trip() {
+            let key = [7u8; 32];
+            let target = EncryptedTarget::new(
+                Box::new(FileTarget::new("/tmp", "test_encrypted_target.bin")) as Box<dyn Target>,
+                &key,
+            );
+            let plaintext = b"account 1234, balance 99".to_vec();
+            target.write(&plaintext).unwrap();
+            let stored = target.inner().read().unwrap();
+            assert_ne!(stored, plaintext);
+            assert!(!stored
+                .windows(plaintext.len())
+                .any(|window| window == plaintext));
+            assert_eq!(target.read().unwrap(), plaintext);
+
+            // another key can't read it
+            let other = EncryptedTarget::new(
+                FileTarget::new("/tmp", "test_encrypted_target.bin"),
+                &[8u8; 32],
+            );
+            assert!(other.read().is_err());
+
+            // and neither can anyone after tampering
+            let mut tampered = stored;
+            let last = tampered.len() - 1;
+            tampered[last] ^= 1;
+            target.inner().write(&tampered).unwrap();
+            assert!(target.read().is_err());
+        }
+    }
+}
@@ -0,0 +1,116 @@
+/// Named pipe targets, for streaming task output to a consumer process as it is
+/// written.
+pub mod fifo {
+    use std::{
+        fs,
+        io::{Read, Write},
+        os::unix::fs::FileTypeExt,
+        path::{Path, PathBuf},
+    };
+
+    use anyhow::{anyhow, Result};
+    use nix::{sys::stat::Mode, unistd::mkfifo};
+
+    use crate::tasks::Target;
+
+    /// FifoTarget writes to a FIFO (named pipe), creating it if needed. write blocks
+    /// until a reader opens the FIFO, and read blocks until a writer opens it and then
+    /// reads until the writer closes it. exists is true once the FIFO node has been
+    /// created, whether or not anything was written, so a task with a FifoTarget is
+    /// considered done once its pipe exists.
+    pub struct FifoTarget {
+        filename: PathBuf,
+    }
+
+    impl FifoTarget {
+        pub fn new(cache_dir: &str, local_filename: &str) -> Self {
+            FifoTarget {
+                filename: Path::new(cache_dir).join(local_filename),
+            }
+        }
+
+        /// Full filename of the FIFO
+        pub fn filename(&self) -> &Path {
+            &self.filename
+        }
+
+        /// Create the FIFO unless it already exists, failing if something else has its
+        /// name
+        pub fn create(&self) -> Result<()> {
+            if !self.exists()? {
+                if self.filename.exists() {
+                    return Err(anyhow!("{:?} exists and isn't a FIFO", self.filename));
+                }
+                mkfifo(&self.filename, Mode::S_IRUSR | Mode::S_IWUSR)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl Target for FifoTarget {
+        /// Blocks until a writer opens the FIFO, then reads until it's closed
+        fn read(&self) -> Result<Vec<u8>> {
+            let mut data = Vec::new();
+            fs::File::open(&self.filename)?.read_to_end(&mut data)?;
+            Ok(data)
+        }
+
+        /// Blocks until a reader opens the FIFO
+        fn write(&self, s: &[u8]) -> Result<()> {
+            self.create()?;
+            let mut pipe = fs::OpenOptions::new().write(true).open(&self.filename)?;
+            pipe.write_all(s)?;
+            Ok(())
+        }
+
+        fn delete(&self) -> Result<()> {
+            if self.exists()? {
+                fs::remove_file(&self.filename)?;
+            }
+            Ok(())
+        }
+
+        fn exists(&self) -> Result<bool> {
+            match fs::symlink_metadata(&self.filename) {
+                Ok(metadata) => Ok(metadata.file_type().is_fifo()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+                Err(e) => Err(e.into()),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::{fs, thread};
+
+        use super::FifoTarget;
+        use crate::tasks::Target;
+
+        #[test]
+        fn reader_consumes_writes() {
+            let target = FifoTarget::new("/tmp", "test_fifo_target.fifo");
+            target.delete().unwrap();
+            assert!(!target.exists().unwrap());
+            target.create().unwrap();
+            assert!(target.exists().unwrap());
+
+            let reader = thread::spawn(|| {
+                FifoTarget::new("/tmp", "test_fifo_target.fifo")
+                    .read()
+                    .unwrap()
+            });
+            let data = "streamed line\n".repeat(10_000).into_bytes();
+            target.write(&data).unwrap();
+            assert_eq!(reader.join().unwrap(), data);
+
+            target.delete().unwrap();
+            assert!(!target.exists().unwrap());
+
+            // a regular file with the same name isn't mistaken for the FIFO
+            fs::write(target.filename(), "not a pipe").unwrap();
+            assert!(!target.exists().unwrap());
+            assert!(target.create().is_err());
+            fs::remove_file(target.filename()).unwrap();
+        }
+    }
+}
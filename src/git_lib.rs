@@ -0,0 +1,167 @@
+/// Git backed targets, for small outputs that should be versioned for audit.
+pub mod git {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use anyhow::Result;
+    use git2::{ErrorCode, Repository, Signature};
+
+    use crate::tasks::Target;
+
+    /// Author used when the repository has no user.name / user.email configured
+    const DEFAULT_AUTHOR: (&str, &str) = ("tasks", "tasks@localhost");
+
+    /// GitTarget stores data in a file inside a git working tree and commits every
+    /// write and delete, with a message naming the task. The repository is opened for
+    /// each operation, so targets in the same repository shouldn't be written
+    /// concurrently (git's index lock makes the second writer fail).
+    pub struct GitTarget {
+        repo_dir: PathBuf,
+        local_filename: String,
+        task_name: String,
+    }
+
+    impl GitTarget {
+        /// local_filename is relative to the root of the repository at repo_dir
+        pub fn new(repo_dir: &str, local_filename: &str, task_name: &str) -> Self {
+            GitTarget {
+                repo_dir: PathBuf::from(repo_dir),
+                local_filename: local_filename.to_string(),
+                task_name: task_name.to_string(),
+            }
+        }
+
+        /// Full filename in the working tree
+        pub fn filename(&self) -> PathBuf {
+            self.repo_dir.join(&self.local_filename)
+        }
+
+        // Stage the file (or its removal) and commit, unless the tree is unchanged
+        fn commit(&self, action: &str, remove: bool) -> Result<()> {
+            let repo = Repository::open(&self.repo_dir)?;
+            let mut index = repo.index()?;
+            let path = Path::new(&self.local_filename);
+            if remove {
+                index.remove_path(path)?;
+            } else {
+                index.add_path(path)?;
+            }
+            index.write()?;
+            let tree = repo.find_tree(index.write_tree()?)?;
+            let parent = match repo.head() {
+                Ok(head) => Some(head.peel_to_commit()?),
+                Err(e)
+                    if e.code() == ErrorCode::UnbornBranch || e.code() == ErrorCode::NotFound =>
+                {
+                    None
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if let Some(parent) = &parent {
+                if parent.tree_id() == tree.id() {
+                    return Ok(());
+                }
+            }
+            let signature = repo
+                .signature()
+                .or_else(|_| Signature::now(DEFAULT_AUTHOR.0, DEFAULT_AUTHOR.1))?;
+            let message = format!("{}: {} {}", self.task_name, action, self.local_filename);
+            let parents = parent.iter().collect::<Vec<_>>();
+            repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                &message,
+                &tree,
+                &parents,
+            )?;
+            Ok(())
+        }
+    }
+
+    impl Target for GitTarget {
+        /// Current working tree contents
+        fn read(&self) -> Result<Vec<u8>> {
+            Ok(fs::read(self.filename())?)
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            let filename = self.filename();
+            if let Some(dir) = filename.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(filename, s)?;
+            self.commit("update", false)
+        }
+
+        fn delete(&self) -> Result<()> {
+            if self.exists()? {
+                fs::remove_file(self.filename())?;
+                self.commit("delete", true)?;
+            }
+            Ok(())
+        }
+
+        fn exists(&self) -> Result<bool> {
+            Ok(self.filename().is_file())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::fs;
+
+        use git2::{Repository, Sort};
+
+        use super::GitTarget;
+        use crate::tasks::Target;
+
+        fn commit_messages(repo: &Repository) -> Vec<String> {
+            let mut walk = repo.revwalk().unwrap();
+            walk.set_sorting(Sort::TOPOLOGICAL).unwrap();
+            walk.push_head().unwrap();
+            walk.map(|id| {
+                let commit = repo.find_commit(id.unwrap()).unwrap();
+                commit.message().unwrap().to_string()
+            })
+            .collect()
+        }
+
+        #[test]
+        fn commits_writes() {
+            let repo_dir = "/tmp/test_git_target_repo";
+            let _ = fs::remove_dir_all(repo_dir);
+            let repo = Repository::init(repo_dir).unwrap();
+
+            let target = GitTarget::new(repo_dir, "outputs/result.txt", "MyTask");
+            assert!(!target.exists().unwrap());
+            target.write("first".as_bytes()).unwrap();
+            assert!(target.exists().unwrap());
+            assert_eq!(target.read().unwrap(), "first".as_bytes().to_vec());
+            assert_eq!(
+                commit_messages(&repo),
+                vec!["MyTask: update outputs/result.txt"]
+            );
+
+            // rewriting the same data doesn't create an empty commit
+            target.write("first".as_bytes()).unwrap();
+            assert_eq!(commit_messages(&repo).len(), 1);
+
+            target.write("second".as_bytes()).unwrap();
+            target.delete().unwrap();
+            assert!(!target.exists().unwrap());
+            assert_eq!(
+                commit_messages(&repo),
+                vec![
+                    "MyTask: delete outputs/result.txt",
+                    "MyTask: update outputs/result.txt",
+                    "MyTask: update outputs/result.txt",
+                ]
+            );
+            let head = repo.head().unwrap().peel_to_tree().unwrap();
+            assert!(head.get_path("outputs/result.txt".as_ref()).is_err());
+        }
+    }
+}
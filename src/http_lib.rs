@@ -0,0 +1,138 @@
+/// HTTP targets, for pipelines whose first task downloads a remote input.
+pub mod http {
+    use std::io::Read;
+
+    use anyhow::{anyhow, Result};
+
+    use crate::tasks::Target;
+
+    /// HttpTarget reads a URL with GET. It is read-only: write and delete fail. exists
+    /// sends a HEAD request and is true if the response is 200 OK, and the version is
+    /// the ETag (or Last-Modified) header, so tasks depending on it are recomputed when
+    /// the remote data changes. Wrap the download in a task with a file target to
+    /// cache it.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct HttpTarget {
+        url: String,
+    }
+
+    impl HttpTarget {
+        pub fn new(url: &str) -> Self {
+            HttpTarget {
+                url: url.to_string(),
+            }
+        }
+
+        pub fn url(&self) -> &str {
+            &self.url
+        }
+
+        // HEAD response, or None if the server answered with an error status
+        fn head(&self) -> Result<Option<ureq::Response>> {
+            match ureq::head(&self.url).call() {
+                Ok(response) => Ok(Some(response)),
+                Err(ureq::Error::Status(_, _)) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        }
+
+        fn read_only(&self) -> anyhow::Error {
+            anyhow!("HttpTarget {} is read-only", self.url)
+        }
+    }
+
+    impl Target for HttpTarget {
+        fn read(&self) -> Result<Vec<u8>> {
+            let mut data = Vec::new();
+            self.read_stream()?.read_to_end(&mut data)?;
+            Ok(data)
+        }
+
+        /// Read the response body as it arrives
+        fn read_stream(&self) -> Result<Box<dyn Read>> {
+            let response = ureq::get(&self.url)
+                .call()
+                .map_err(|e| anyhow!("GET {} failed: {}", self.url, e))?;
+            Ok(Box::new(response.into_reader()))
+        }
+
+        fn write(&self, _s: &[u8]) -> Result<()> {
+            Err(self.read_only())
+        }
+
+        fn delete(&self) -> Result<()> {
+            Err(self.read_only())
+        }
+
+        fn exists(&self) -> Result<bool> {
+            Ok(self
+                .head()?
+                .is_some_and(|response| response.status() == 200))
+        }
+
+        fn version(&self) -> Result<Option<String>> {
+            Ok(self.head()?.and_then(|response| {
+                response
+                    .header("ETag")
+                    .or_else(|| response.header("Last-Modified"))
+                    .map(str::to_string)
+            }))
+        }
+
+        fn uri(&self) -> Option<String> {
+            Some(self.url.clone())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::{
+            io::{BufRead, BufReader, Write},
+            net::TcpListener,
+            thread,
+        };
+
+        use super::HttpTarget;
+        use crate::tasks::Target;
+
+        /// Serve the responses, one per connection, on a local port, returning its URL
+        fn serve(responses: Vec<&'static str>) -> String {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            thread::spawn(move || {
+                for response in responses {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    // skip the request head
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).unwrap() > 2 {
+                        line.clear();
+                    }
+                    stream.write_all(response.as_bytes()).unwrap();
+                }
+            });
+            url
+        }
+
+        #[test]
+        fn read_and_exists() {
+            let url = serve(vec![
+                "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n",
+                "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n",
+                "HTTP/1.1 200 OK\r\nContent-Length: 11\r\nConnection: close\r\n\r\nremote data",
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            ]);
+            let target = HttpTarget::new(&format!("{}/data.csv", url));
+            assert!(target.exists().unwrap());
+            assert_eq!(target.version().unwrap(), Some("\"v1\"".to_string()));
+            assert_eq!(target.read().unwrap(), "remote data".as_bytes().to_vec());
+            assert!(!target.exists().unwrap());
+            assert!(target.read().is_err());
+
+            let err = target.write("data".as_bytes()).unwrap_err();
+            assert!(err.to_string().contains("read-only"));
+            assert!(target.delete().is_err());
+        }
+    }
+}
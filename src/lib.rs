@@ -1,5 +1,29 @@
+#[cfg(feature = "avro")]
+mod avro_lib;
+#[cfg(feature = "encrypted")]
+mod encrypted_lib;
+#[cfg(all(unix, feature = "fifo"))]
+mod fifo_lib;
+#[cfg(feature = "git")]
+mod git_lib;
+#[cfg(feature = "http")]
+mod http_lib;
+#[cfg(feature = "mmap")]
+mod mmap_lib;
+mod pipeline_lib;
+#[cfg(feature = "postgres")]
+mod postgres_lib;
+mod resilience_lib;
+#[cfg(feature = "s3")]
+mod s3_lib;
 mod scheduler_lib;
+#[cfg(feature = "sqlite")]
+mod sqlite_lib;
+mod targets_lib;
 mod task_lib;
 
+pub use pipeline_lib::pipeline;
+pub use resilience_lib::resilience;
 pub use scheduler_lib::scheduler;
+pub use targets_lib::targets;
 pub use task_lib::tasks;
@@ -0,0 +1,139 @@
+/// Memory-mapped file targets, for large outputs that are read many times.
+///
+/// Safety: a memory map is only valid while the underlying file isn't modified
+/// by anyone else. Truncating or rewriting the file in place from another
+/// process while a map is alive is undefined behaviour and typically crashes with
+/// SIGBUS. MmapTarget itself replaces the file on write rather than modifying it,
+/// so existing maps stay valid, but there must be no concurrent writers.
+pub mod mmap {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+    };
+
+    use anyhow::Result;
+    use memmap2::{Mmap, MmapMut};
+
+    use crate::tasks::Target;
+
+    /// MmapTarget stores data in a file and reads it through a memory map. The map is
+    /// created on first read and kept until this target writes or deletes the file.
+    pub struct MmapTarget {
+        filename: PathBuf,
+        map: Mutex<Option<Arc<Mmap>>>,
+    }
+
+    impl MmapTarget {
+        pub fn new(cache_dir: &str, local_filename: &str) -> Self {
+            MmapTarget {
+                filename: Path::new(cache_dir).join(local_filename),
+                map: Mutex::new(None),
+            }
+        }
+
+        /// Cache full filename
+        pub fn filename(&self) -> &Path {
+            &self.filename
+        }
+
+        /// Zero-copy access to the mapped data. The map stays valid after later writes
+        /// through this target, but then holds the old contents.
+        pub fn mapped(&self) -> Result<Arc<Mmap>> {
+            let mut map = self.map.lock().unwrap();
+            if let Some(mmap) = map.as_ref() {
+                return Ok(mmap.clone());
+            }
+            let file = fs::File::open(&self.filename)?;
+            // Safety: see the module documentation
+            let mmap = Arc::new(unsafe { Mmap::map(&file)? });
+            *map = Some(mmap.clone());
+            Ok(mmap)
+        }
+    }
+
+    impl Target for MmapTarget {
+        /// Copy of the mapped data
+        fn read(&self) -> Result<Vec<u8>> {
+            Ok(self.mapped()?.to_vec())
+        }
+
+        /// Size a new file to the data length, copy the data in through a writable map, then
+        /// rename it over the target file. Maps handed out earlier keep the old file alive.
+        fn write(&self, s: &[u8]) -> Result<()> {
+            let mut map = self.map.lock().unwrap();
+            *map = None;
+            let mut tmp_filename = self.filename.clone().into_os_string();
+            tmp_filename.push(".tmp");
+            let file = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&tmp_filename)?;
+            file.set_len(s.len() as u64)?;
+            let mmap = if s.is_empty() {
+                None
+            } else {
+                // Safety: see the module documentation
+                let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+                mmap.copy_from_slice(s);
+                mmap.flush()?;
+                Some(Arc::new(mmap.make_read_only()?))
+            };
+            fs::rename(&tmp_filename, &self.filename)?;
+            *map = mmap;
+            Ok(())
+        }
+
+        fn delete(&self) -> Result<()> {
+            *self.map.lock().unwrap() = None;
+            if self.exists()? {
+                fs::remove_file(&self.filename)?;
+            }
+            Ok(())
+        }
+
+        fn exists(&self) -> Result<bool> {
+            Ok(self.filename.is_file())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::MmapTarget;
+        use crate::tasks::Target;
+
+        #[test]
+        fn round_trip() {
+            let target = MmapTarget::new("/tmp", "test_mmap_target.bin");
+            target.delete().unwrap();
+            assert!(!target.exists().unwrap());
+
+            let data = (0..4 * 1024 * 1024)
+                .map(|i| (i % 251) as u8)
+                .collect::<Vec<_>>();
+            target.write(&data).unwrap();
+            assert!(target.exists().unwrap());
+            assert_eq!(target.read().unwrap(), data);
+            assert_eq!(&target.mapped().unwrap()[..], &data[..]);
+
+            // a fresh target maps the existing file
+            let target = MmapTarget::new("/tmp", "test_mmap_target.bin");
+            assert_eq!(&target.mapped().unwrap()[..], &data[..]);
+
+            // shrinking and growing the file, old maps keep the old contents
+            let old_map = target.mapped().unwrap();
+            target.write("small".as_bytes()).unwrap();
+            assert_eq!(target.read().unwrap(), "small".as_bytes().to_vec());
+            assert_eq!(&old_map[..], &data[..]);
+            target.write(&data).unwrap();
+            assert_eq!(target.read().unwrap(), data);
+            target.write(&[]).unwrap();
+            assert!(target.read().unwrap().is_empty());
+
+            target.delete().unwrap();
+            assert!(!target.exists().unwrap());
+        }
+    }
+}
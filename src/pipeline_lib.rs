@@ -0,0 +1,351 @@
+/// Registry of named pipelines. A pipeline is a factory for its head task, so a thin
+/// main can pick a pipeline by name (e.g., from its command line arguments) and run it.
+///
+/// Tasks with run_isolated set are run in a child process: the binary is started
+/// again with environment variables naming the pipeline and task, and main should
+/// start with run_isolated_child to pick these up, e.g.,
+///
+/// ```ignore
+/// if let Some(result) = registry.run_isolated_child() {
+///     return result;
+/// }
+/// ```
+pub mod pipeline {
+    use std::{collections::HashMap, env, path::PathBuf, process::Command};
+
+    use anyhow::{anyhow, Result};
+
+    use crate::{
+        scheduler::{RunReport, RunStyle, DAG},
+        tasks::Task,
+    };
+
+    /// Environment variable naming the pipeline of the task an isolated child process runs
+    pub const ISOLATED_PIPELINE_ENV: &str = "RUST_TASKS_ISOLATED_PIPELINE";
+    /// Environment variable holding the Task::identity of the task an isolated child
+    /// process runs
+    pub const ISOLATED_TASK_ENV: &str = "RUST_TASKS_ISOLATED_TASK";
+
+    /// Factory that builds the head task of a pipeline
+    pub type TaskFactory = Box<dyn Fn() -> Box<dyn Task> + Send + Sync>;
+
+    /// PipelineRegistry maps pipeline names to factories for their head tasks
+    #[derive(Default)]
+    pub struct PipelineRegistry {
+        factories: HashMap<String, TaskFactory>,
+        child_command: Option<(PathBuf, Vec<String>)>,
+    }
+
+    impl PipelineRegistry {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Register a pipeline, replacing any existing pipeline with the same name
+        pub fn register<F>(&mut self, name: &str, factory: F)
+        where
+            F: Fn() -> Box<dyn Task> + Send + Sync + 'static,
+        {
+            self.factories.insert(name.to_string(), Box::new(factory));
+        }
+
+        /// Registered pipeline names, sorted
+        pub fn names(&self) -> Vec<String> {
+            let mut names = self.factories.keys().cloned().collect::<Vec<_>>();
+            names.sort();
+            names
+        }
+
+        /// Build the head task of the named pipeline
+        pub fn build(&self, name: &str) -> Result<Box<dyn Task>> {
+            let factory = self.factories.get(name).ok_or_else(|| {
+                anyhow!(
+                    "Unknown pipeline {:?}, registered pipelines: {:?}",
+                    name,
+                    self.names()
+                )
+            })?;
+            Ok(factory())
+        }
+
+        /// Build a DAG for the named pipeline and run it
+        pub fn run_pipeline(&self, name: &str, run_style: &RunStyle) -> Result<RunReport> {
+            let (program, args) = match &self.child_command {
+                Some(command) => command.clone(),
+                None => (env::current_exe()?, Vec::new()),
+            };
+            let pipeline = name.to_string();
+            let mut dag = DAG::new(self.build(name)?)?.with_isolated_runner(move |task| {
+                let status = Command::new(&program)
+                    .args(&args)
+                    .env(ISOLATED_PIPELINE_ENV, &pipeline)
+                    .env(ISOLATED_TASK_ENV, task.identity())
+                    .status()?;
+                if !status.success() {
+                    return Err(anyhow!(
+                        "Isolated task {} failed: {}",
+                        task.get_name(),
+                        status
+                    ));
+                }
+                if !task.output_target()?.exists()? {
+                    return Err(anyhow!(
+                        "Isolated task {} finished without writing its target",
+                        task.get_name()
+                    ));
+                }
+                Ok(())
+            });
+            dag.run(run_style)?;
+            Ok(dag.last_report().cloned().unwrap_or_default())
+        }
+
+        /// Command (with arguments) started to run isolated tasks. By default the current
+        /// executable is started without arguments.
+        pub fn set_child_command(&mut self, program: &str, args: &[&str]) {
+            self.child_command = Some((
+                PathBuf::from(program),
+                args.iter().map(|arg| arg.to_string()).collect(),
+            ));
+        }
+
+        /// If this process was started to run an isolated task, run it (without its
+        /// dependencies, which are already done) and return the result. Returns None
+        /// for a normal start.
+        pub fn run_isolated_child(&self) -> Option<Result<()>> {
+            let pipeline = env::var(ISOLATED_PIPELINE_ENV).ok()?;
+            let identity = env::var(ISOLATED_TASK_ENV).ok()?;
+            Some(self.run_isolated_task(&pipeline, &identity))
+        }
+
+        // Find the task by identity in the pipeline's DAG and run it
+        fn run_isolated_task(&self, pipeline: &str, identity: &str) -> Result<()> {
+            let dag = DAG::new(self.build(pipeline)?)?;
+            let node = dag
+                .nodes
+                .values()
+                .find(|node| node.task.identity() == identity)
+                .ok_or_else(|| anyhow!("No task {:?} in pipeline {:?}", identity, pipeline))?;
+            node.task.run_no_deps()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::collections::HashMap;
+
+        use anyhow::Result;
+
+        use crate::{
+            pipeline::PipelineRegistry,
+            scheduler::{NodeOutcome, RunStyle},
+            tasks::{FileTarget, Target, Task},
+        };
+
+        #[derive(Debug)]
+        struct Leaf {
+            filename: &'static str,
+        }
+        impl Task for Leaf {
+            fn get_name(&self) -> String {
+                self.filename.to_string()
+            }
+
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new("/tmp", self.filename)))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok("leaf data".as_bytes().to_vec())
+            }
+        }
+
+        #[derive(Debug)]
+        struct Head {
+            filename: &'static str,
+            dep_filename: &'static str,
+        }
+        impl Task for Head {
+            fn get_name(&self) -> String {
+                self.filename.to_string()
+            }
+
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new("/tmp", self.filename)))
+            }
+
+            fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                let mut result = HashMap::<String, Box<dyn Task>>::new();
+                result.insert(
+                    "leaf".to_string(),
+                    Box::new(Leaf {
+                        filename: self.dep_filename,
+                    }),
+                );
+                Ok(result)
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                self.get_dep_targets()?.get("leaf").unwrap().read()
+            }
+        }
+
+        fn registry() -> PipelineRegistry {
+            let mut registry = PipelineRegistry::new();
+            registry.register("first", || {
+                Box::new(Head {
+                    filename: "test_pipeline_first_head.txt",
+                    dep_filename: "test_pipeline_first_leaf.txt",
+                })
+            });
+            registry.register("second", || {
+                Box::new(Head {
+                    filename: "test_pipeline_second_head.txt",
+                    dep_filename: "test_pipeline_second_leaf.txt",
+                })
+            });
+            registry
+        }
+
+        #[test]
+        fn run_pipeline_by_name() {
+            let registry = registry();
+            assert_eq!(registry.names(), vec!["first", "second"]);
+            for name in registry.names() {
+                registry
+                    .build(&name)
+                    .unwrap()
+                    .recursively_delete_data()
+                    .expect("Failed to delete pipeline data");
+            }
+
+            let report = registry
+                .run_pipeline("first", &RunStyle::LOCAL)
+                .expect("Failed to run pipeline");
+            assert_eq!(
+                report.names(NodeOutcome::Computed),
+                vec![
+                    "test_pipeline_first_head.txt",
+                    "test_pipeline_first_leaf.txt"
+                ]
+            );
+
+            let first = registry.build("first").unwrap();
+            let second = registry.build("second").unwrap();
+            assert!(first.get_target().unwrap().exists().unwrap());
+            assert!(!second.get_target().unwrap().exists().unwrap());
+            for (_, dep) in second.get_dep_targets().unwrap() {
+                assert!(!dep.exists().unwrap());
+            }
+        }
+
+        /// Writes the id of the process computing it. All parts share a name.
+        #[derive(Debug)]
+        struct Isolated {
+            part: &'static str,
+        }
+        impl Task for Isolated {
+            fn get_name(&self) -> String {
+                "isolated".to_string()
+            }
+
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new(
+                    "/tmp",
+                    &format!("test_pipeline_isolated_{}.txt", self.part),
+                )))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok(std::process::id().to_string().into_bytes())
+            }
+
+            fn run_isolated(&self) -> bool {
+                true
+            }
+        }
+
+        /// Runs in the parent, after both isolated parts
+        #[derive(Debug)]
+        struct IsolatedParts {}
+        impl Task for IsolatedParts {
+            fn get_name(&self) -> String {
+                "isolated_parts".to_string()
+            }
+
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new(
+                    "/tmp",
+                    "test_pipeline_isolated_parts.txt",
+                )))
+            }
+
+            fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                let mut result = HashMap::<String, Box<dyn Task>>::new();
+                for part in ["a", "b"] {
+                    result.insert(part.to_string(), Box::new(Isolated { part }));
+                }
+                Ok(result)
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok(Vec::new())
+            }
+        }
+
+        fn isolated_registry() -> PipelineRegistry {
+            let mut registry = PipelineRegistry::new();
+            registry.register("isolated", || Box::new(IsolatedParts {}));
+            // the child is this test binary, running only isolated_child_entry
+            registry.set_child_command(
+                std::env::current_exe().unwrap().to_str().unwrap(),
+                &[
+                    "--exact",
+                    "pipeline_lib::pipeline::tests::isolated_child_entry",
+                    "--test-threads=1",
+                ],
+            );
+            registry
+        }
+
+        #[test]
+        fn isolated_child_entry() {
+            // a no-op unless started as a child by run_isolated_task
+            if let Some(result) = isolated_registry().run_isolated_child() {
+                result.expect("Isolated task failed");
+            }
+        }
+
+        #[test]
+        fn run_isolated_task() {
+            let registry = isolated_registry();
+            let task = registry.build("isolated").unwrap();
+            task.recursively_delete_data().unwrap();
+            let report = registry
+                .run_pipeline("isolated", &RunStyle::LOCAL)
+                .expect("Failed to run pipeline");
+            assert_eq!(
+                report.names(NodeOutcome::Computed),
+                vec!["isolated", "isolated", "isolated_parts"]
+            );
+            // each part ran in its own child, although they share a name
+            let mut pids = Vec::new();
+            for part in ["a", "b"] {
+                let pid: u32 = String::from_utf8(Isolated { part }.get_data().unwrap())
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+                assert_ne!(pid, std::process::id());
+                pids.push(pid);
+            }
+            assert_ne!(pids[0], pids[1]);
+        }
+
+        #[test]
+        fn unknown_pipeline() {
+            assert!(registry()
+                .run_pipeline("missing", &RunStyle::LOCAL)
+                .is_err());
+        }
+    }
+}
@@ -0,0 +1,298 @@
+/// Retry and circuit breaker policies, shared by targets (e.g., RetryingTarget) and the
+/// task retry loop so resilience is configured in one place.
+pub mod resilience {
+    use std::{
+        collections::hash_map::RandomState,
+        fmt,
+        hash::{BuildHasher, Hasher},
+        sync::Mutex,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use anyhow::Result;
+    use log::warn;
+
+    /// Decides whether an error is worth retrying
+    pub type Retryable = fn(&anyhow::Error) -> bool;
+
+    /// Default Retryable: any error except an open circuit
+    fn unless_circuit_open(e: &anyhow::Error) -> bool {
+        e.downcast_ref::<CircuitOpen>().is_none()
+    }
+
+    /// RetryPolicy retries a failing operation with exponential backoff: retry n waits
+    /// base_delay * multiplier^(n - 1), capped at max_delay. With jitter j each delay is
+    /// reduced by a random fraction of up to j, so clients don't retry in lockstep.
+    #[derive(Clone, Copy)]
+    pub struct RetryPolicy {
+        /// Attempts in total, including the first one
+        pub max_attempts: u32,
+        pub base_delay: Duration,
+        pub multiplier: f64,
+        pub max_delay: Duration,
+        /// Fraction of each delay that is randomized, from 0 (none) to 1
+        pub jitter: f64,
+        retryable: Retryable,
+    }
+
+    impl Default for RetryPolicy {
+        /// Three attempts, doubling from 100ms, retrying any error except an open circuit
+        fn default() -> Self {
+            RetryPolicy::new(3, Duration::from_millis(100))
+        }
+    }
+
+    impl fmt::Debug for RetryPolicy {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("RetryPolicy")
+                .field("max_attempts", &self.max_attempts)
+                .field("base_delay", &self.base_delay)
+                .field("multiplier", &self.multiplier)
+                .field("max_delay", &self.max_delay)
+                .field("jitter", &self.jitter)
+                .finish()
+        }
+    }
+
+    impl RetryPolicy {
+        /// Doubling delays capped at 30s, without jitter, retrying any error except an
+        /// open circuit
+        pub const fn new(max_attempts: u32, base_delay: Duration) -> Self {
+            RetryPolicy {
+                max_attempts,
+                base_delay,
+                multiplier: 2.0,
+                max_delay: Duration::from_secs(30),
+                jitter: 0.0,
+                retryable: unless_circuit_open,
+            }
+        }
+
+        pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+            self.multiplier = multiplier;
+            self
+        }
+
+        pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+            self.max_delay = max_delay;
+            self
+        }
+
+        pub fn with_jitter(mut self, jitter: f64) -> Self {
+            self.jitter = jitter.clamp(0.0, 1.0);
+            self
+        }
+
+        /// Only retry errors for which retryable returns true
+        pub const fn with_retryable(mut self, retryable: Retryable) -> Self {
+            self.retryable = retryable;
+            self
+        }
+
+        /// Delay before retry number `retry` (1 for the first retry), without jitter
+        pub fn delay(&self, retry: u32) -> Duration {
+            let factor = self.multiplier.powi(retry.saturating_sub(1) as i32);
+            let nanos = (self.base_delay.as_nanos() as f64 * factor).round();
+            if nanos >= self.max_delay.as_nanos() as f64 {
+                self.max_delay
+            } else {
+                Duration::from_nanos(nanos as u64)
+            }
+        }
+
+        /// Delay before retry number `retry`, with jitter applied
+        pub fn jittered_delay(&self, retry: u32) -> Duration {
+            self.delay(retry)
+                .mul_f64(1.0 - self.jitter * random_fraction())
+        }
+
+        pub fn is_retryable(&self, e: &anyhow::Error) -> bool {
+            (self.retryable)(e)
+        }
+
+        /// Call f until it succeeds, the error isn't retryable, or the attempts run out,
+        /// returning the last result
+        pub fn run<T>(&self, mut f: impl FnMut() -> Result<T>) -> Result<T> {
+            let mut attempt = 1;
+            loop {
+                match f() {
+                    Err(e) if attempt < self.max_attempts && self.is_retryable(&e) => {
+                        let delay = self.jittered_delay(attempt);
+                        warn!(
+                            "Attempt {} of {} failed, retrying in {:?}: {:?}",
+                            attempt, self.max_attempts, delay, e
+                        );
+                        thread::sleep(delay);
+                        attempt += 1;
+                    }
+                    res => return res,
+                }
+            }
+        }
+    }
+
+    /// Uniformly distributed in [0, 1), from the randomly seeded std hasher
+    fn random_fraction() -> f64 {
+        let hasher = RandomState::new().build_hasher();
+        (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Error returned by CircuitBreaker::call while the circuit is open
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct CircuitOpen {
+        /// Time until calls are let through again
+        pub retry_after: Duration,
+    }
+
+    impl fmt::Display for CircuitOpen {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Circuit open, retry after {:?}", self.retry_after)
+        }
+    }
+
+    impl std::error::Error for CircuitOpen {}
+
+    #[derive(Debug, Default)]
+    struct BreakerState {
+        consecutive_failures: u32,
+        opened_at: Option<Instant>,
+    }
+
+    /// CircuitBreaker stops calling a failing backend: after failure_threshold
+    /// consecutive failures it opens, and calls fail immediately with CircuitOpen until
+    /// the cooldown has passed. Then calls are let through again; one success closes the
+    /// circuit and another failure opens it for a further cooldown. Share it (e.g., in an
+    /// Arc) between everything using the same backend.
+    #[derive(Debug)]
+    pub struct CircuitBreaker {
+        failure_threshold: u32,
+        cooldown: Duration,
+        state: Mutex<BreakerState>,
+    }
+
+    impl CircuitBreaker {
+        pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+            CircuitBreaker {
+                failure_threshold,
+                cooldown,
+                state: Mutex::new(BreakerState::default()),
+            }
+        }
+
+        /// Are calls currently rejected?
+        pub fn is_open(&self) -> bool {
+            self.retry_after().is_some()
+        }
+
+        // Remaining cooldown if the circuit is open
+        fn retry_after(&self) -> Option<Duration> {
+            let state = self.state.lock().unwrap();
+            let elapsed = state.opened_at?.elapsed();
+            self.cooldown.checked_sub(elapsed).filter(|d| !d.is_zero())
+        }
+
+        /// Call f unless the circuit is open, recording whether it succeeded
+        pub fn call<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+            if let Some(retry_after) = self.retry_after() {
+                return Err(CircuitOpen { retry_after }.into());
+            }
+            let res = f();
+            let mut state = self.state.lock().unwrap();
+            match &res {
+                Ok(_) => *state = BreakerState::default(),
+                Err(_) => {
+                    state.consecutive_failures += 1;
+                    if state.consecutive_failures >= self.failure_threshold {
+                        state.opened_at = Some(Instant::now());
+                    }
+                }
+            }
+            res
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::{cell::Cell, thread, time::Duration};
+
+        use anyhow::anyhow;
+
+        use super::{CircuitBreaker, CircuitOpen, RetryPolicy};
+
+        #[test]
+        fn backoff_schedule() {
+            let policy = RetryPolicy::new(6, Duration::from_millis(100))
+                .with_multiplier(2.0)
+                .with_max_delay(Duration::from_millis(500));
+            let delays = (1..=5).map(|retry| policy.delay(retry)).collect::<Vec<_>>();
+            assert_eq!(
+                delays,
+                [100, 200, 400, 500, 500]
+                    .into_iter()
+                    .map(Duration::from_millis)
+                    .collect::<Vec<_>>()
+            );
+
+            // jitter only ever shortens the delay
+            let jittered = policy.with_jitter(0.5);
+            for retry in 1..=5 {
+                let delay = jittered.jittered_delay(retry);
+                assert!(delay <= policy.delay(retry));
+                assert!(delay >= policy.delay(retry) / 2);
+            }
+        }
+
+        #[test]
+        fn retry_run() {
+            let policy = RetryPolicy::new(3, Duration::from_millis(1));
+            let calls = Cell::new(0);
+            let res = policy.run(|| {
+                calls.set(calls.get() + 1);
+                if calls.get() < 3 {
+                    Err(anyhow!("flaky"))
+                } else {
+                    Ok(calls.get())
+                }
+            });
+            assert_eq!(res.unwrap(), 3);
+
+            // errors that aren't retryable are returned straight away
+            let policy = policy.with_retryable(|e| e.to_string() != "fatal");
+            calls.set(0);
+            let res: anyhow::Result<()> = policy.run(|| {
+                calls.set(calls.get() + 1);
+                Err(anyhow!("fatal"))
+            });
+            assert!(res.is_err());
+            assert_eq!(calls.get(), 1);
+        }
+
+        #[test]
+        fn circuit_breaker() {
+            let breaker = CircuitBreaker::new(3, Duration::from_millis(100));
+            let calls = Cell::new(0);
+            let failing = || -> anyhow::Result<()> {
+                calls.set(calls.get() + 1);
+                Err(anyhow!("backend down"))
+            };
+            for _ in 0..3 {
+                assert!(!breaker.is_open());
+                assert!(breaker.call(failing).is_err());
+            }
+            assert!(breaker.is_open());
+
+            // rejected without calling the backend
+            let err = breaker.call(failing).unwrap_err();
+            assert!(err.downcast_ref::<CircuitOpen>().is_some());
+            assert_eq!(calls.get(), 3);
+
+            // after the cooldown calls go through, and a success closes the circuit
+            thread::sleep(Duration::from_millis(120));
+            assert!(!breaker.is_open());
+            assert_eq!(breaker.call(|| Ok(1)).unwrap(), 1);
+            assert!(breaker.call(failing).is_err());
+            assert!(!breaker.is_open());
+        }
+    }
+}
@@ -0,0 +1,203 @@
+/// S3 backed targets, so caches can live in a bucket shared between machines.
+pub mod s3 {
+    use std::sync::OnceLock;
+
+    use anyhow::Result;
+    use aws_config::BehaviorVersion;
+    use aws_sdk_s3::{primitives::ByteStream, Client};
+    use tokio::runtime::Runtime;
+
+    use crate::tasks::Target;
+
+    /// Runtime driving the S3 client, shared by all targets
+    fn runtime() -> Result<&'static Runtime> {
+        static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+        if let Some(runtime) = RUNTIME.get() {
+            return Ok(runtime);
+        }
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        Ok(RUNTIME.get_or_init(|| runtime))
+    }
+
+    /// S3Target stores data in the object prefix/key of a bucket. Operations block on
+    /// a runtime shared by all S3 targets, so they must not be called from async code.
+    pub struct S3Target {
+        client: Client,
+        bucket: String,
+        key: String,
+    }
+
+    impl S3Target {
+        /// Create a target using the default AWS configuration (environment variables,
+        /// profile files, instance metadata, ...)
+        pub fn new(bucket: &str, prefix: &str, key: &str) -> Result<Self> {
+            let config = runtime()?.block_on(aws_config::load_defaults(BehaviorVersion::latest()));
+            Ok(S3Target::with_client(
+                Client::new(&config),
+                bucket,
+                prefix,
+                key,
+            ))
+        }
+
+        /// Create a target using a configured client, e.g., for a custom endpoint
+        pub fn with_client(client: Client, bucket: &str, prefix: &str, key: &str) -> Self {
+            let prefix = prefix.trim_end_matches('/');
+            let key = if prefix.is_empty() {
+                key.to_string()
+            } else {
+                format!("{}/{}", prefix, key)
+            };
+            S3Target {
+                client,
+                bucket: bucket.to_string(),
+                key,
+            }
+        }
+
+        pub fn bucket(&self) -> &str {
+            &self.bucket
+        }
+
+        /// Full object key, including the prefix
+        pub fn key(&self) -> &str {
+            &self.key
+        }
+
+        // ETag of the object, or None if it doesn't exist
+        fn head(&self) -> Result<Option<String>> {
+            let res = runtime()?.block_on(
+                self.client
+                    .head_object()
+                    .bucket(&self.bucket)
+                    .key(&self.key)
+                    .send(),
+            );
+            match res {
+                Ok(head) => Ok(Some(head.e_tag().unwrap_or_default().to_string())),
+                Err(e) if e.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        }
+    }
+
+    impl Target for S3Target {
+        fn read(&self) -> Result<Vec<u8>> {
+            runtime()?.block_on(async {
+                let object = self
+                    .client
+                    .get_object()
+                    .bucket(&self.bucket)
+                    .key(&self.key)
+                    .send()
+                    .await?;
+                Ok(object.body.collect().await?.into_bytes().to_vec())
+            })
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            runtime()?.block_on(
+                self.client
+                    .put_object()
+                    .bucket(&self.bucket)
+                    .key(&self.key)
+                    .body(ByteStream::from(s.to_vec()))
+                    .send(),
+            )?;
+            Ok(())
+        }
+
+        /// Does nothing if the object doesn't exist
+        fn delete(&self) -> Result<()> {
+            if self.exists()? {
+                runtime()?.block_on(
+                    self.client
+                        .delete_object()
+                        .bucket(&self.bucket)
+                        .key(&self.key)
+                        .send(),
+                )?;
+            }
+            Ok(())
+        }
+
+        fn exists(&self) -> Result<bool> {
+            Ok(self.head()?.is_some())
+        }
+
+        /// The object's ETag
+        fn version(&self) -> Result<Option<String>> {
+            self.head()
+        }
+
+        fn uri(&self) -> Option<String> {
+            Some(format!("s3://{}/{}", self.bucket, self.key))
+        }
+
+        /// Objects next to this one, named key.suffix
+        fn sidecar(&self, suffix: &str) -> Option<Box<dyn Target>> {
+            Some(Box::new(S3Target {
+                client: self.client.clone(),
+                bucket: self.bucket.clone(),
+                key: format!("{}.{}", self.key, suffix),
+            }))
+        }
+    }
+
+    /// Run against a MinIO server at TASKS_S3_ENDPOINT (default http://localhost:9000)
+    /// with a bucket named TASKS_S3_BUCKET (default "tasks-test"), using the
+    /// credentials in AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY
+    #[cfg(all(test, feature = "s3-integration"))]
+    mod tests {
+        use std::env;
+
+        use aws_config::{BehaviorVersion, Region};
+        use aws_sdk_s3::Client;
+
+        use super::{runtime, S3Target};
+        use crate::tasks::Target;
+
+        fn minio_client() -> Client {
+            let endpoint =
+                env::var("TASKS_S3_ENDPOINT").unwrap_or_else(|_| "http://localhost:9000".into());
+            let config = runtime()
+                .unwrap()
+                .block_on(aws_config::load_defaults(BehaviorVersion::latest()));
+            let config = aws_sdk_s3::config::Builder::from(&config)
+                .endpoint_url(endpoint)
+                .region(Region::new("us-east-1"))
+                .force_path_style(true)
+                .build();
+            Client::from_conf(config)
+        }
+
+        fn bucket() -> String {
+            env::var("TASKS_S3_BUCKET").unwrap_or_else(|_| "tasks-test".into())
+        }
+
+        #[test]
+        fn read_write_delete() {
+            let target = S3Target::with_client(minio_client(), &bucket(), "cache/", "result.json");
+            assert_eq!(target.key(), "cache/result.json");
+            target.delete().unwrap();
+            assert!(!target.exists().unwrap());
+            // deleting an absent object is fine
+            target.delete().unwrap();
+
+            target.write("some data".as_bytes()).unwrap();
+            assert!(target.exists().unwrap());
+            assert_eq!(target.read().unwrap(), "some data".as_bytes().to_vec());
+            let version = target.version().unwrap();
+            assert!(version.is_some());
+            target.write("other data".as_bytes()).unwrap();
+            assert_ne!(target.version().unwrap(), version);
+
+            target.delete().unwrap();
+            assert!(!target.exists().unwrap());
+            assert!(target.read().is_err());
+        }
+    }
+}
@@ -4,44 +4,93 @@
 /// a compute cluster).
 pub mod scheduler {
     use std::{
-        collections::{HashMap, HashSet},
+        cmp::Ordering,
+        collections::{BTreeMap, HashMap, HashSet, VecDeque},
         fmt,
+        sync::{
+            atomic::{AtomicBool, Ordering as AtomicOrdering},
+            mpsc::Sender,
+            Arc, Mutex,
+        },
+        thread,
+        time::{Duration, Instant},
     };
 
-    use crate::tasks::Task;
-    use anyhow::Result;
-    use log::warn;
-    use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+    use crate::{
+        resilience::RetryPolicy,
+        tasks::{check_timeout, with_write_permits, Target, Task, WritePermits},
+    };
+    use anyhow::{anyhow, Result};
+    use log::{info, warn};
+    use rayon::{
+        prelude::{IntoParallelIterator, ParallelIterator},
+        ThreadPool, ThreadPoolBuilder,
+    };
+    use serde::{Deserialize, Serialize};
     use uuid::Uuid;
 
+    pub use crate::tasks::Codec;
+
+    /// Where a node is in a run. Nodes whose target already exists when the DAG is
+    /// built (or its state loaded) are Skipped, i.e., cached, and are done like Completed
+    /// ones. Nodes that can't run because a dependency failed stay Pending: the run's
+    /// report gives them the NodeOutcome::Blocked outcome.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum TaskStatus {
+        Pending,
+        Running,
+        Skipped,
+        Completed,
+        Failed,
+    }
+
     /// Node data for a DAG including an identifier, a task, parent and children ids,
-    /// and done status
+    /// and run status
     pub struct Node {
         pub id: Uuid,
         pub task: Box<dyn Task>,
-        pub is_done: bool,
-        pub parent: Option<Uuid>,
+        pub status: TaskStatus,
+        /// The nodes depending on this one (several if the task is shared), empty for the
+        /// head node
+        pub parents: HashSet<Uuid>,
         pub children: HashSet<Uuid>,
+        /// The children that are soft dependencies, whose failure doesn't block this node
+        pub soft_children: HashSet<Uuid>,
     }
 
-    /// Tasks don't implement Debug so just print their names
+    /// Tasks don't implement Debug so just print their names, and descriptions if they
+    /// have one
     impl fmt::Debug for Node {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            f.debug_struct("Node")
+            let mut debug = f.debug_struct("Node");
+            debug
                 .field("id", &self.id)
-                .field("task", &self.task.get_name())
-                .field("is_done", &self.is_done)
-                .field("parent", &self.parent)
+                .field("task", &self.task.get_name());
+            if let Some(description) = self.task.description() {
+                debug.field("description", &description);
+            }
+            debug
+                .field("status", &self.status)
+                .field("parents", &self.parents)
                 .field("children", &self.children)
+                .field("soft_children", &self.soft_children)
                 .finish()
         }
     }
 
+    impl Node {
+        /// Is the node's output available, either cached or computed?
+        pub fn is_done(&self) -> bool {
+            matches!(self.status, TaskStatus::Skipped | TaskStatus::Completed)
+        }
+    }
+
     /// ChildData is a struct that contains a task as well as its ID and parent ID
     struct ChildData {
         id: Uuid,
         task: Box<dyn Task>,
         parent: Uuid,
+        soft: bool,
     }
 
     /// NodeWithChildren contains a node and a vec of dependencies, with enough
@@ -51,326 +100,3702 @@ pub mod scheduler {
         children: Vec<ChildData>,
     }
 
-    pub enum RunStyle {
-        LOCAL,
-        PARALLEL,
-        // CLUSTER
+    /// What a DAG run does once a task fails
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum FailurePolicy {
+        /// Start no more tasks: everything that hasn't started yet is blocked, while
+        /// remote tasks already submitted are waited for and reported as they finish
+        FailFast,
+        /// Block the tasks depending on the failed one, but keep running the others
+        #[default]
+        ContinueIndependent,
     }
 
-    /// DAG represents a directed acylic graph corresponding to the logical
-    /// structure of a task with dependencies. It's currently implemented as an
-    /// arena (a map of nodes where nodes specify dependencies), with UUIDs as
-    /// node identifiers.
-    pub struct DAG {
-        pub nodes: HashMap<Uuid, Node>,
+    /// How a node was handled in a DAG run
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum NodeOutcome {
+        /// The target already existed when the DAG was constructed (TaskStatus::Skipped)
+        Cached,
+        Computed,
+        Failed,
+        /// Not run because a task it depends on failed (see RunReport::skipped_due_to)
+        Blocked,
     }
 
-    impl DAG {
-        // Construct a DAG given a Task object
-        pub fn new(head_task: Box<dyn Task>) -> Result<Self> {
-            let mut to_process = Vec::new();
-            let mut processed = HashMap::new();
-            let node_data = DAG::make_node(head_task, None, Uuid::new_v4())?;
-            processed.insert(node_data.node.id, node_data.node);
-            to_process.extend(node_data.children);
+    /// Outcome and wall-clock duration of a single node in a DAG run
+    #[derive(Debug, Clone)]
+    pub struct NodeReport {
+        pub name: String,
+        pub outcome: NodeOutcome,
+        /// Time from the start of the run until the task started (zero for cached and
+        /// blocked nodes)
+        pub start: Duration,
+        pub duration: Duration,
+        /// Peak growth in the process's resident memory (from /proc/self/statm) while the
+        /// task ran, in bytes, if the DAG measures memory and the platform supports it
+        /// (only Linux does). This is process-wide: with PARALLEL, POOL or CLUSTER runs
+        /// it includes the memory of the tasks running at the same time, so it's only
+        /// the task's own peak with LOCAL.
+        pub peak_memory: Option<u64>,
+    }
 
-            while let Some(child_data) = to_process.pop() {
-                let node_data =
-                    DAG::make_node(child_data.task, Some(child_data.parent), child_data.id)?;
-                processed.insert(node_data.node.id, node_data.node);
-                to_process.extend(node_data.children);
+    /// Summary of a DAG run, one entry per node
+    #[derive(Debug, Clone, Default)]
+    pub struct RunReport {
+        pub nodes: Vec<NodeReport>,
+        /// Blocked node names mapped to the name of the failed task that blocked them
+        pub skipped_due_to: HashMap<String, String>,
+    }
+
+    /// How much of a DAG is already cached, by node count and by Task::weight
+    #[derive(Debug, Clone, Copy, PartialEq, Default)]
+    pub struct CacheStatus {
+        pub cached: usize,
+        pub missing: usize,
+        pub cached_weight: f64,
+        pub missing_weight: f64,
+    }
+
+    impl CacheStatus {
+        /// Fraction of the total weight that is cached (1 for an empty DAG)
+        pub fn hit_ratio(&self) -> f64 {
+            let total = self.cached_weight + self.missing_weight;
+            if total > 0.0 {
+                self.cached_weight / total
+            } else {
+                1.0
             }
+        }
+    }
+
+    /// What a run would do with one task, see DAG::dry_run
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct DryRunEntry {
+        pub name: String,
+        /// The target exists, so the task would be skipped
+        pub cached: bool,
+    }
+
+    /// Tasks of a DAG in topological order (dependencies first), with whether each one
+    /// would be computed
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct DryRunReport {
+        pub tasks: Vec<DryRunEntry>,
+    }
 
-            Ok(Self { nodes: processed })
+    impl DryRunReport {
+        /// Names of the tasks that would be computed, in order
+        pub fn to_compute(&self) -> Vec<String> {
+            self.tasks
+                .iter()
+                .filter(|entry| !entry.cached)
+                .map(|entry| entry.name.clone())
+                .collect()
         }
+    }
 
-        // Run all tasks in the DAG according to run_style (e.g., local or multi-threaded parallel)
-        pub fn run(&mut self, run_style: &RunStyle) -> Result<()> {
-            let mut finished = self
-                .nodes
-                .values()
-                .filter(|&node| node.is_done)
-                .map(|node| node.id)
-                .collect::<HashSet<_>>();
-            let mut not_finished = self
+    /// One line per task, e.g., "compute  my_task" or "skip     my_task"
+    impl fmt::Display for DryRunReport {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            for entry in &self.tasks {
+                let action = if entry.cached { "skip" } else { "compute" };
+                writeln!(f, "{:<8} {}", action, entry.name)?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Done status of each node, keyed by Task::identity
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    struct DagState {
+        done: BTreeMap<String, bool>,
+    }
+
+    impl RunReport {
+        /// Names of nodes with the given outcome, sorted
+        pub fn names(&self, outcome: NodeOutcome) -> Vec<String> {
+            let mut names = self
                 .nodes
-                .values()
-                .filter(|&node| !node.is_done)
-                .map(|node| node.id)
-                .collect::<HashSet<_>>();
+                .iter()
+                .filter(|node| node.outcome == outcome)
+                .map(|node| node.name.clone())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        }
 
-            while !&not_finished.is_empty() {
-                let candidate_ids = self.get_run_candidates(&not_finished);
-                match run_style {
-                    RunStyle::LOCAL => {
-                        candidate_ids.clone().into_iter().for_each(|id| {
-                            if let Some(node) = self.nodes.get(&id) {
-                                let task_res = node.task.run_no_deps();
-                                if task_res.is_err() {
-                                    warn!("Task {:?} failed to run", node.task);
-                                    warn!("{:?}", task_res);
-                                }
-                            }
-                        });
-                    }
-                    RunStyle::PARALLEL => {
-                        candidate_ids.clone().into_par_iter().for_each(|id| {
-                            if let Some(node) = self.nodes.get(&id) {
-                                let task_res = node.task.run_no_deps();
-                                if task_res.is_err() {
-                                    warn!("Task {:?} failed to run", node.task);
-                                    warn!("{:?}", task_res);
-                                }
-                            }
-                        });
-                    }
-                };
-                for id in candidate_ids {
-                    if let Some(node) = self.nodes.get_mut(&id) {
-                        node.is_done = true;
-                        finished.insert(id);
-                        not_finished.remove(&id);
+        /// Total wall-clock time spent running tasks (summed over nodes, so parallel runs
+        /// report more than the elapsed time)
+        pub fn total_duration(&self) -> Duration {
+            self.nodes.iter().map(|node| node.duration).sum()
+        }
+
+        /// What changed between this report and a later one, matching nodes by name
+        pub fn diff(&self, other: &RunReport) -> RunDiff {
+            let before = self.by_name();
+            let after = other.by_name();
+            let mut diff = RunDiff::default();
+            for (name, new) in &after {
+                match before.get(name) {
+                    None => diff.appeared.push(name.to_string()),
+                    Some(old) => {
+                        if new.outcome == NodeOutcome::Computed
+                            && old.outcome != NodeOutcome::Computed
+                        {
+                            diff.newly_computed.push(name.to_string());
+                        }
+                        if new.outcome == NodeOutcome::Cached && old.outcome != NodeOutcome::Cached
+                        {
+                            diff.became_cached.push(name.to_string());
+                        }
+                        diff.durations
+                            .insert(name.to_string(), (old.duration, new.duration));
                     }
                 }
             }
-            Ok(())
+            for name in before.keys() {
+                if !after.contains_key(name) {
+                    diff.disappeared.push(name.to_string());
+                }
+            }
+            diff.newly_computed.sort();
+            diff.became_cached.sort();
+            diff.appeared.sort();
+            diff.disappeared.sort();
+            diff
         }
 
-        // Delete all target data
-        pub fn delete_all(&mut self) -> Result<()> {
-            for node in &mut self.nodes.values_mut() {
-                node.task.delete_data()?;
-                node.is_done = false;
+        /// Metrics in the Prometheus text exposition format: node counts by outcome (with
+        /// blocked nodes counted by rust_tasks_skipped_total) and the duration of each task
+        pub fn to_prometheus(&self) -> String {
+            let mut out = String::new();
+            let counters = [
+                ("computed", NodeOutcome::Computed),
+                ("cached", NodeOutcome::Cached),
+                ("failed", NodeOutcome::Failed),
+                ("skipped", NodeOutcome::Blocked),
+            ];
+            for (label, outcome) in counters {
+                let count = self.nodes.iter().filter(|n| n.outcome == outcome).count();
+                out.push_str(&format!(
+                    "# HELP rust_tasks_{0}_total Number of tasks {0} in the run\n\
+                     # TYPE rust_tasks_{0}_total counter\n\
+                     rust_tasks_{0}_total {1}\n",
+                    label, count
+                ));
             }
-            Ok(())
+            out.push_str(
+                "# HELP rust_tasks_duration_seconds Wall-clock time spent running each task\n\
+                 # TYPE rust_tasks_duration_seconds gauge\n",
+            );
+            for node in &self.nodes {
+                out.push_str(&format!(
+                    "rust_tasks_duration_seconds{{task=\"{}\"}} {}\n",
+                    escape_label(&node.name),
+                    node.duration.as_secs_f64()
+                ));
+            }
+            out
         }
 
-        // return run candidates: nodes that are not already done and where the children are all done
-        // (i.e., the dependencies are all satisfied)
-        fn get_run_candidates(&self, not_finished: &HashSet<Uuid>) -> HashSet<Uuid> {
-            let mut candidates = HashSet::new();
-            for id in not_finished {
-                if let Some(node) = self.nodes.get(id) {
-                    if !node.is_done && node.children.intersection(not_finished).next().is_none() {
-                        candidates.insert(*id);
+        /// Chrome trace (chrome://tracing, Perfetto) JSON array with a complete ("X")
+        /// event per task that ran. Tasks that overlap are put on separate thread tracks,
+        /// so the parallelism of the run is visible.
+        pub fn to_chrome_trace(&self) -> String {
+            let mut ran = self
+                .nodes
+                .iter()
+                .filter(|node| matches!(node.outcome, NodeOutcome::Computed | NodeOutcome::Failed))
+                .collect::<Vec<_>>();
+            ran.sort_by_key(|node| (node.start, node.duration));
+            // end time of the last event on each track
+            let mut track_ends: Vec<Duration> = Vec::new();
+            let mut events = Vec::new();
+            for node in ran {
+                let end = node.start + node.duration;
+                let tid = match track_ends.iter().position(|&e| e <= node.start) {
+                    Some(tid) => {
+                        track_ends[tid] = end;
+                        tid
                     }
-                }
+                    None => {
+                        track_ends.push(end);
+                        track_ends.len() - 1
+                    }
+                };
+                events.push(serde_json::json!({
+                    "name": node.name,
+                    "cat": "task",
+                    "ph": "X",
+                    "ts": node.start.as_micros() as u64,
+                    "dur": node.duration.as_micros() as u64,
+                    "pid": 1,
+                    "tid": tid + 1,
+                    "args": {"outcome": format!("{:?}", node.outcome)},
+                }));
             }
-            candidates
+            serde_json::Value::Array(events).to_string()
         }
 
-        /// Make a node and a collection of children with enough information to connect them to the DAG
-        fn make_node(
-            task: Box<dyn Task>,
-            parent_id: Option<Uuid>,
-            node_id: Uuid,
-        ) -> Result<NodeWithChildren> {
-            let is_done = task.get_target()?.exists()?;
-            let dep_tasks = task.get_dep_tasks()?;
-            let child_tasks = dep_tasks.into_values().collect::<Vec<_>>();
-            let mut children = Vec::new();
-            for child in child_tasks {
-                children.push(ChildData {
-                    id: Uuid::new_v4(),
-                    task: child,
-                    parent: node_id,
-                });
-            }
-            let node = Node {
-                id: node_id,
-                task,
-                is_done,
-                parent: parent_id,
-                children: children.iter().map(|c| c.id).collect::<HashSet<_>>(),
-            };
-            Ok(NodeWithChildren { node, children })
+        // Nodes keyed by name (if names repeat the last node wins)
+        fn by_name(&self) -> HashMap<&str, &NodeReport> {
+            self.nodes
+                .iter()
+                .map(|node| (node.name.as_str(), node))
+                .collect()
         }
     }
 
-    /// Print the DAG, one line per node
-    impl fmt::Debug for DAG {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            let mut node_str_vec = Vec::new();
-            for node in self.nodes.values() {
-                node_str_vec.push(format!("{:?}", node));
+    /// Escape a DOT string: quotes and backslashes are escaped, newlines become \n and
+    /// other control characters are written out as escape sequences
+    fn escape_dot(value: &str) -> String {
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            match c {
+                '"' | '\\' => {
+                    escaped.push('\\');
+                    escaped.push(c);
+                }
+                '\n' => escaped.push_str("\\n"),
+                c if c.is_control() => {
+                    escaped.push_str(&c.escape_default().to_string().replace('\\', "\\\\"))
+                }
+                c => escaped.push(c),
             }
-            let s = node_str_vec.join("\n");
-            f.write_str(&s)
         }
+        escaped
     }
 
-    #[cfg(test)]
-    mod tests {
-        use std::collections::HashMap;
-
-        use crate::{
-            scheduler::DAG,
-            tasks::{FileTarget, Target, Task},
-        };
-        use anyhow::Result;
+    /// Escape a Prometheus label value
+    fn escape_label(value: &str) -> String {
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    }
 
-        #[derive(Debug)]
-        struct Dep1 {}
-        impl Task for Dep1 {
-            fn get_name(&self) -> String {
-                "Dep1".to_string()
-            }
+    /// Differences between two run reports, e.g., from consecutive deployments. Name lists
+    /// are sorted.
+    #[derive(Debug, Clone, Default)]
+    pub struct RunDiff {
+        /// Nodes computed in the later run that weren't computed in the earlier one
+        pub newly_computed: Vec<String>,
+        /// Nodes cached in the later run that weren't cached in the earlier one
+        pub became_cached: Vec<String>,
+        /// Nodes only in the later run
+        pub appeared: Vec<String>,
+        /// Nodes only in the earlier run
+        pub disappeared: Vec<String>,
+        /// Durations (earlier, later) of nodes in both runs
+        pub durations: HashMap<String, (Duration, Duration)>,
+    }
 
-            fn get_target(&self) -> Result<Box<dyn Target>> {
-                Ok(Box::new(FileTarget {
-                    cache_dir: "/tmp".to_string(),
-                    local_filename: "test_dag_target_dep1.txt".to_string(),
-                }))
-            }
+    pub enum RunStyle {
+        LOCAL,
+        PARALLEL,
+        /// Like PARALLEL, but on a thread pool of its own with at most max_workers tasks
+        /// running at once (0 for one per logical CPU)
+        POOL {
+            max_workers: usize,
+        },
+        /// Hand each ready task to a dispatcher (e.g., submitting a cluster job), with
+        /// the tasks of a batch dispatched in parallel. Tasks are dispatched by name, so
+        /// runs fail up front if tasks left to run share a name.
+        CLUSTER(Box<dyn TaskDispatcher>),
+    }
 
-            fn compute_output(&self) -> Result<Vec<u8>> {
-                Ok("dep1 data".as_bytes().to_vec())
+    impl RunStyle {
+        // The executor running batches for this run style
+        fn executor(&self) -> Box<dyn Executor + '_> {
+            match self {
+                RunStyle::LOCAL => Box::new(LocalExecutor),
+                RunStyle::PARALLEL => Box::new(RayonExecutor),
+                RunStyle::POOL { max_workers } => {
+                    match ThreadPoolBuilder::new().num_threads(*max_workers).build() {
+                        Ok(pool) => Box::new(PoolExecutor { pool }),
+                        Err(e) => {
+                            warn!("Failed to build a thread pool, using the global one: {}", e);
+                            Box::new(RayonExecutor)
+                        }
+                    }
+                }
+                RunStyle::CLUSTER(dispatcher) => Box::new(DispatchExecutor {
+                    dispatcher: dispatcher.as_ref(),
+                }),
             }
         }
+    }
 
-        #[derive(Debug)]
-        struct Dep2 {}
-        impl Task for Dep2 {
-            fn get_name(&self) -> String {
-                "Dep2".to_string()
-            }
+    /// Runs a single task, identified by name, somewhere else: e.g., by submitting an
+    /// SGE or SLURM job that re-invokes the binary for that task. dispatch returns once
+    /// the task has finished, and Ok means the task's target has been written.
+    pub trait TaskDispatcher: Send + Sync {
+        fn dispatch(&self, task_name: &str) -> Result<()>;
+    }
 
-            fn get_target(&self) -> Result<Box<dyn Target>> {
-                Ok(Box::new(FileTarget {
-                    cache_dir: "/tmp".to_string(),
-                    local_filename: "test_dag_target_dep2.txt".to_string(),
-                }))
-            }
+    /// Dispatcher running the tasks in this process, looking them up by name in the
+    /// DAG of a head task. Useful for testing a cluster setup locally.
+    pub struct LocalProcessDispatcher {
+        tasks: HashMap<String, Box<dyn Task>>,
+    }
 
-            fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
-                let mut result = HashMap::<String, Box<dyn Task>>::new();
-                result.insert("dep3".to_string(), Box::new(Dep3 {}));
-                Ok(result)
+    impl LocalProcessDispatcher {
+        /// Fails if several tasks in the DAG have the same name, as dispatch couldn't
+        /// tell them apart
+        pub fn new(head_task: Box<dyn Task>) -> Result<Self> {
+            let mut tasks = HashMap::new();
+            for node in DAG::new(head_task)?.nodes.into_values() {
+                let name = node.task.get_name();
+                if tasks.insert(name.clone(), node.task).is_some() {
+                    return Err(anyhow!("Several tasks are named {}", name));
+                }
             }
+            Ok(LocalProcessDispatcher { tasks })
+        }
+    }
 
-            fn compute_output(&self) -> Result<Vec<u8>> {
-                let dep_targets = self
-                    .get_dep_targets()
-                    .expect("Couldn't get dependent targets");
-                let mut s1 = dep_targets.get("dep3").unwrap().read()?;
-                s1.extend(" - dep2".as_bytes());
-                Ok(s1)
-            }
+    impl TaskDispatcher for LocalProcessDispatcher {
+        fn dispatch(&self, task_name: &str) -> Result<()> {
+            self.tasks
+                .get(task_name)
+                .ok_or_else(|| anyhow!("Unknown task {:?}", task_name))?
+                .run_no_deps()
         }
+    }
 
-        #[derive(Debug)]
-        struct Dep3 {}
-        impl Task for Dep3 {
-            fn get_name(&self) -> String {
-                "Dep3".to_string()
-            }
+    /// Executor for RunStyle::CLUSTER
+    struct DispatchExecutor<'a> {
+        dispatcher: &'a dyn TaskDispatcher,
+    }
 
-            fn get_target(&self) -> Result<Box<dyn Target>> {
-                Ok(Box::new(FileTarget {
-                    cache_dir: "/tmp".to_string(),
-                    local_filename: "test_dag_target_dep3.txt".to_string(),
-                }))
-            }
+    impl Executor for DispatchExecutor<'_> {
+        fn run_batch(&self, tasks: Vec<&dyn Task>) -> Vec<Result<()>> {
+            tasks
+                .into_par_iter()
+                .map(|task| self.dispatcher.dispatch(&task.get_name()))
+                .collect()
+        }
 
-            fn compute_output(&self) -> Result<Vec<u8>> {
-                Ok("dep3 data".as_bytes().to_vec())
+        /// Tasks are dispatched by name, so names must be unique
+        fn check_tasks(&self, tasks: &[&dyn Task]) -> Result<()> {
+            let mut names = HashSet::new();
+            for task in tasks {
+                let name = task.get_name();
+                if !names.insert(name.clone()) {
+                    return Err(anyhow!("Several tasks are named {}", name));
+                }
             }
+            Ok(())
         }
+    }
 
-        #[derive(Debug)]
-        struct FinalTask {}
-        impl Task for FinalTask {
-            fn get_name(&self) -> String {
-                "FinalTask".to_string()
-            }
+    /// Events sent while a DAG runs, e.g., to a UI
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum TaskEvent {
+        Started {
+            name: String,
+        },
+        Finished {
+            name: String,
+            duration: Duration,
+        },
+        Failed {
+            name: String,
+            error: String,
+        },
+        /// Not run because the blocked_by task failed, i.e., NodeOutcome::Blocked.
+        /// Cached tasks (TaskStatus::Skipped) send no events.
+        Skipped {
+            name: String,
+            blocked_by: String,
+        },
+    }
 
-            fn get_target(&self) -> Result<Box<dyn Target>> {
-                Ok(Box::new(FileTarget::new(
-                    "/tmp",
-                    "test_dag_target_depfinal.txt",
-                )))
-            }
+    /// Receives the events of a run, from whichever thread runs the task
+    type EventSink<'a> = &'a (dyn Fn(TaskEvent) + Sync);
 
-            fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
-                let mut result = HashMap::<String, Box<dyn Task>>::new();
-                result.insert("dep1".to_string(), Box::new(Dep1 {}));
-                result.insert("dep2".to_string(), Box::new(Dep2 {}));
-                Ok(result)
-            }
+    /// Order in which the ready tasks of a run are handed to the executor
+    type TaskOrder<'a> = &'a dyn Fn(&dyn Task, &dyn Task) -> Ordering;
 
-            fn compute_output(&self) -> Result<Vec<u8>> {
-                let dep_targets = self
-                    .get_dep_targets()
-                    .expect("Couldn't get dependent targets");
-                let mut s1 = dep_targets.get("dep1").unwrap().read()?;
-                s1.extend(" - ".as_bytes());
-                s1.extend(dep_targets.get("dep2").unwrap().read()?);
-                Ok(s1)
-            }
+    /// Hands a remote task over to whatever will run it, without waiting for it
+    type Submit<'a> = &'a dyn Fn(&dyn Task) -> Result<()>;
+
+    /// Result, start, duration and peak memory of one task of a batch
+    type TaskRun = (Result<()>, Option<Instant>, Duration, Option<u64>);
+
+    /// Options of a single run
+    #[derive(Default)]
+    struct RunOptions<'a> {
+        /// Nodes to run (with their dependencies), instead of all of them
+        selected: Option<&'a HashSet<Uuid>>,
+        events: Option<EventSink<'a>>,
+        retries: Option<&'a RetryPolicy>,
+        order: Option<TaskOrder<'a>>,
+        /// Submit callback for remote tasks, and how often to check their targets
+        remote: Option<(Submit<'a>, Duration)>,
+        /// While set, no new batches are started
+        pause: Option<&'a AtomicBool>,
+        /// Compute every task, whether or not its target exists
+        forced: bool,
+    }
+
+    /// How often a paused run checks whether it has been resumed
+    const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    /// An Executor runs a batch of tasks whose dependencies are all done, returning one
+    /// result per task in the same order. The tasks only need run_no_deps to be called.
+    /// Implement this to run tasks on your own thread pool, an async runtime, or remotely.
+    pub trait Executor {
+        fn run_batch(&self, tasks: Vec<&dyn Task>) -> Vec<Result<()>>;
+
+        /// Check the tasks a run is about to hand over, before any of them runs
+        fn check_tasks(&self, _tasks: &[&dyn Task]) -> Result<()> {
+            Ok(())
         }
+    }
 
-        #[test]
-        fn construct_scheduler() {
-            let task: Box<dyn Task> = Box::new(FinalTask {});
-            task.recursively_delete_data()
-                .expect("Failed to delete task and dependent task data");
-            let dag = DAG::new(task).expect("Failed to construct DAG");
-            let any_done = dag.nodes.values().any(|node| node.is_done);
-            assert!(!any_done);
+    /// Run the tasks one after another on the current thread
+    pub struct LocalExecutor;
 
-            let task: Box<dyn Task> = Box::new(FinalTask {});
-            task.run().expect("task failed to run");
-            let dag = DAG::new(task).expect("Failed to construct DAG");
-            let all_done = dag.nodes.values().all(|node| node.is_done);
-            assert!(all_done);
+    impl Executor for LocalExecutor {
+        fn run_batch(&self, tasks: Vec<&dyn Task>) -> Vec<Result<()>> {
+            tasks.into_iter().map(|task| task.run_no_deps()).collect()
         }
+    }
 
-        #[test]
-        fn local_run() {
-            let task: Box<dyn Task> = Box::new(FinalTask {});
-            task.recursively_delete_data()
-                .expect("Failed to delete task and dependent task data");
-            let mut dag = DAG::new(task).expect("Failed to construct DAG");
+    /// Run the tasks in parallel on the rayon global thread pool
+    pub struct RayonExecutor;
 
-            let any_done = dag.nodes.values().any(|node| node.is_done);
-            assert!(!any_done);
+    impl Executor for RayonExecutor {
+        fn run_batch(&self, tasks: Vec<&dyn Task>) -> Vec<Result<()>> {
+            tasks
+                .into_par_iter()
+                .map(|task| task.run_no_deps())
+                .collect()
+        }
+    }
 
-            dag.run(&crate::scheduler::RunStyle::LOCAL)
-                .expect("Failed to run the DAG");
+    /// Run the tasks in parallel on a dedicated rayon thread pool
+    struct PoolExecutor {
+        pool: ThreadPool,
+    }
 
-            let all_done = dag.nodes.values().all(|node| node.is_done);
-            assert!(all_done);
+    impl Executor for PoolExecutor {
+        fn run_batch(&self, tasks: Vec<&dyn Task>) -> Vec<Result<()>> {
+            self.pool.install(|| RayonExecutor.run_batch(tasks))
         }
+    }
 
-        #[test]
-        fn parallel_run() {
-            let task: Box<dyn Task> = Box::new(FinalTask {});
+    /// Runs a task that asked to be isolated (e.g., in a child process), returning once
+    /// the task has finished
+    pub type IsolatedRunner = Box<dyn Fn(&dyn Task) -> Result<()> + Send + Sync>;
+
+    /// Wrapper handed to executors so run_no_deps is timed (and its writes limited, the
+    /// task isolated, or retried) on whichever thread runs it
+    struct TimedTask<'a> {
+        task: &'a dyn Task,
+        started: Mutex<Option<Instant>>,
+        duration: Mutex<Duration>,
+        write_permits: Option<Arc<WritePermits>>,
+        isolated_runner: Option<&'a IsolatedRunner>,
+        events: Option<EventSink<'a>>,
+        retries: Option<&'a RetryPolicy>,
+        measure_memory: bool,
+        peak_memory: Mutex<Option<u64>>,
+        forced: bool,
+    }
+
+    impl<'a> TimedTask<'a> {
+        fn new(
+            task: &'a dyn Task,
+            write_permits: Option<Arc<WritePermits>>,
+            isolated_runner: Option<&'a IsolatedRunner>,
+            events: Option<EventSink<'a>>,
+            retries: Option<&'a RetryPolicy>,
+            measure_memory: bool,
+            forced: bool,
+        ) -> Self {
+            TimedTask {
+                task,
+                started: Mutex::new(None),
+                duration: Mutex::new(Duration::ZERO),
+                write_permits,
+                isolated_runner,
+                events,
+                retries,
+                measure_memory,
+                peak_memory: Mutex::new(None),
+                forced,
+            }
+        }
+
+        // Run the task once, isolated if it asks to be (unless the run is forced)
+        fn run_once(&self) -> Result<()> {
+            match self.isolated_runner {
+                _ if self.forced => with_write_permits(self.write_permits.clone(), || {
+                    self.task.run_forced_no_deps()
+                }),
+                Some(runner) if self.task.run_isolated() => {
+                    info!("{}: running isolated", self.task.display_name());
+                    runner(self.task)
+                }
+                _ => with_write_permits(self.write_permits.clone(), || self.task.run_no_deps()),
+            }
+        }
+    }
+
+    /// Samples the resident memory of the process on a background thread, tracking the
+    /// largest growth over the value when sampling started
+    struct MemorySampler {
+        stop: Arc<AtomicBool>,
+        sampler: thread::JoinHandle<Option<u64>>,
+    }
+
+    impl MemorySampler {
+        const INTERVAL: Duration = Duration::from_millis(1);
+
+        fn start() -> Self {
+            let stop = Arc::new(AtomicBool::new(false));
+            let baseline = resident_memory();
+            let stopped = stop.clone();
+            let sampler = thread::spawn(move || {
+                let baseline = baseline?;
+                let mut peak = baseline;
+                loop {
+                    peak = peak.max(resident_memory()?);
+                    if stopped.load(AtomicOrdering::Relaxed) {
+                        return Some(peak - baseline);
+                    }
+                    thread::sleep(MemorySampler::INTERVAL);
+                }
+            });
+            MemorySampler { stop, sampler }
+        }
+
+        /// Stop sampling, returning the peak growth in bytes (None if unsupported)
+        fn finish(self) -> Option<u64> {
+            self.stop.store(true, AtomicOrdering::Relaxed);
+            self.sampler.join().ok().flatten()
+        }
+    }
+
+    /// Resident memory of the process in bytes, from the resident pages in
+    /// /proc/self/statm
+    #[cfg(target_os = "linux")]
+    fn resident_memory() -> Option<u64> {
+        let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+        let pages = statm.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+        // Safety: sysconf only reads a system setting
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        Some(pages * u64::try_from(page_size).ok()?)
+    }
+
+    /// Resident memory isn't measured on other platforms
+    #[cfg(not(target_os = "linux"))]
+    fn resident_memory() -> Option<u64> {
+        None
+    }
+
+    impl fmt::Debug for TimedTask<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("TimedTask")
+                .field("task", &self.task)
+                .field("duration", &self.duration)
+                .finish()
+        }
+    }
+
+    impl Task for TimedTask<'_> {
+        fn get_target(&self) -> Result<Box<dyn Target>> {
+            self.task.get_target()
+        }
+
+        fn compute_output(&self) -> Result<Vec<u8>> {
+            self.task.compute_output()
+        }
+
+        fn get_name(&self) -> String {
+            self.task.get_name()
+        }
+
+        fn identity(&self) -> String {
+            self.task.identity()
+        }
+
+        fn description(&self) -> Option<String> {
+            self.task.description()
+        }
+
+        fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+            self.task.get_dep_tasks()
+        }
+
+        fn soft_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+            self.task.soft_dep_tasks()
+        }
+
+        fn validate(&self, data: &[u8]) -> Result<()> {
+            self.task.validate(data)
+        }
+
+        fn delete_on_validation_failure(&self) -> bool {
+            self.task.delete_on_validation_failure()
+        }
+
+        fn run_no_deps(&self) -> Result<()> {
+            if let Some(events) = self.events {
+                events(TaskEvent::Started {
+                    name: self.task.get_name(),
+                });
+            }
+            let sampler = self.measure_memory.then(MemorySampler::start);
+            let start = Instant::now();
+            *self.started.lock().unwrap() = Some(start);
+            let task_res = match self.retries {
+                Some(policy) => policy.run(|| self.run_once()),
+                None => self.run_once(),
+            };
+            *self.duration.lock().unwrap() = start.elapsed();
+            *self.peak_memory.lock().unwrap() = sampler.and_then(MemorySampler::finish);
+            task_res
+        }
+    }
+
+    /// DAG represents a directed acylic graph corresponding to the logical
+    /// structure of a task with dependencies. It's currently implemented as an
+    /// arena (a map of nodes where nodes specify dependencies), with UUIDs as
+    /// node identifiers.
+    pub struct DAG {
+        pub nodes: HashMap<Uuid, Node>,
+        last_report: Option<RunReport>,
+        write_permits: Option<Arc<WritePermits>>,
+        prune: bool,
+        isolated_runner: Option<IsolatedRunner>,
+        retry_backoff: Duration,
+        measure_memory: bool,
+        failure_policy: FailurePolicy,
+    }
+
+    impl DAG {
+        // Construct a DAG given a Task object
+        pub fn new(head_task: Box<dyn Task>) -> Result<Self> {
+            DAG::new_bounded(head_task, usize::MAX)
+        }
+
+        /// Construct a DAG, expanding dependencies breadth-first, and fail as soon as it
+        /// would hold more than max_nodes nodes. Use this for graphs that may be too big
+        /// to expand in memory. Tasks with the same Task::identity share one node, so
+        /// they run once. Fails if a task depends on itself, directly or through other
+        /// tasks.
+        pub fn new_bounded(head_task: Box<dyn Task>, max_nodes: usize) -> Result<Self> {
+            let mut to_process = VecDeque::new();
+            let mut processed = HashMap::new();
+            // node id of each task identity seen so far
+            let mut ids = HashMap::new();
+            let head_id = Uuid::new_v4();
+            ids.insert(head_task.identity(), head_id);
+            let node_data = DAG::make_node(head_task, head_id, &mut ids)?;
+            processed.insert(node_data.node.id, node_data.node);
+            to_process.extend(node_data.children);
+
+            while let Some(child_data) = to_process.pop_front() {
+                // a shared task only needs another parent
+                if processed.contains_key(&child_data.id) {
+                    DAG::check_cycle(&processed, &child_data)?;
+                    let node = processed.get_mut(&child_data.id).unwrap();
+                    node.parents.insert(child_data.parent);
+                    continue;
+                }
+                if processed.len() >= max_nodes {
+                    return Err(anyhow!(
+                        "DAG truncated: more than {} nodes (stopped at task {})",
+                        max_nodes,
+                        child_data.task.get_name()
+                    ));
+                }
+                let mut node_data = DAG::make_node(child_data.task, child_data.id, &mut ids)?;
+                node_data.node.parents.insert(child_data.parent);
+                processed.insert(node_data.node.id, node_data.node);
+                to_process.extend(node_data.children);
+            }
+
+            Ok(Self {
+                nodes: processed,
+                last_report: None,
+                write_permits: None,
+                prune: false,
+                isolated_runner: None,
+                retry_backoff: Duration::ZERO,
+                measure_memory: false,
+                failure_policy: FailurePolicy::default(),
+            })
+        }
+
+        /// Run tasks whose run_isolated is true with runner instead of in this process
+        pub fn with_isolated_runner<F>(mut self, runner: F) -> Self
+        where
+            F: Fn(&dyn Task) -> Result<()> + Send + Sync + 'static,
+        {
+            self.isolated_runner = Some(Box::new(runner));
+            self
+        }
+
+        /// Record an estimate of each task's peak memory use in the run report, only
+        /// meaningful for LOCAL runs (see NodeReport::peak_memory)
+        pub fn with_memory_measurement(mut self) -> Self {
+            self.measure_memory = true;
+            self
+        }
+
+        /// Call prune_done_leaves after every batch of a run, so the tasks of finished
+        /// nodes are freed during long runs. The pruned nodes are no longer in nodes
+        /// afterwards (e.g., for validate_all or delete_all).
+        pub fn with_pruning(mut self) -> Self {
+            self.prune = true;
+            self
+        }
+
+        /// Allow at most max_writes task outputs to be written at once across the whole
+        /// run, however many tasks compute in parallel (e.g., to avoid thrashing a slow
+        /// disk). Only writes made while materializing task outputs are limited.
+        /// Fails if max_writes is 0.
+        pub fn with_write_limit(mut self, max_writes: usize) -> Result<Self> {
+            if max_writes == 0 {
+                return Err(anyhow!("Write limit must be at least 1"));
+            }
+            self.write_permits = Some(Arc::new(WritePermits::new(max_writes)));
+            Ok(self)
+        }
+
+        /// Set what runs do once a task fails (ContinueIndependent by default). Either
+        /// way the run returns an error naming every failed task.
+        pub fn with_failure_policy(mut self, policy: FailurePolicy) -> Self {
+            self.failure_policy = policy;
+            self
+        }
+
+        /// Wait before each retry of run_with_retries, starting at base_delay and doubling
+        /// with each retry of the same node. There is no wait by default.
+        pub fn with_retry_backoff(mut self, base_delay: Duration) -> Self {
+            self.retry_backoff = base_delay;
+            self
+        }
+
+        /// Run all tasks in the DAG according to run_style (e.g., local or multi-threaded
+        /// parallel). Tasks depending on a failed task are blocked (or every task left,
+        /// see with_failure_policy), and the run returns an error naming the failed tasks
+        /// once everything else has run. last_report is set either way.
+        pub fn run(&mut self, run_style: &RunStyle) -> Result<()> {
+            self.run_with_executor(run_style.executor().as_ref())
+        }
+
+        /// Run all tasks in the DAG, computing and writing every output even if its
+        /// target exists (see Task::run_forced). Nodes that were done are run again, and
+        /// isolated tasks run in this process since the isolated runner doesn't force.
+        pub fn run_forced(&mut self, run_style: &RunStyle) -> Result<()> {
+            for node in self.nodes.values_mut() {
+                node.status = TaskStatus::Pending;
+            }
+            let options = RunOptions {
+                forced: true,
+                ..RunOptions::default()
+            };
+            self.run_nodes(run_style.executor().as_ref(), options)
+        }
+
+        /// Run all tasks in the DAG, handing each set of ready tasks to the executor
+        pub fn run_with_executor(&mut self, executor: &dyn Executor) -> Result<()> {
+            self.run_nodes(executor, RunOptions::default())
+        }
+
+        /// Run all tasks in the DAG, running a failed task again up to
+        /// max_retries_per_node times before it counts as failed, whatever retries the task
+        /// does itself. See with_retry_backoff to wait between retries.
+        pub fn run_with_retries(
+            &mut self,
+            run_style: &RunStyle,
+            max_retries_per_node: u32,
+        ) -> Result<()> {
+            let policy = RetryPolicy::new(max_retries_per_node + 1, self.retry_backoff);
+            let options = RunOptions {
+                retries: Some(&policy),
+                ..RunOptions::default()
+            };
+            self.run_nodes(run_style.executor().as_ref(), options)
+        }
+
+        /// Run all tasks in the DAG, sending events to tx so they can be consumed on
+        /// another thread. tx is dropped when the run ends, closing the channel.
+        pub fn run_with_sender(
+            &mut self,
+            run_style: &RunStyle,
+            tx: Sender<TaskEvent>,
+        ) -> Result<()> {
+            let send = move |event| {
+                // the receiver may have stopped listening, which shouldn't stop the run
+                let _ = tx.send(event);
+            };
+            let options = RunOptions {
+                events: Some(&send),
+                ..RunOptions::default()
+            };
+            self.run_nodes(run_style.executor().as_ref(), options)
+        }
+
+        /// Run the nodes whose task has the tag, and the nodes they depend on
+        pub fn run_tagged(&mut self, tag: &str, run_style: &RunStyle) -> Result<()> {
+            let mut selected = HashSet::new();
+            for id in self.tagged(tag) {
+                selected.insert(id);
+                selected.extend(self.get_descendants(id));
+            }
+            let options = RunOptions {
+                selected: Some(&selected),
+                ..RunOptions::default()
+            };
+            self.run_nodes(run_style.executor().as_ref(), options)
+        }
+
+        /// Run the node whose task is named target_task_name and the nodes it depends on,
+        /// leaving the rest of the DAG untouched. Fails if no node or several nodes have
+        /// that name.
+        pub fn run_until(&mut self, target_task_name: &str, run_style: &RunStyle) -> Result<()> {
+            let matches = self
+                .nodes
+                .values()
+                .filter(|node| node.task.get_name() == target_task_name)
+                .map(|node| node.id)
+                .collect::<Vec<_>>();
+            let id = match matches[..] {
+                [id] => id,
+                [] => return Err(anyhow!("No task named {}", target_task_name)),
+                _ => {
+                    return Err(anyhow!(
+                        "{} tasks are named {}",
+                        matches.len(),
+                        target_task_name
+                    ))
+                }
+            };
+            let mut selected = self.get_descendants(id);
+            selected.insert(id);
+            let options = RunOptions {
+                selected: Some(&selected),
+                ..RunOptions::default()
+            };
+            self.run_nodes(run_style.executor().as_ref(), options)
+        }
+
+        /// Run all tasks in the DAG, handing the ready tasks to the executor sorted by
+        /// order (e.g., to submit tasks using the same storage together). With
+        /// RunStyle::LOCAL the tasks of each batch run in this order.
+        pub fn run_with_order<F>(&mut self, run_style: &RunStyle, order: F) -> Result<()>
+        where
+            F: Fn(&dyn Task, &dyn Task) -> Ordering,
+        {
+            let options = RunOptions {
+                order: Some(&order),
+                ..RunOptions::default()
+            };
+            self.run_nodes(run_style.executor().as_ref(), options)
+        }
+
+        /// Delete the target data of the nodes whose task has the tag (but not of the
+        /// nodes they depend on)
+        pub fn delete_tagged(&mut self, tag: &str) -> Result<()> {
+            for id in self.tagged(tag) {
+                let node = self.nodes.get_mut(&id).unwrap();
+                node.task.delete_data()?;
+                node.status = TaskStatus::Pending;
+            }
+            Ok(())
+        }
+
+        /// Run the DAG, handing tasks whose Task::is_remote is true to submit instead of
+        /// running them. A submitted task counts as done once its target exists, which is
+        /// checked every poll_interval; meanwhile the local tasks that are ready keep
+        /// running. The run waits for as long as remote tasks take.
+        pub fn run_with_remote<F>(
+            &mut self,
+            run_style: &RunStyle,
+            submit: F,
+            poll_interval: Duration,
+        ) -> Result<()>
+        where
+            F: Fn(&dyn Task) -> Result<()>,
+        {
+            let options = RunOptions {
+                remote: Some((&submit, poll_interval)),
+                ..RunOptions::default()
+            };
+            self.run_nodes(run_style.executor().as_ref(), options)
+        }
+
+        /// Run the DAG, not starting any new tasks while pause is set. Tasks already
+        /// running when it's set finish, and the run carries on once it's cleared.
+        pub fn run_with_pause(
+            &mut self,
+            run_style: &RunStyle,
+            pause: Arc<AtomicBool>,
+        ) -> Result<()> {
+            let options = RunOptions {
+                pause: Some(&pause),
+                ..RunOptions::default()
+            };
+            self.run_nodes(run_style.executor().as_ref(), options)
+        }
+
+        // Run the selected nodes (or all of them), handing each set of ready tasks to the
+        // executor, sending events if there's a sink and retrying failed tasks if there's a
+        // retry policy. Selections must include the dependencies of the selected nodes.
+        fn run_nodes(&mut self, executor: &dyn Executor, options: RunOptions) -> Result<()> {
+            let send = |event| {
+                if let Some(events) = options.events {
+                    events(event)
+                }
+            };
+            let run_start = Instant::now();
+            let mut report = RunReport::default();
+            let mut failed_names = Vec::new();
+            let mut finished = HashSet::new();
+            let mut not_finished = HashSet::new();
+            let nodes = self.nodes.values().filter(|node| {
+                options
+                    .selected
+                    .is_none_or(|selected| selected.contains(&node.id))
+            });
+            for node in nodes {
+                if node.is_done() {
+                    finished.insert(node.id);
+                    report.nodes.push(NodeReport {
+                        name: node.task.get_name(),
+                        outcome: NodeOutcome::Cached,
+                        start: Duration::ZERO,
+                        duration: Duration::ZERO,
+                        peak_memory: None,
+                    });
+                } else {
+                    not_finished.insert(node.id);
+                }
+            }
+            executor.check_tasks(
+                &not_finished
+                    .iter()
+                    .map(|id| self.nodes[id].task.as_ref())
+                    .collect::<Vec<_>>(),
+            )?;
+
+            // remote tasks that have been submitted, with when
+            let mut submitted = HashMap::<Uuid, Instant>::new();
+            while !&not_finished.is_empty() {
+                if let Some(pause) = options.pause {
+                    if pause.load(AtomicOrdering::SeqCst) {
+                        info!("Run paused");
+                        while pause.load(AtomicOrdering::SeqCst) {
+                            thread::sleep(PAUSE_POLL_INTERVAL);
+                        }
+                        info!("Run resumed");
+                    }
+                }
+                let mut candidate_ids = self
+                    .get_run_candidates(&not_finished)
+                    .into_iter()
+                    .filter(|id| !submitted.contains_key(id))
+                    .collect::<Vec<_>>();
+                if let Some(order) = options.order {
+                    candidate_ids.sort_by(|a, b| {
+                        order(self.nodes[a].task.as_ref(), self.nodes[b].task.as_ref())
+                    });
+                }
+                let mut outcomes = Vec::new();
+                if let Some((submit, _)) = options.remote {
+                    let (remote_ids, local_ids) = candidate_ids
+                        .into_iter()
+                        .partition::<Vec<_>, _>(|id| self.nodes[id].task.is_remote());
+                    candidate_ids = local_ids;
+                    for id in remote_ids {
+                        let task = self.nodes[&id].task.as_ref();
+                        info!("{}: submitting remote task", task.display_name());
+                        send(TaskEvent::Started {
+                            name: task.get_name(),
+                        });
+                        match submit(task) {
+                            Ok(()) => {
+                                self.nodes.get_mut(&id).unwrap().status = TaskStatus::Running;
+                                submitted.insert(id, Instant::now());
+                            }
+                            Err(e) => outcomes.push((
+                                id,
+                                Err(e),
+                                Some(Instant::now()),
+                                Duration::ZERO,
+                                None,
+                            )),
+                        }
+                    }
+                }
+                for id in &candidate_ids {
+                    self.nodes.get_mut(id).unwrap().status = TaskStatus::Running;
+                }
+                if !candidate_ids.is_empty() {
+                    let results = self.run_batch(executor, &candidate_ids, &options)?;
+                    for (id, (task_res, started, duration, peak_memory)) in
+                        candidate_ids.into_iter().zip(results)
+                    {
+                        outcomes.push((id, task_res, started, duration, peak_memory));
+                    }
+                }
+                if let Some((_, poll_interval)) = options.remote {
+                    if outcomes.is_empty() && !submitted.is_empty() {
+                        std::thread::sleep(poll_interval);
+                    }
+                    let mut completed = Vec::new();
+                    for (id, started) in &submitted {
+                        match self.nodes[id].task.get_target().and_then(|t| t.exists()) {
+                            Ok(false) => {}
+                            res => completed.push((*id, res.map(|_| ()), *started)),
+                        }
+                    }
+                    for (id, task_res, started) in completed {
+                        submitted.remove(&id);
+                        outcomes.push((id, task_res, Some(started), started.elapsed(), None));
+                    }
+                }
+                let mut failed = Vec::new();
+                for (id, task_res, started, duration, peak_memory) in outcomes {
+                    if let Some(node) = self.nodes.get_mut(&id) {
+                        let outcome = match task_res {
+                            Ok(()) => {
+                                node.status = TaskStatus::Completed;
+                                send(TaskEvent::Finished {
+                                    name: node.task.get_name(),
+                                    duration,
+                                });
+                                NodeOutcome::Computed
+                            }
+                            Err(e) => {
+                                warn!("Task {:?} failed to run", node.task);
+                                warn!("{:?}", e);
+                                node.status = TaskStatus::Failed;
+                                send(TaskEvent::Failed {
+                                    name: node.task.get_name(),
+                                    error: format!("{:?}", e),
+                                });
+                                failed.push(id);
+                                failed_names.push(node.task.get_name());
+                                NodeOutcome::Failed
+                            }
+                        };
+                        report.nodes.push(NodeReport {
+                            name: node.task.get_name(),
+                            outcome,
+                            start: started
+                                .map(|started| started.saturating_duration_since(run_start))
+                                .unwrap_or_default(),
+                            duration,
+                            peak_memory,
+                        });
+                        finished.insert(id);
+                        not_finished.remove(&id);
+                    }
+                }
+                // tasks depending on a failed task can't run, unless it's a soft dependency,
+                // and failing fast no task can start, though submitted ones are waited for
+                for id in failed {
+                    let failed_name = self.nodes[&id].task.get_name();
+                    let blocked = match self.failure_policy {
+                        FailurePolicy::FailFast => not_finished
+                            .iter()
+                            .filter(|id| !submitted.contains_key(id))
+                            .copied()
+                            .collect(),
+                        FailurePolicy::ContinueIndependent => self.get_ancestors(id),
+                    };
+                    for blocked_id in blocked {
+                        if not_finished.remove(&blocked_id) {
+                            let name = self.nodes[&blocked_id].task.get_name();
+                            warn!("Task {} skipped as {} failed", name, failed_name);
+                            send(TaskEvent::Skipped {
+                                name: name.clone(),
+                                blocked_by: failed_name.clone(),
+                            });
+                            report.nodes.push(NodeReport {
+                                name: name.clone(),
+                                outcome: NodeOutcome::Blocked,
+                                start: Duration::ZERO,
+                                duration: Duration::ZERO,
+                                peak_memory: None,
+                            });
+                            report.skipped_due_to.insert(name, failed_name.clone());
+                        }
+                    }
+                }
+                if self.prune {
+                    self.prune_done_leaves();
+                }
+            }
+            self.last_report = Some(report);
+            if !failed_names.is_empty() {
+                failed_names.sort();
+                return Err(anyhow!("Tasks failed: {}", failed_names.join(", ")));
+            }
+            Ok(())
+        }
+
+        /// Report from the most recent call to run, if any
+        pub fn last_report(&self) -> Option<&RunReport> {
+            self.last_report.as_ref()
+        }
+
+        // Run the nodes' tasks (without dependencies) on the executor, timing each one
+        fn run_batch(
+            &self,
+            executor: &dyn Executor,
+            ids: &[Uuid],
+            options: &RunOptions,
+        ) -> Result<Vec<TaskRun>> {
+            let timed = ids
+                .iter()
+                .map(|id| {
+                    TimedTask::new(
+                        self.nodes[id].task.as_ref(),
+                        self.write_permits.clone(),
+                        self.isolated_runner.as_ref(),
+                        options.events,
+                        options.retries,
+                        self.measure_memory,
+                        options.forced,
+                    )
+                })
+                .collect::<Vec<_>>();
+            let results = executor.run_batch(timed.iter().map(|t| t as &dyn Task).collect());
+            if results.len() != timed.len() {
+                return Err(anyhow!(
+                    "Executor returned {} results for {} tasks",
+                    results.len(),
+                    timed.len()
+                ));
+            }
+            Ok(results
+                .into_iter()
+                .zip(timed)
+                .map(|(task_res, t)| {
+                    let started = *t.started.lock().unwrap();
+                    let peak_memory = *t.peak_memory.lock().unwrap();
+                    (
+                        task_res,
+                        started,
+                        t.duration.into_inner().unwrap(),
+                        peak_memory,
+                    )
+                })
+                .collect())
+        }
+
+        /// Nodes that are ready to run: pending (not running, done or failed), with all
+        /// dependencies done (soft dependencies may have failed instead). Together with
+        /// mark_running, mark_done and mark_failed this lets a caller (e.g., an external
+        /// scheduler) drive the DAG without using run.
+        pub fn ready(&self) -> Vec<Uuid> {
+            self.nodes
+                .values()
+                .filter(|node| node.status == TaskStatus::Pending)
+                .filter(|node| {
+                    node.children.iter().all(|id| {
+                        let child = &self.nodes[id];
+                        child.is_done()
+                            || (node.soft_children.contains(id)
+                                && child.status == TaskStatus::Failed)
+                    })
+                })
+                .map(|node| node.id)
+                .collect()
+        }
+
+        /// Record that a node's task has been started, so it isn't ready any more
+        pub fn mark_running(&mut self, id: Uuid) -> Result<()> {
+            self.set_status(id, TaskStatus::Running)
+        }
+
+        /// Record that a node's task has completed
+        pub fn mark_done(&mut self, id: Uuid) -> Result<()> {
+            self.set_status(id, TaskStatus::Completed)
+        }
+
+        /// Record that a node's task has failed, so neither it nor the nodes depending on
+        /// it become ready
+        pub fn mark_failed(&mut self, id: Uuid) -> Result<()> {
+            self.set_status(id, TaskStatus::Failed)
+        }
+
+        fn set_status(&mut self, id: Uuid, status: TaskStatus) -> Result<()> {
+            let node = self
+                .nodes
+                .get_mut(&id)
+                .ok_or_else(|| anyhow!("Unknown node {}", id))?;
+            node.status = status;
+            Ok(())
+        }
+
+        /// Remove done nodes without children whose parents are all done, freeing their
+        /// tasks, and repeat while that creates new such leaves. Returns the number of
+        /// nodes removed. A node is only removed once its parents no longer need it to
+        /// become ready, and the head node is always kept.
+        pub fn prune_done_leaves(&mut self) -> usize {
+            let mut pruned = 0;
+            loop {
+                let leaves = self
+                    .nodes
+                    .values()
+                    .filter(|node| node.is_done() && node.children.is_empty())
+                    .filter(|node| !node.parents.is_empty())
+                    .filter(|node| node.parents.iter().all(|id| self.nodes[id].is_done()))
+                    .map(|node| node.id)
+                    .collect::<Vec<_>>();
+                if leaves.is_empty() {
+                    return pruned;
+                }
+                for id in leaves {
+                    let node = self.nodes.remove(&id).unwrap();
+                    for parent in node.parents {
+                        if let Some(parent) = self.nodes.get_mut(&parent) {
+                            parent.children.remove(&id);
+                            parent.soft_children.remove(&id);
+                        }
+                    }
+                    pruned += 1;
+                }
+            }
+        }
+
+        /// Validate the existing target data of every node without recomputing anything,
+        /// returning the names of nodes whose data fails validation (or can't be read)
+        pub fn validate_all(&self) -> Result<Vec<(String, anyhow::Error)>> {
+            let mut failures = Vec::new();
+            for node in self.nodes.values() {
+                let target = node.task.get_target()?;
+                if !target.exists()? {
+                    continue;
+                }
+                if let Err(e) = target.read().and_then(|data| node.task.validate(&data)) {
+                    failures.push((node.task.get_name(), e));
+                }
+            }
+            failures.sort_by(|a, b| a.0.cmp(&b.0));
+            Ok(failures)
+        }
+
+        // Delete all target data
+        pub fn delete_all(&mut self) -> Result<()> {
+            for node in &mut self.nodes.values_mut() {
+                node.task.delete_data()?;
+                node.status = TaskStatus::Pending;
+            }
+            Ok(())
+        }
+
+        /// Node ids in an order the nodes could run in, every node after its dependencies
+        /// (leaves first), ignoring whether they are done. Fails if the nodes form a
+        /// cycle.
+        pub fn topological_order(&self) -> Result<Vec<Uuid>> {
+            let mut order = Vec::new();
+            let mut remaining = self.nodes.keys().copied().collect::<HashSet<_>>();
+            while !remaining.is_empty() {
+                let mut leaves = self.get_leaves(&remaining).into_iter().collect::<Vec<_>>();
+                if leaves.is_empty() {
+                    let mut names = remaining
+                        .iter()
+                        .map(|id| self.nodes[id].task.get_name())
+                        .collect::<Vec<_>>();
+                    names.sort();
+                    return Err(anyhow!("Dependency cycle among: {}", names.join(", ")));
+                }
+                leaves.sort_by_key(|id| (self.nodes[id].task.get_name(), *id));
+                for id in &leaves {
+                    remaining.remove(id);
+                }
+                order.extend(leaves);
+            }
+            Ok(order)
+        }
+
+        /// Task names in topological_order
+        pub fn task_names_in_order(&self) -> Result<Vec<String>> {
+            Ok(self
+                .topological_order()?
+                .iter()
+                .map(|id| self.nodes[id].task.get_name())
+                .collect())
+        }
+
+        /// Save the done status of every node to a target, serialized with codec
+        pub fn save_state(&self, target: &dyn Target, codec: Codec) -> Result<()> {
+            let state = DagState {
+                done: self
+                    .nodes
+                    .values()
+                    .map(|node| (node.task.identity(), node.is_done()))
+                    .collect(),
+            };
+            target.write(&codec.encode(&state)?)
+        }
+
+        /// Restore the done status of nodes from state saved by save_state, in any codec.
+        /// Nodes the state doesn't mention are left as they are. Returns the number of
+        /// nodes restored.
+        pub fn load_state(&mut self, target: &dyn Target) -> Result<usize> {
+            let state: DagState = Codec::decode(&target.read()?)?;
+            let mut restored = 0;
+            for node in self.nodes.values_mut() {
+                if let Some(is_done) = state.done.get(&node.task.identity()) {
+                    node.status = if *is_done {
+                        TaskStatus::Skipped
+                    } else {
+                        TaskStatus::Pending
+                    };
+                    restored += 1;
+                }
+            }
+            Ok(restored)
+        }
+
+        /// Number of nodes in each status (statuses no node is in are left out)
+        pub fn status_summary(&self) -> HashMap<TaskStatus, usize> {
+            let mut summary = HashMap::new();
+            for node in self.nodes.values() {
+                *summary.entry(node.status).or_insert(0) += 1;
+            }
+            summary
+        }
+
+        /// Count the nodes that are done and those that still need to run, and add up
+        /// their weights, without running anything
+        pub fn cache_status(&self) -> CacheStatus {
+            let mut status = CacheStatus::default();
+            for node in self.nodes.values() {
+                let weight = node.task.weight();
+                if node.is_done() {
+                    status.cached += 1;
+                    status.cached_weight += weight;
+                } else {
+                    status.missing += 1;
+                    status.missing_weight += weight;
+                }
+            }
+            status
+        }
+
+        /// Total size of the outputs of the nodes whose targets exist, counting targets
+        /// that can't report a size (Target::size) as zero
+        pub fn total_cached_bytes(&self) -> Result<u64> {
+            let mut total = 0;
+            for node in self.nodes.values() {
+                let target = node.task.output_target()?;
+                if target.exists()? {
+                    total += target.size()?.unwrap_or(0);
+                }
+            }
+            Ok(total)
+        }
+
+        /// What a run would do, without running anything: each task in topological
+        /// order with whether its target already exists. Only checks that the targets
+        /// exist, so nothing is computed or written.
+        pub fn dry_run(&self) -> Result<DryRunReport> {
+            let mut report = DryRunReport::default();
+            for id in self.topological_order()? {
+                let task = self.nodes[&id].task.as_ref();
+                report.tasks.push(DryRunEntry {
+                    name: task.get_name(),
+                    cached: task.get_target()?.exists()?,
+                });
+            }
+            Ok(report)
+        }
+
+        /// Every task's name with whether its target exists now, sorted by name. Unlike
+        /// dry_run this is a flat snapshot rather than a plan in run order.
+        pub fn node_statuses(&self) -> Result<Vec<(String, bool)>> {
+            let mut statuses = Vec::new();
+            for node in self.nodes.values() {
+                statuses.push((node.task.get_name(), node.task.get_target()?.exists()?));
+            }
+            statuses.sort();
+            Ok(statuses)
+        }
+
+        /// Graphviz digraph of the DAG, labelled with Task::display_name, with done nodes
+        /// in green and the rest in grey, and an edge from each node to each of its
+        /// dependencies (dashed for soft ones). Render it with e.g. `dot -Tpng`.
+        pub fn to_dot(&self) -> String {
+            let mut nodes = self.nodes.values().collect::<Vec<_>>();
+            nodes.sort_by_key(|node| (node.task.get_name(), node.id));
+            let mut out = String::from("digraph DAG {\n");
+            for node in &nodes {
+                let color = if node.is_done() { "green" } else { "grey" };
+                out.push_str(&format!(
+                    "    \"{}\" [label=\"{}\", style=filled, fillcolor={}];\n",
+                    node.id,
+                    escape_dot(&node.task.display_name()),
+                    color
+                ));
+            }
+            for node in &nodes {
+                let mut children = node.children.iter().collect::<Vec<_>>();
+                children.sort();
+                for child in children {
+                    let style = if node.soft_children.contains(child) {
+                        " [style=dashed]"
+                    } else {
+                        ""
+                    };
+                    out.push_str(&format!("    \"{}\" -> \"{}\"{};\n", node.id, child, style));
+                }
+            }
+            out.push_str("}\n");
+            out
+        }
+
+        // return the ids of the nodes whose task has the tag
+        fn tagged(&self, tag: &str) -> Vec<Uuid> {
+            self.nodes
+                .values()
+                .filter(|node| node.task.tags().iter().any(|t| t == tag))
+                .map(|node| node.id)
+                .collect()
+        }
+
+        // return the ids of the nodes a node depends on, directly or indirectly
+        fn get_descendants(&self, id: Uuid) -> HashSet<Uuid> {
+            let mut descendants = HashSet::new();
+            let mut to_visit = vec![id];
+            while let Some(id) = to_visit.pop() {
+                for child in &self.nodes[&id].children {
+                    if descendants.insert(*child) {
+                        to_visit.push(*child);
+                    }
+                }
+            }
+            descendants
+        }
+
+        // return the ids of the nodes that depend on a node, directly or indirectly, stopping
+        // at nodes that only depend on it through a soft dependency
+        fn get_ancestors(&self, id: Uuid) -> Vec<Uuid> {
+            let mut ancestors = Vec::new();
+            let mut visited = HashSet::new();
+            let mut to_visit = vec![id];
+            while let Some(child_id) = to_visit.pop() {
+                let Some(child) = self.nodes.get(&child_id) else {
+                    continue;
+                };
+                for parent_id in &child.parents {
+                    let parent_node = &self.nodes[parent_id];
+                    if parent_node.soft_children.contains(&child_id) {
+                        continue;
+                    }
+                    if visited.insert(*parent_id) {
+                        ancestors.push(*parent_id);
+                        to_visit.push(*parent_id);
+                    }
+                }
+            }
+            ancestors
+        }
+
+        // return run candidates: nodes that are not already done and where the children are all done
+        // (i.e., the dependencies are all satisfied)
+        fn get_run_candidates(&self, not_finished: &HashSet<Uuid>) -> HashSet<Uuid> {
+            self.get_leaves(not_finished)
+                .into_iter()
+                .filter(|id| !self.nodes[id].is_done())
+                .collect()
+        }
+
+        // return the ids of the nodes in remaining with no children in remaining
+        fn get_leaves(&self, remaining: &HashSet<Uuid>) -> HashSet<Uuid> {
+            let mut leaves = HashSet::new();
+            for id in remaining {
+                if let Some(node) = self.nodes.get(id) {
+                    if node.children.intersection(remaining).next().is_none() {
+                        leaves.insert(*id);
+                    }
+                }
+            }
+            leaves
+        }
+
+        // Fail if the child's existing node is its parent or one of the parent's
+        // ancestors, naming the tasks in the cycle
+        fn check_cycle(nodes: &HashMap<Uuid, Node>, child: &ChildData) -> Result<()> {
+            // depth-first search up from the parent, keeping the path
+            let mut path = vec![child.parent];
+            let mut to_visit = vec![(child.parent, 0)];
+            let mut visited = HashSet::new();
+            while let Some((id, depth)) = to_visit.pop() {
+                path.truncate(depth);
+                path.push(id);
+                if id == child.id {
+                    let mut chain = path
+                        .iter()
+                        .rev()
+                        .map(|id| nodes[id].task.get_name())
+                        .collect::<Vec<_>>();
+                    chain.push(child.task.get_name());
+                    return Err(anyhow!("Dependency cycle: {}", chain.join(" -> ")));
+                }
+                if visited.insert(id) {
+                    to_visit.extend(nodes[&id].parents.iter().map(|p| (*p, depth + 1)));
+                }
+            }
+            Ok(())
+        }
+
+        /// Make a node and a collection of children with enough information to connect them to the DAG
+        fn make_node(
+            task: Box<dyn Task>,
+            node_id: Uuid,
+            ids: &mut HashMap<String, Uuid>,
+        ) -> Result<NodeWithChildren> {
+            check_timeout(task.as_ref())?;
+            let status = if task.get_target()?.exists()? {
+                TaskStatus::Skipped
+            } else {
+                TaskStatus::Pending
+            };
+            let hard_tasks = task.get_dep_tasks()?.into_values().map(|t| (t, false));
+            let soft_tasks = task.soft_dep_tasks()?.into_values().map(|t| (t, true));
+            let mut children = Vec::new();
+            for (child, soft) in hard_tasks.chain(soft_tasks) {
+                let id = *ids.entry(child.identity()).or_insert_with(Uuid::new_v4);
+                children.push(ChildData {
+                    id,
+                    task: child,
+                    parent: node_id,
+                    soft,
+                });
+            }
+            let node = Node {
+                id: node_id,
+                task,
+                status,
+                parents: HashSet::new(),
+                children: children.iter().map(|c| c.id).collect::<HashSet<_>>(),
+                soft_children: children
+                    .iter()
+                    .filter(|c| c.soft)
+                    .map(|c| c.id)
+                    .collect::<HashSet<_>>(),
+            };
+            Ok(NodeWithChildren { node, children })
+        }
+    }
+
+    /// Print the DAG, one line per node
+    impl fmt::Debug for DAG {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let mut node_str_vec = Vec::new();
+            for node in self.nodes.values() {
+                node_str_vec.push(format!("{:?}", node));
+            }
+            let s = node_str_vec.join("\n");
+            f.write_str(&s)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::collections::HashMap;
+
+        use crate::{
+            scheduler::DAG,
+            tasks::{FileTarget, Target, Task},
+        };
+        use anyhow::{anyhow, Result};
+
+        #[derive(Debug)]
+        struct Dep1 {}
+        impl Task for Dep1 {
+            fn get_name(&self) -> String {
+                "Dep1".to_string()
+            }
+
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget {
+                    cache_dir: "/tmp".to_string(),
+                    local_filename: "test_dag_target_dep1.txt".to_string(),
+                }))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok("dep1 data".as_bytes().to_vec())
+            }
+        }
+
+        #[derive(Debug)]
+        struct Dep2 {}
+        impl Task for Dep2 {
+            fn get_name(&self) -> String {
+                "Dep2".to_string()
+            }
+
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget {
+                    cache_dir: "/tmp".to_string(),
+                    local_filename: "test_dag_target_dep2.txt".to_string(),
+                }))
+            }
+
+            fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                let mut result = HashMap::<String, Box<dyn Task>>::new();
+                result.insert("dep3".to_string(), Box::new(Dep3 {}));
+                Ok(result)
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                let dep_targets = self
+                    .get_dep_targets()
+                    .expect("Couldn't get dependent targets");
+                let mut s1 = dep_targets.get("dep3").unwrap().read()?;
+                s1.extend(" - dep2".as_bytes());
+                Ok(s1)
+            }
+        }
+
+        #[derive(Debug)]
+        struct Dep3 {}
+        impl Task for Dep3 {
+            fn get_name(&self) -> String {
+                "Dep3".to_string()
+            }
+
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget {
+                    cache_dir: "/tmp".to_string(),
+                    local_filename: "test_dag_target_dep3.txt".to_string(),
+                }))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok("dep3 data".as_bytes().to_vec())
+            }
+        }
+
+        #[derive(Debug)]
+        struct FinalTask {}
+        impl Task for FinalTask {
+            fn get_name(&self) -> String {
+                "FinalTask".to_string()
+            }
+
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new(
+                    "/tmp",
+                    "test_dag_target_depfinal.txt",
+                )))
+            }
+
+            fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                let mut result = HashMap::<String, Box<dyn Task>>::new();
+                result.insert("dep1".to_string(), Box::new(Dep1 {}));
+                result.insert("dep2".to_string(), Box::new(Dep2 {}));
+                Ok(result)
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                let dep_targets = self
+                    .get_dep_targets()
+                    .expect("Couldn't get dependent targets");
+                let mut s1 = dep_targets.get("dep1").unwrap().read()?;
+                s1.extend(" - ".as_bytes());
+                s1.extend(dep_targets.get("dep2").unwrap().read()?);
+                Ok(s1)
+            }
+        }
+
+        /// Generic test task writing "<name> data" to /tmp/test_dag_<name>.txt once its
+        /// dependencies are done. Output that doesn't start with that fails validation.
+        #[derive(Debug, Clone)]
+        struct Step {
+            name: &'static str,
+            deps: Vec<Step>,
+            fails: bool,
+            tags: Vec<&'static str>,
+            weight: f64,
+            remote: bool,
+        }
+
+        impl Step {
+            fn new(name: &'static str, deps: Vec<Step>) -> Self {
+                Step {
+                    name,
+                    deps,
+                    fails: false,
+                    tags: Vec::new(),
+                    weight: 1.0,
+                    remote: false,
+                }
+            }
+
+            /// Run elsewhere by run_with_remote
+            fn remote(mut self) -> Self {
+                self.remote = true;
+                self
+            }
+
+            fn weighted(mut self, weight: f64) -> Self {
+                self.weight = weight;
+                self
+            }
+
+            fn tagged(mut self, tag: &'static str) -> Self {
+                self.tags.push(tag);
+                self
+            }
+
+            fn leaf(name: &'static str) -> Self {
+                Step::new(name, Vec::new())
+            }
+
+            /// Leaf whose compute_output fails
+            fn failing(name: &'static str) -> Self {
+                Step {
+                    fails: true,
+                    ..Step::leaf(name)
+                }
+            }
+        }
+
+        impl Task for Step {
+            fn get_name(&self) -> String {
+                self.name.to_string()
+            }
+
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new(
+                    "/tmp",
+                    &format!("test_dag_{}.txt", self.name),
+                )))
+            }
+
+            fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                let mut result = HashMap::<String, Box<dyn Task>>::new();
+                for dep in &self.deps {
+                    result.insert(dep.name.to_string(), Box::new(dep.clone()));
+                }
+                Ok(result)
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                if self.fails {
+                    return Err(anyhow!("{} failed", self.name));
+                }
+                Ok(format!("{} data", self.name).into_bytes())
+            }
+
+            fn tags(&self) -> Vec<String> {
+                self.tags.iter().map(|tag| tag.to_string()).collect()
+            }
+
+            fn weight(&self) -> f64 {
+                self.weight
+            }
+
+            fn is_remote(&self) -> bool {
+                self.remote
+            }
+
+            fn validate(&self, data: &[u8]) -> Result<()> {
+                if data.starts_with(format!("{} data", self.name).as_bytes()) {
+                    Ok(())
+                } else {
+                    Err(anyhow!("unexpected data for {}", self.name))
+                }
+            }
+        }
+
+        #[test]
+        fn construct_scheduler() {
+            let task: Box<dyn Task> = Box::new(FinalTask {});
+            task.recursively_delete_data()
+                .expect("Failed to delete task and dependent task data");
+            let dag = DAG::new(task).expect("Failed to construct DAG");
+            let any_done = dag.nodes.values().any(|node| node.is_done());
+            assert!(!any_done);
+
+            let task: Box<dyn Task> = Box::new(FinalTask {});
+            task.run().expect("task failed to run");
+            let dag = DAG::new(task).expect("Failed to construct DAG");
+            let all_done = dag.nodes.values().all(|node| node.is_done());
+            assert!(all_done);
+        }
+
+        #[test]
+        fn timeout_without_boxed_clone() {
+            use std::time::Duration;
+
+            /// Has a timeout but can't be moved to a worker thread
+            #[derive(Debug)]
+            struct Unclonable {}
+            impl Task for Unclonable {
+                fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                    let mut result = HashMap::<String, Box<dyn Task>>::new();
+                    result.insert("dep".to_string(), Box::new(Step::leaf("unclonable_dep")));
+                    Ok(result)
+                }
+
+                fn timeout(&self) -> Option<Duration> {
+                    Some(Duration::from_secs(1))
+                }
+            }
+
+            let err = DAG::new(Box::new(Unclonable {})).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Unclonable: tasks with a timeout must implement boxed_clone()"
+            );
+        }
+
+        #[test]
+        fn local_run() {
+            let task: Box<dyn Task> = Box::new(FinalTask {});
+            task.recursively_delete_data()
+                .expect("Failed to delete task and dependent task data");
+            let mut dag = DAG::new(task).expect("Failed to construct DAG");
+
+            let any_done = dag.nodes.values().any(|node| node.is_done());
+            assert!(!any_done);
+
+            dag.run(&crate::scheduler::RunStyle::LOCAL)
+                .expect("Failed to run the DAG");
+
+            let all_done = dag.nodes.values().all(|node| node.is_done());
+            assert!(all_done);
+        }
+
+        #[test]
+        fn parallel_run() {
+            let task: Box<dyn Task> = Box::new(FinalTask {});
             task.recursively_delete_data()
                 .expect("Failed to delete task and dependent task data");
             let mut dag = DAG::new(task).expect("Failed to construct DAG");
 
-            let any_done = dag.nodes.values().any(|node| node.is_done);
-            assert!(!any_done);
+            let any_done = dag.nodes.values().any(|node| node.is_done());
+            assert!(!any_done);
+
+            dag.run(&crate::scheduler::RunStyle::PARALLEL)
+                .expect("Failed to run the DAG");
+
+            let all_done = dag.nodes.values().all(|node| node.is_done());
+            assert!(all_done);
+        }
+
+        #[test]
+        fn delete_all() {
+            let task: Box<dyn Task> = Box::new(FinalTask {});
+            let mut dag = DAG::new(task).expect("Failed to construct DAG");
+            dag.run(&crate::scheduler::RunStyle::LOCAL)
+                .expect("Failed to run the DAG");
+            let all_done = dag.nodes.values().all(|node| node.is_done());
+            assert!(all_done);
+
+            dag.delete_all().expect("delete_all failed");
+            let any_done = dag.nodes.values().any(|node| node.is_done());
+            assert!(!any_done);
+        }
+
+        #[test]
+        fn validate_all() {
+            let task = Step::new(
+                "validate_all_head",
+                vec![
+                    Step::leaf("validate_all_leaf1"),
+                    Step::leaf("validate_all_leaf2"),
+                ],
+            );
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task)).expect("Failed to construct DAG");
+            dag.run(&crate::scheduler::RunStyle::LOCAL)
+                .expect("Failed to run the DAG");
+            assert!(dag.validate_all().unwrap().is_empty());
+
+            // corrupt one of the cached outputs
+            Step::leaf("validate_all_leaf2")
+                .get_target()
+                .unwrap()
+                .write("corrupted".as_bytes())
+                .unwrap();
+            let failures = dag.validate_all().unwrap();
+            assert_eq!(failures.len(), 1);
+            assert_eq!(failures[0].0, "validate_all_leaf2");
+        }
+
+        #[test]
+        fn run_validates() {
+            use crate::scheduler::{NodeOutcome, RunStyle};
+
+            /// Output that never passes validation
+            #[derive(Debug)]
+            struct Invalid {}
+            impl Task for Invalid {
+                fn get_name(&self) -> String {
+                    "invalid".to_string()
+                }
+
+                fn get_target(&self) -> Result<Box<dyn Target>> {
+                    Ok(Box::new(FileTarget::new("/tmp", "test_dag_invalid.txt")))
+                }
+
+                fn compute_output(&self) -> Result<Vec<u8>> {
+                    Ok("bad data".as_bytes().to_vec())
+                }
+
+                fn validate(&self, _data: &[u8]) -> Result<()> {
+                    Err(anyhow!("bad data rejected"))
+                }
+            }
+
+            for run_style in [RunStyle::LOCAL, RunStyle::PARALLEL] {
+                Invalid {}.delete_data().unwrap();
+                let mut dag = DAG::new(Box::new(Invalid {})).expect("Failed to construct DAG");
+                let err = dag.run(&run_style).unwrap_err();
+                assert_eq!(err.to_string(), "Tasks failed: invalid");
+                let report = dag.last_report().unwrap();
+                assert_eq!(report.names(NodeOutcome::Failed), vec!["invalid"]);
+                assert!(!Invalid {}.get_target().unwrap().exists().unwrap());
+            }
+        }
+
+        #[test]
+        fn custom_executor() {
+            use crate::scheduler::{Executor, LocalExecutor};
+            use std::sync::Mutex;
+
+            /// Runs tasks locally, recording the names in each batch
+            #[derive(Default)]
+            struct RecordingExecutor {
+                batches: Mutex<Vec<Vec<String>>>,
+            }
+
+            impl Executor for RecordingExecutor {
+                fn run_batch(&self, tasks: Vec<&dyn Task>) -> Vec<Result<()>> {
+                    let mut names = tasks.iter().map(|t| t.get_name()).collect::<Vec<_>>();
+                    names.sort();
+                    self.batches.lock().unwrap().push(names);
+                    LocalExecutor.run_batch(tasks)
+                }
+            }
+
+            let task = Step::new(
+                "executor_head",
+                vec![
+                    Step::new("executor_mid", vec![Step::leaf("executor_leaf1")]),
+                    Step::leaf("executor_leaf2"),
+                ],
+            );
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task)).expect("Failed to construct DAG");
+            let executor = RecordingExecutor::default();
+            dag.run_with_executor(&executor)
+                .expect("Failed to run the DAG");
+
+            assert!(dag.nodes.values().all(|node| node.is_done()));
+            assert_eq!(
+                executor.batches.into_inner().unwrap(),
+                vec![
+                    vec!["executor_leaf1", "executor_leaf2"],
+                    vec!["executor_mid"],
+                    vec!["executor_head"],
+                ]
+            );
+        }
+
+        #[test]
+        fn bounded_construction() {
+            /// A chain of tasks depth deep
+            #[derive(Debug)]
+            struct Chain {
+                depth: usize,
+            }
+            impl Task for Chain {
+                fn get_name(&self) -> String {
+                    format!("Chain {}", self.depth)
+                }
+
+                fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                    let mut result = HashMap::<String, Box<dyn Task>>::new();
+                    if self.depth > 0 {
+                        result.insert(
+                            "next".to_string(),
+                            Box::new(Chain {
+                                depth: self.depth - 1,
+                            }),
+                        );
+                    }
+                    Ok(result)
+                }
+            }
+
+            let dag = DAG::new_bounded(Box::new(Chain { depth: 9 }), 10)
+                .expect("Failed to construct DAG");
+            assert_eq!(dag.nodes.len(), 10);
+
+            let err = DAG::new_bounded(Box::new(Chain { depth: 1_000_000 }), 100)
+                .expect_err("DAG should be truncated");
+            assert!(err.to_string().contains("more than 100 nodes"));
+        }
+
+        #[test]
+        fn skipped_due_to_failure() {
+            use crate::scheduler::NodeOutcome;
+
+            let task = Step::new(
+                "skipped_head",
+                vec![
+                    Step::new("skipped_mid", vec![Step::failing("skipped_failing_leaf")]),
+                    Step::leaf("skipped_ok_leaf"),
+                ],
+            );
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task)).expect("Failed to construct DAG");
+            let err = dag.run(&crate::scheduler::RunStyle::LOCAL).unwrap_err();
+            assert_eq!(err.to_string(), "Tasks failed: skipped_failing_leaf");
+            // failed and skipped nodes aren't done
+            assert!(dag
+                .nodes
+                .values()
+                .filter(|node| node.task.get_name() != "skipped_ok_leaf")
+                .all(|node| !node.is_done()));
+
+            let report = dag.last_report().unwrap();
+            assert_eq!(
+                report.names(NodeOutcome::Failed),
+                vec!["skipped_failing_leaf"]
+            );
+            assert_eq!(report.names(NodeOutcome::Computed), vec!["skipped_ok_leaf"]);
+            assert_eq!(
+                report.names(NodeOutcome::Blocked),
+                vec!["skipped_head", "skipped_mid"]
+            );
+            assert_eq!(report.skipped_due_to.len(), 2);
+            for name in ["skipped_head", "skipped_mid"] {
+                assert_eq!(report.skipped_due_to[name], "skipped_failing_leaf");
+            }
+            assert!(!Step::leaf("skipped_head")
+                .get_target()
+                .unwrap()
+                .exists()
+                .unwrap());
+        }
+
+        #[test]
+        fn failure_policy() {
+            use crate::scheduler::{FailurePolicy, NodeOutcome, RunStyle};
+
+            let task = Step::new(
+                "policy_head",
+                vec![
+                    Step::new("policy_mid_a", vec![Step::failing("policy_failing_leaf")]),
+                    Step::new("policy_mid_b", vec![Step::leaf("policy_leaf_b")]),
+                ],
+            );
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task.clone())).expect("Failed to construct DAG");
+            let err = dag.run(&RunStyle::LOCAL).unwrap_err();
+            assert_eq!(err.to_string(), "Tasks failed: policy_failing_leaf");
+            let report = dag.last_report().unwrap();
+            assert_eq!(
+                report.names(NodeOutcome::Computed),
+                vec!["policy_leaf_b", "policy_mid_b"]
+            );
+            assert_eq!(
+                report.names(NodeOutcome::Blocked),
+                vec!["policy_head", "policy_mid_a"]
+            );
+
+            // failing fast the independent branch stops too
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task))
+                .expect("Failed to construct DAG")
+                .with_failure_policy(FailurePolicy::FailFast);
+            let err = dag.run(&RunStyle::LOCAL).unwrap_err();
+            assert_eq!(err.to_string(), "Tasks failed: policy_failing_leaf");
+            let report = dag.last_report().unwrap();
+            assert_eq!(report.names(NodeOutcome::Computed), vec!["policy_leaf_b"]);
+            assert_eq!(
+                report.names(NodeOutcome::Blocked),
+                vec!["policy_head", "policy_mid_a", "policy_mid_b"]
+            );
+            assert_eq!(report.skipped_due_to["policy_mid_b"], "policy_failing_leaf");
+        }
+
+        #[test]
+        fn fail_fast_waits_for_remote() {
+            use crate::scheduler::{FailurePolicy, NodeOutcome, RunStyle};
+            use std::{sync::Mutex, thread, time::Duration};
+
+            let task = Step::new(
+                "ff_remote_head",
+                vec![
+                    Step::leaf("ff_remote_leaf").remote(),
+                    Step::failing("ff_failing_leaf"),
+                ],
+            );
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task))
+                .expect("Failed to construct DAG")
+                .with_failure_policy(FailurePolicy::FailFast);
+
+            // the remote task is still running when the local one fails
+            let workers = Mutex::new(Vec::new());
+            let submit = |task: &dyn Task| {
+                let name = task.get_name();
+                let target = FileTarget::new("/tmp", &format!("test_dag_{}.txt", name));
+                workers.lock().unwrap().push(thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(50));
+                    target.write(format!("{} data", name).as_bytes()).unwrap();
+                }));
+                Ok(())
+            };
+            let err = dag
+                .run_with_remote(&RunStyle::PARALLEL, submit, Duration::from_millis(5))
+                .unwrap_err();
+            assert_eq!(err.to_string(), "Tasks failed: ff_failing_leaf");
+            for worker in workers.into_inner().unwrap() {
+                worker.join().unwrap();
+            }
+
+            let report = dag.last_report().unwrap();
+            assert_eq!(report.names(NodeOutcome::Computed), vec!["ff_remote_leaf"]);
+            assert_eq!(report.names(NodeOutcome::Failed), vec!["ff_failing_leaf"]);
+            assert_eq!(report.names(NodeOutcome::Blocked), vec!["ff_remote_head"]);
+        }
+
+        #[test]
+        fn soft_dependency_failure() {
+            use crate::scheduler::{NodeOutcome, RunStyle};
+
+            /// Combines a required dependency with optional enrichment from a soft one
+            #[derive(Debug)]
+            struct Enriched {}
+            impl Task for Enriched {
+                fn get_name(&self) -> String {
+                    "soft_head".to_string()
+                }
+
+                fn get_target(&self) -> Result<Box<dyn Target>> {
+                    Ok(Box::new(FileTarget::new("/tmp", "test_dag_soft_head.txt")))
+                }
+
+                fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                    let mut result = HashMap::<String, Box<dyn Task>>::new();
+                    result.insert("base".to_string(), Box::new(Step::leaf("soft_base")));
+                    Ok(result)
+                }
+
+                fn soft_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                    let mut result = HashMap::<String, Box<dyn Task>>::new();
+                    result.insert(
+                        "enrichment".to_string(),
+                        Box::new(Step::failing("soft_enrichment")),
+                    );
+                    Ok(result)
+                }
+
+                fn compute_output(&self) -> Result<Vec<u8>> {
+                    let mut data = self.get_dep_targets()?["base"].read()?;
+                    let enrichment = &self.soft_dep_targets()?["enrichment"];
+                    if enrichment.exists()? {
+                        data.extend(enrichment.read()?);
+                    } else {
+                        data.extend(" - no enrichment".as_bytes());
+                    }
+                    Ok(data)
+                }
+            }
+
+            let task = Enriched {};
+            task.recursively_delete_data().unwrap();
+            task.run()
+                .expect("soft dependency failure should not fail the task");
+            assert_eq!(
+                task.get_data().unwrap(),
+                "soft_base data - no enrichment".as_bytes().to_vec()
+            );
+
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task)).expect("Failed to construct DAG");
+            // the soft dependency's failure fails the run, but not the task depending on it
+            assert!(dag.run(&RunStyle::LOCAL).is_err());
+            let report = dag.last_report().unwrap();
+            assert_eq!(report.names(NodeOutcome::Failed), vec!["soft_enrichment"]);
+            assert_eq!(
+                report.names(NodeOutcome::Computed),
+                vec!["soft_base", "soft_head"]
+            );
+            assert!(report.names(NodeOutcome::Blocked).is_empty());
+            assert_eq!(
+                Enriched {}.get_data().unwrap(),
+                "soft_base data - no enrichment".as_bytes().to_vec()
+            );
+
+            // with manual stepping a failed soft dependency doesn't block the head either
+            Enriched {}.delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(Enriched {})).expect("Failed to construct DAG");
+            while let Some(id) = dag.ready().pop() {
+                match dag.nodes[&id].task.run_no_deps() {
+                    Ok(()) => dag.mark_done(id).unwrap(),
+                    Err(_) => dag.mark_failed(id).unwrap(),
+                }
+            }
+            assert!(Enriched {}.get_target().unwrap().exists().unwrap());
+        }
+
+        #[test]
+        fn run_report_diff() {
+            use crate::scheduler::{NodeOutcome, NodeReport, RunReport};
+            use std::time::Duration;
+
+            fn report(nodes: &[(&str, NodeOutcome, u64)]) -> RunReport {
+                RunReport {
+                    nodes: nodes
+                        .iter()
+                        .map(|(name, outcome, millis)| NodeReport {
+                            name: name.to_string(),
+                            outcome: *outcome,
+                            start: Duration::ZERO,
+                            duration: Duration::from_millis(*millis),
+                            peak_memory: None,
+                        })
+                        .collect(),
+                    ..RunReport::default()
+                }
+            }
+
+            let first = report(&[
+                ("head", NodeOutcome::Computed, 30),
+                ("leaf1", NodeOutcome::Computed, 10),
+                ("leaf2", NodeOutcome::Cached, 0),
+                ("old_leaf", NodeOutcome::Cached, 0),
+            ]);
+            let second = report(&[
+                ("head", NodeOutcome::Computed, 20),
+                ("leaf1", NodeOutcome::Cached, 0),
+                ("leaf2", NodeOutcome::Computed, 15),
+                ("new_leaf", NodeOutcome::Failed, 5),
+            ]);
+            let diff = first.diff(&second);
+            assert_eq!(diff.newly_computed, vec!["leaf2"]);
+            assert_eq!(diff.became_cached, vec!["leaf1"]);
+            assert_eq!(diff.appeared, vec!["new_leaf"]);
+            assert_eq!(diff.disappeared, vec!["old_leaf"]);
+            assert_eq!(diff.durations.len(), 3);
+            assert_eq!(
+                diff.durations["head"],
+                (Duration::from_millis(30), Duration::from_millis(20))
+            );
+
+            let same = first.diff(&first);
+            assert!(same.newly_computed.is_empty() && same.became_cached.is_empty());
+            assert!(same.appeared.is_empty() && same.disappeared.is_empty());
+        }
+
+        #[test]
+        fn write_limit() {
+            use crate::scheduler::RunStyle;
+            use std::{
+                sync::{
+                    atomic::{AtomicUsize, Ordering},
+                    Arc,
+                },
+                thread,
+                time::Duration,
+            };
+
+            /// Tracks how many writes are in progress, and the most seen at once
+            #[derive(Debug, Default)]
+            struct WriteCounter {
+                active: AtomicUsize,
+                max_active: AtomicUsize,
+            }
+
+            struct SlowTarget {
+                counter: Arc<WriteCounter>,
+                target: FileTarget,
+            }
+
+            impl Target for SlowTarget {
+                fn read(&self) -> Result<Vec<u8>> {
+                    self.target.read()
+                }
+
+                fn write(&self, s: &[u8]) -> Result<()> {
+                    let active = self.counter.active.fetch_add(1, Ordering::SeqCst) + 1;
+                    self.counter.max_active.fetch_max(active, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(5));
+                    let res = self.target.write(s);
+                    self.counter.active.fetch_sub(1, Ordering::SeqCst);
+                    res
+                }
+
+                fn delete(&self) -> Result<()> {
+                    self.target.delete()
+                }
+
+                fn exists(&self) -> Result<bool> {
+                    self.target.exists()
+                }
+            }
+
+            #[derive(Debug)]
+            struct Writer {
+                i: usize,
+                counter: Arc<WriteCounter>,
+            }
+
+            impl Task for Writer {
+                fn get_name(&self) -> String {
+                    format!("Writer{}", self.i)
+                }
+
+                fn get_target(&self) -> Result<Box<dyn Target>> {
+                    Ok(Box::new(SlowTarget {
+                        counter: self.counter.clone(),
+                        target: FileTarget::new(
+                            "/tmp",
+                            &format!("test_dag_write_limit_{}.txt", self.i),
+                        ),
+                    }))
+                }
+            }
+
+            #[derive(Debug)]
+            struct AllWriters {
+                counter: Arc<WriteCounter>,
+            }
+
+            impl Task for AllWriters {
+                fn get_name(&self) -> String {
+                    "AllWriters".to_string()
+                }
+
+                fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                    let mut result = HashMap::<String, Box<dyn Task>>::new();
+                    for i in 0..16 {
+                        result.insert(
+                            i.to_string(),
+                            Box::new(Writer {
+                                i,
+                                counter: self.counter.clone(),
+                            }),
+                        );
+                    }
+                    Ok(result)
+                }
+            }
+
+            let counter = Arc::new(WriteCounter::default());
+            let task = AllWriters {
+                counter: counter.clone(),
+            };
+            task.recursively_delete_data().unwrap();
+            let dag = DAG::new(Box::new(task)).expect("Failed to construct DAG");
+            assert!(DAG::new(Box::new(AllWriters {
+                counter: counter.clone(),
+            }))
+            .unwrap()
+            .with_write_limit(0)
+            .is_err());
+            let mut dag = dag.with_write_limit(1).unwrap();
+            dag.run(&RunStyle::PARALLEL).expect("Failed to run the DAG");
+            assert!(dag.nodes.values().all(|node| node.is_done()));
+            assert_eq!(counter.max_active.load(Ordering::SeqCst), 1);
+        }
+
+        #[test]
+        fn prune_done_leaves() {
+            use crate::scheduler::RunStyle;
+
+            let task = Step::new(
+                "prune_head",
+                vec![
+                    Step::new(
+                        "prune_mid1",
+                        vec![Step::leaf("prune_leaf1"), Step::leaf("prune_leaf2")],
+                    ),
+                    Step::new("prune_mid2", vec![Step::leaf("prune_leaf3")]),
+                ],
+            );
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task.clone())).expect("Failed to construct DAG");
+            let mut counts = vec![dag.nodes.len()];
+            while let Some(id) = dag.ready().pop() {
+                dag.nodes[&id].task.run_no_deps().unwrap();
+                dag.mark_done(id).unwrap();
+                dag.prune_done_leaves();
+                counts.push(dag.nodes.len());
+            }
+            assert_eq!(counts.first(), Some(&6));
+            assert_eq!(counts.last(), Some(&1));
+            assert!(counts.windows(2).all(|w| w[1] <= w[0]));
+            assert!(task.get_target().unwrap().exists().unwrap());
+
+            // pruning during a run
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task.clone()))
+                .expect("Failed to construct DAG")
+                .with_pruning();
+            dag.run(&RunStyle::LOCAL).expect("Failed to run the DAG");
+            assert_eq!(dag.nodes.len(), 1);
+            assert!(dag.nodes.values().all(|node| node.is_done()));
+            assert_eq!(dag.last_report().unwrap().nodes.len(), 6);
+        }
+
+        #[test]
+        fn run_report_timings() {
+            use crate::scheduler::{NodeOutcome, RunStyle};
+
+            let task = Step::new(
+                "timed_head",
+                vec![
+                    Step::new("timed_mid", vec![Step::leaf("timed_leaf1")]),
+                    Step::leaf("timed_leaf2"),
+                ],
+            );
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task.clone())).expect("Failed to construct DAG");
+            dag.run(&RunStyle::PARALLEL).expect("Failed to run the DAG");
+            let report = dag.last_report().unwrap();
+            assert_eq!(report.nodes.len(), 4);
+            for node in &report.nodes {
+                assert_eq!(node.outcome, NodeOutcome::Computed);
+                assert!(!node.duration.is_zero(), "{} wasn't timed", node.name);
+            }
+
+            // on a second run everything is cached
+            let mut dag = DAG::new(Box::new(task)).expect("Failed to construct DAG");
+            dag.run(&RunStyle::PARALLEL).expect("Failed to run the DAG");
+            let report = dag.last_report().unwrap();
+            assert_eq!(report.names(NodeOutcome::Cached).len(), 4);
+            assert!(report.nodes.iter().all(|node| node.duration.is_zero()));
+        }
+
+        #[test]
+        fn run_forced() {
+            use crate::scheduler::{NodeOutcome, RunStyle};
+
+            let task = Step::new("forced_head", vec![Step::leaf("forced_leaf")]);
+            for step in [&task, &task.deps[0]] {
+                step.get_target().unwrap().write(b"stale").unwrap();
+            }
+            let mut dag = DAG::new(Box::new(task.clone())).expect("Failed to construct DAG");
+            dag.run_forced(&RunStyle::PARALLEL)
+                .expect("Failed to run the DAG");
+            let report = dag.last_report().unwrap();
+            assert_eq!(report.names(NodeOutcome::Computed).len(), 2);
+            assert_eq!(task.get_data().unwrap(), b"forced_head data".to_vec());
+            assert_eq!(
+                task.deps[0].get_data().unwrap(),
+                b"forced_leaf data".to_vec()
+            );
+        }
+
+        #[test]
+        fn run_until() {
+            use crate::scheduler::{NodeOutcome, RunStyle, TaskStatus};
+
+            // shaped like FinalTask, with targets no other test touches
+            let task = Step::new(
+                "until_final",
+                vec![
+                    Step::leaf("until_dep1"),
+                    Step::new("until_dep2", vec![Step::leaf("until_dep3")]),
+                ],
+            );
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task.clone())).expect("Failed to construct DAG");
+            assert!(dag.run_until("until_missing", &RunStyle::LOCAL).is_err());
+            dag.run_until("until_dep2", &RunStyle::LOCAL)
+                .expect("Failed to run the DAG");
+            assert_eq!(
+                dag.last_report().unwrap().names(NodeOutcome::Computed),
+                vec!["until_dep2", "until_dep3"]
+            );
+            for name in ["until_final", "until_dep1"] {
+                let node = dag
+                    .nodes
+                    .values()
+                    .find(|node| node.task.get_name() == name)
+                    .unwrap();
+                assert_eq!(node.status, TaskStatus::Pending);
+            }
+            assert!(!task.get_target().unwrap().exists().unwrap());
+        }
+
+        #[test]
+        fn run_and_delete_tagged() {
+            use crate::scheduler::{NodeOutcome, RunStyle};
+
+            let task = Step::new(
+                "tagged_head",
+                vec![
+                    Step::new("tagged_mid", vec![Step::leaf("tagged_leaf1")]).tagged("nightly"),
+                    Step::leaf("tagged_leaf2").tagged("nightly"),
+                ],
+            );
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task.clone())).expect("Failed to construct DAG");
+            dag.run_tagged("nightly", &RunStyle::LOCAL)
+                .expect("Failed to run the DAG");
+            assert_eq!(
+                dag.last_report().unwrap().names(NodeOutcome::Computed),
+                vec!["tagged_leaf1", "tagged_leaf2", "tagged_mid"]
+            );
+            assert!(!task.get_target().unwrap().exists().unwrap());
+
+            dag.delete_tagged("nightly").unwrap();
+            for name in ["tagged_mid", "tagged_leaf2"] {
+                assert!(!Step::leaf(name).get_target().unwrap().exists().unwrap());
+            }
+            assert!(Step::leaf("tagged_leaf1")
+                .get_target()
+                .unwrap()
+                .exists()
+                .unwrap());
+        }
+
+        #[test]
+        fn run_with_sender() {
+            use crate::scheduler::{RunStyle, TaskEvent};
+            use std::{sync::mpsc, thread};
+
+            let task = Step::new(
+                "sender_head",
+                vec![
+                    Step::new("sender_mid", vec![Step::failing("sender_failing_leaf")]),
+                    Step::leaf("sender_leaf"),
+                ],
+            );
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task)).expect("Failed to construct DAG");
+            let (tx, rx) = mpsc::channel();
+            let consumer = thread::spawn(move || rx.iter().collect::<Vec<_>>());
+            assert!(dag.run_with_sender(&RunStyle::PARALLEL, tx).is_err());
+            // the channel is closed when the run ends, so the consumer finishes
+            let events = consumer.join().unwrap();
+
+            assert_eq!(events.len(), 6);
+            let started = events
+                .iter()
+                .filter(|e| matches!(e, TaskEvent::Started { .. }))
+                .count();
+            assert_eq!(started, 2);
+            assert!(events.iter().any(|e| matches!(e,
+                TaskEvent::Finished { name, .. } if name == "sender_leaf")));
+            assert!(events.iter().any(|e| matches!(e,
+                TaskEvent::Failed { name, .. } if name == "sender_failing_leaf")));
+            for skipped in ["sender_head", "sender_mid"] {
+                assert!(events.contains(&TaskEvent::Skipped {
+                    name: skipped.to_string(),
+                    blocked_by: "sender_failing_leaf".to_string(),
+                }));
+            }
+
+            // on a successful run every node starts and then finishes
+            let task = Step::new(
+                "progress_head",
+                vec![Step::leaf("progress_leaf1"), Step::leaf("progress_leaf2")],
+            );
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task)).expect("Failed to construct DAG");
+            let (tx, rx) = mpsc::channel();
+            dag.run_with_sender(&RunStyle::PARALLEL, tx).unwrap();
+            let events = rx.iter().collect::<Vec<_>>();
+            assert_eq!(events.len(), 6);
+            for node in ["progress_head", "progress_leaf1", "progress_leaf2"] {
+                let position = |started: bool| {
+                    events.iter().position(|e| match e {
+                        TaskEvent::Started { name } => started && name == node,
+                        TaskEvent::Finished { name, .. } => !started && name == node,
+                        _ => false,
+                    })
+                };
+                assert!(position(true).unwrap() < position(false).unwrap());
+            }
+        }
+
+        #[test]
+        fn run_with_retries() {
+            use crate::scheduler::{NodeOutcome, RunStyle};
+            use std::{
+                sync::atomic::{AtomicUsize, Ordering},
+                time::Duration,
+            };
+
+            /// Fails on the first attempt only
+            #[derive(Debug)]
+            struct Flaky {
+                attempts: AtomicUsize,
+            }
+            impl Task for Flaky {
+                fn get_name(&self) -> String {
+                    "Flaky".to_string()
+                }
+
+                fn get_target(&self) -> Result<Box<dyn Target>> {
+                    Ok(Box::new(FileTarget::new("/tmp", "test_dag_flaky.txt")))
+                }
 
-            dag.run(&crate::scheduler::RunStyle::PARALLEL)
+                fn compute_output(&self) -> Result<Vec<u8>> {
+                    if self.attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                        return Err(anyhow!("flaky failure"));
+                    }
+                    Ok("flaky data".as_bytes().to_vec())
+                }
+            }
+
+            let flaky = || Flaky {
+                attempts: AtomicUsize::new(0),
+            };
+            flaky().delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(flaky())).expect("Failed to construct DAG");
+            assert!(dag.run(&RunStyle::LOCAL).is_err());
+            let report = dag.last_report().unwrap();
+            assert_eq!(report.nodes[0].outcome, NodeOutcome::Failed);
+
+            let mut dag = DAG::new(Box::new(flaky()))
+                .expect("Failed to construct DAG")
+                .with_retry_backoff(Duration::from_millis(1));
+            dag.run_with_retries(&RunStyle::PARALLEL, 1)
                 .expect("Failed to run the DAG");
+            let report = dag.last_report().unwrap();
+            assert_eq!(report.nodes.len(), 1);
+            assert_eq!(report.nodes[0].outcome, NodeOutcome::Computed);
+            assert!(flaky().get_target().unwrap().exists().unwrap());
+        }
 
-            let all_done = dag.nodes.values().all(|node| node.is_done);
-            assert!(all_done);
+        #[test]
+        fn run_with_order() {
+            use crate::scheduler::RunStyle;
+            use std::sync::{Arc, Mutex};
+
+            /// Task appending its name to a shared log when computed
+            struct Logged {
+                name: &'static str,
+                deps: Vec<&'static str>,
+                log: Arc<Mutex<Vec<String>>>,
+            }
+            impl std::fmt::Debug for Logged {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    write!(f, "Logged({})", self.name)
+                }
+            }
+            impl Task for Logged {
+                fn get_name(&self) -> String {
+                    self.name.to_string()
+                }
+
+                fn get_target(&self) -> Result<Box<dyn Target>> {
+                    Ok(Box::new(FileTarget::new(
+                        "/tmp",
+                        &format!("test_dag_order_{}.txt", self.name),
+                    )))
+                }
+
+                fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                    let mut result = HashMap::<String, Box<dyn Task>>::new();
+                    for name in &self.deps {
+                        let dep = Logged {
+                            name,
+                            deps: Vec::new(),
+                            log: self.log.clone(),
+                        };
+                        result.insert(name.to_string(), Box::new(dep));
+                    }
+                    Ok(result)
+                }
+
+                fn compute_output(&self) -> Result<Vec<u8>> {
+                    self.log.lock().unwrap().push(self.name.to_string());
+                    Ok(Vec::new())
+                }
+            }
+
+            let log = Arc::new(Mutex::new(Vec::new()));
+            let head = Logged {
+                name: "order_head",
+                deps: vec!["b_leaf", "d_leaf", "a_leaf", "c_leaf"],
+                log: log.clone(),
+            };
+            head.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(head)).expect("Failed to construct DAG");
+            // reverse alphabetical order
+            dag.run_with_order(&RunStyle::LOCAL, |a, b| b.get_name().cmp(&a.get_name()))
+                .expect("Failed to run the DAG");
+            assert_eq!(
+                *log.lock().unwrap(),
+                vec!["d_leaf", "c_leaf", "b_leaf", "a_leaf", "order_head"]
+            );
         }
 
         #[test]
-        fn delete_all() {
-            let task: Box<dyn Task> = Box::new(FinalTask {});
-            let mut dag = DAG::new(task).expect("Failed to construct DAG");
-            dag.run(&crate::scheduler::RunStyle::LOCAL)
+        fn pool_run() {
+            use crate::scheduler::RunStyle;
+            use std::{
+                sync::{
+                    atomic::{AtomicUsize, Ordering},
+                    Arc,
+                },
+                thread,
+                time::Duration,
+            };
+
+            /// Tracks how many tasks run at once, and the most seen
+            #[derive(Debug, Default)]
+            struct Concurrency {
+                active: AtomicUsize,
+                max_active: AtomicUsize,
+            }
+
+            #[derive(Debug)]
+            struct Slow {
+                i: usize,
+                concurrency: Arc<Concurrency>,
+            }
+            impl Task for Slow {
+                fn get_name(&self) -> String {
+                    format!("pool_leaf{}", self.i)
+                }
+
+                fn get_target(&self) -> Result<Box<dyn Target>> {
+                    Ok(Box::new(FileTarget::new(
+                        "/tmp",
+                        &format!("test_dag_pool_{}.txt", self.i),
+                    )))
+                }
+
+                fn compute_output(&self) -> Result<Vec<u8>> {
+                    let active = self.concurrency.active.fetch_add(1, Ordering::SeqCst) + 1;
+                    self.concurrency
+                        .max_active
+                        .fetch_max(active, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    self.concurrency.active.fetch_sub(1, Ordering::SeqCst);
+                    Ok(Vec::new())
+                }
+            }
+
+            #[derive(Debug)]
+            struct AllLeaves {
+                concurrency: Arc<Concurrency>,
+            }
+            impl Task for AllLeaves {
+                fn get_name(&self) -> String {
+                    "pool_head".to_string()
+                }
+
+                fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                    let mut result = HashMap::<String, Box<dyn Task>>::new();
+                    for i in 0..8 {
+                        let leaf = Slow {
+                            i,
+                            concurrency: self.concurrency.clone(),
+                        };
+                        result.insert(i.to_string(), Box::new(leaf));
+                    }
+                    Ok(result)
+                }
+            }
+
+            let concurrency = Arc::new(Concurrency::default());
+            let task = AllLeaves {
+                concurrency: concurrency.clone(),
+            };
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task)).expect("Failed to construct DAG");
+            dag.run(&RunStyle::POOL { max_workers: 2 })
                 .expect("Failed to run the DAG");
-            let all_done = dag.nodes.values().all(|node| node.is_done);
-            assert!(all_done);
+            assert!(dag.nodes.values().all(|node| node.is_done()));
+            assert!(concurrency.max_active.load(Ordering::SeqCst) <= 2);
+        }
 
-            dag.delete_all().expect("delete_all failed");
-            let any_done = dag.nodes.values().any(|node| node.is_done);
-            assert!(!any_done);
+        #[test]
+        fn cluster_run() {
+            use crate::scheduler::{LocalProcessDispatcher, RunStyle, TaskDispatcher};
+            use std::sync::{Arc, Mutex};
+
+            /// Records the dispatched names without running anything
+            struct Recorder {
+                dispatched: Arc<Mutex<Vec<String>>>,
+            }
+            impl TaskDispatcher for Recorder {
+                fn dispatch(&self, task_name: &str) -> Result<()> {
+                    self.dispatched.lock().unwrap().push(task_name.to_string());
+                    Ok(())
+                }
+            }
+
+            let task = || {
+                Step::new(
+                    "cluster_head",
+                    vec![
+                        Step::new("cluster_mid", vec![Step::leaf("cluster_leaf1")]),
+                        Step::leaf("cluster_leaf2"),
+                    ],
+                )
+            };
+            task().recursively_delete_data().unwrap();
+            let dispatched = Arc::new(Mutex::new(Vec::new()));
+            let style = RunStyle::CLUSTER(Box::new(Recorder {
+                dispatched: dispatched.clone(),
+            }));
+            let mut dag = DAG::new(Box::new(task())).expect("Failed to construct DAG");
+            dag.run(&style).expect("Failed to run the DAG");
+            assert!(dag.nodes.values().all(|node| node.is_done()));
+
+            // every task is dispatched after its dependencies
+            let dispatched = dispatched.lock().unwrap().clone();
+            assert_eq!(dispatched.len(), 4);
+            let position = |name: &str| dispatched.iter().position(|n| n == name).unwrap();
+            assert!(position("cluster_leaf1") < position("cluster_mid"));
+            assert!(position("cluster_mid") < position("cluster_head"));
+            assert!(position("cluster_leaf2") < position("cluster_head"));
+
+            // the local dispatcher runs the tasks in this process
+            let style = RunStyle::CLUSTER(Box::new(
+                LocalProcessDispatcher::new(Box::new(task())).unwrap(),
+            ));
+            let mut dag = DAG::new(Box::new(task())).expect("Failed to construct DAG");
+            dag.run(&style).expect("Failed to run the DAG");
+            assert_eq!(
+                task().get_data().unwrap(),
+                "cluster_head data".as_bytes().to_vec()
+            );
+
+            // tasks sharing a name can't be dispatched by name
+            #[derive(Debug)]
+            struct Twin(u8);
+            impl Task for Twin {
+                fn compute_output(&self) -> Result<Vec<u8>> {
+                    Ok(vec![self.0])
+                }
+            }
+            #[derive(Debug)]
+            struct Twins {}
+            impl Task for Twins {
+                fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                    let mut result = HashMap::<String, Box<dyn Task>>::new();
+                    result.insert("first".to_string(), Box::new(Twin(1)));
+                    result.insert("second".to_string(), Box::new(Twin(2)));
+                    Ok(result)
+                }
+
+                fn compute_output(&self) -> Result<Vec<u8>> {
+                    Ok(Vec::new())
+                }
+            }
+            let err = LocalProcessDispatcher::new(Box::new(Twins {}))
+                .err()
+                .unwrap();
+            assert_eq!(err.to_string(), "Several tasks are named Twin");
+
+            // and a run dispatching them fails before dispatching anything
+            let dispatched = Arc::new(Mutex::new(Vec::new()));
+            let style = RunStyle::CLUSTER(Box::new(Recorder {
+                dispatched: dispatched.clone(),
+            }));
+            let mut dag = DAG::new(Box::new(Twins {})).expect("Failed to construct DAG");
+            let err = dag.run(&style).unwrap_err();
+            assert_eq!(err.to_string(), "Several tasks are named Twin");
+            assert!(dispatched.lock().unwrap().is_empty());
+        }
+
+        #[test]
+        fn shared_dependency() {
+            use crate::scheduler::RunStyle;
+
+            // a diamond: head depends on left and right, which both depend on shared
+            let shared = Step::leaf("diamond_shared");
+            let task = Step::new(
+                "diamond_head",
+                vec![
+                    Step::new("diamond_left", vec![shared.clone()]),
+                    Step::new("diamond_right", vec![shared]),
+                ],
+            );
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task)).expect("Failed to construct DAG");
+            assert_eq!(dag.nodes.len(), 4);
+            let shared_node = dag
+                .nodes
+                .values()
+                .find(|node| node.task.get_name() == "diamond_shared")
+                .unwrap();
+            assert_eq!(shared_node.parents.len(), 2);
+
+            dag.run(&RunStyle::PARALLEL).expect("Failed to run the DAG");
+            let report = dag.last_report().unwrap();
+            assert_eq!(report.nodes.len(), 4);
+            assert!(report
+                .nodes
+                .iter()
+                .all(|n| n.outcome == crate::scheduler::NodeOutcome::Computed));
+        }
+
+        #[test]
+        fn parameterized_tasks() {
+            /// Each day depends on the day before, down to day 0
+            #[derive(Debug)]
+            struct Daily {
+                day: u32,
+            }
+            impl Task for Daily {
+                fn get_target(&self) -> Result<Box<dyn Target>> {
+                    Ok(Box::new(FileTarget::new(
+                        "/tmp",
+                        &format!("test_dag_daily_{}.txt", self.day),
+                    )))
+                }
+
+                fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                    let mut result = HashMap::<String, Box<dyn Task>>::new();
+                    if self.day > 0 {
+                        result.insert(
+                            "previous".to_string(),
+                            Box::new(Daily { day: self.day - 1 }),
+                        );
+                    }
+                    Ok(result)
+                }
+
+                fn compute_output(&self) -> Result<Vec<u8>> {
+                    Ok(self.day.to_string().into_bytes())
+                }
+            }
+
+            // same name for every day, but different tasks and no cycle
+            let dag = DAG::new(Box::new(Daily { day: 2 })).expect("Failed to construct DAG");
+            assert_eq!(dag.nodes.len(), 3);
+            assert!(dag
+                .nodes
+                .values()
+                .all(|node| node.task.get_name() == "Daily"));
+            assert_eq!(dag.topological_order().unwrap().len(), 3);
+        }
+
+        #[test]
+        fn dependency_cycle() {
+            /// A depends on B, B on C and C on A
+            #[derive(Debug)]
+            struct Cyclic(&'static str);
+            impl Task for Cyclic {
+                fn get_name(&self) -> String {
+                    self.0.to_string()
+                }
+
+                fn get_target(&self) -> Result<Box<dyn Target>> {
+                    Ok(Box::new(FileTarget::new(
+                        "/tmp",
+                        &format!("test_dag_cycle_{}.txt", self.0),
+                    )))
+                }
+
+                fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                    let dep = match self.0 {
+                        "A" => "B",
+                        "B" => "C",
+                        _ => "A",
+                    };
+                    let mut result = HashMap::<String, Box<dyn Task>>::new();
+                    result.insert(dep.to_string(), Box::new(Cyclic(dep)));
+                    Ok(result)
+                }
+
+                fn compute_output(&self) -> Result<Vec<u8>> {
+                    Ok(Vec::new())
+                }
+            }
+
+            let err = DAG::new(Box::new(Cyclic("A"))).unwrap_err();
+            assert_eq!(err.to_string(), "Dependency cycle: A -> B -> C -> A");
+        }
+
+        #[test]
+        fn to_prometheus() {
+            use crate::scheduler::{NodeOutcome, NodeReport, RunReport};
+            use std::time::Duration;
+
+            let report = RunReport {
+                nodes: vec![
+                    NodeReport {
+                        name: "head".to_string(),
+                        outcome: NodeOutcome::Computed,
+                        start: Duration::ZERO,
+                        duration: Duration::from_millis(1500),
+                        peak_memory: None,
+                    },
+                    NodeReport {
+                        name: "leaf \"1\"".to_string(),
+                        outcome: NodeOutcome::Computed,
+                        start: Duration::ZERO,
+                        duration: Duration::from_millis(250),
+                        peak_memory: None,
+                    },
+                    NodeReport {
+                        name: "leaf2".to_string(),
+                        outcome: NodeOutcome::Failed,
+                        start: Duration::ZERO,
+                        duration: Duration::ZERO,
+                        peak_memory: None,
+                    },
+                ],
+                ..RunReport::default()
+            };
+            let metrics = report.to_prometheus();
+            let lines = metrics.lines().collect::<Vec<_>>();
+            for expected in [
+                "# TYPE rust_tasks_computed_total counter",
+                "rust_tasks_computed_total 2",
+                "rust_tasks_cached_total 0",
+                "rust_tasks_failed_total 1",
+                "rust_tasks_skipped_total 0",
+                "# TYPE rust_tasks_duration_seconds gauge",
+                "rust_tasks_duration_seconds{task=\"head\"} 1.5",
+                "rust_tasks_duration_seconds{task=\"leaf \\\"1\\\"\"} 0.25",
+                "rust_tasks_duration_seconds{task=\"leaf2\"} 0",
+            ] {
+                assert!(lines.contains(&expected), "missing {:?}", expected);
+            }
+        }
+
+        #[test]
+        fn to_chrome_trace() {
+            use crate::scheduler::{NodeOutcome, RunStyle};
+
+            let cached = Step::leaf("trace_cached");
+            let task = Step::new(
+                "trace_head",
+                vec![
+                    cached.clone(),
+                    Step::leaf("trace_leaf1"),
+                    Step::leaf("trace_leaf2"),
+                    Step::leaf("trace_leaf3"),
+                ],
+            );
+            task.recursively_delete_data().unwrap();
+            cached.run().unwrap();
+            let mut dag = DAG::new(Box::new(task)).unwrap();
+            dag.run(&RunStyle::PARALLEL).unwrap();
+            let report = dag.last_report().unwrap();
+
+            let trace: serde_json::Value = serde_json::from_str(&report.to_chrome_trace()).unwrap();
+            let events = trace.as_array().unwrap();
+            let mut names = events
+                .iter()
+                .map(|e| e["name"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>();
+            names.sort();
+            assert_eq!(names, report.names(NodeOutcome::Computed));
+            assert!(events.iter().all(|e| e["ph"] == "X"));
+
+            // events on the same track don't overlap
+            let span = |e: &serde_json::Value| {
+                let ts = e["ts"].as_u64().unwrap();
+                (ts, ts + e["dur"].as_u64().unwrap())
+            };
+            for a in events {
+                for b in events {
+                    if a != b && a["tid"] == b["tid"] {
+                        let ((a_start, a_end), (b_start, b_end)) = (span(a), span(b));
+                        assert!(a_end <= b_start || b_end <= a_start);
+                    }
+                }
+            }
+            // the head starts once its dependencies have finished
+            let start = |name: &str| {
+                events.iter().find(|e| e["name"] == name).unwrap()["ts"]
+                    .as_u64()
+                    .unwrap()
+            };
+            assert!(start("trace_head") >= start("trace_leaf1"));
+        }
+
+        #[test]
+        fn run_with_remote() {
+            use crate::scheduler::{NodeOutcome, RunStyle};
+            use std::{sync::Mutex, thread, time::Duration};
+
+            let task = Step::new(
+                "remote_head",
+                vec![
+                    Step::new("remote_mid", vec![Step::leaf("remote_leaf")]).remote(),
+                    Step::leaf("local_leaf"),
+                ],
+            );
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task)).unwrap();
+
+            // the "cluster" writes the output from another thread a little later
+            let submitted = Mutex::new(Vec::new());
+            let workers = Mutex::new(Vec::new());
+            let submit = |task: &dyn Task| {
+                let name = task.get_name();
+                submitted.lock().unwrap().push(name.clone());
+                let target = FileTarget::new("/tmp", &format!("test_dag_{}.txt", name));
+                workers.lock().unwrap().push(thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(50));
+                    target.write(format!("{} data", name).as_bytes()).unwrap();
+                }));
+                Ok(())
+            };
+            dag.run_with_remote(&RunStyle::PARALLEL, submit, Duration::from_millis(5))
+                .expect("Failed to run the DAG");
+            for worker in workers.into_inner().unwrap() {
+                worker.join().unwrap();
+            }
+
+            assert!(dag.nodes.values().all(|node| node.is_done()));
+            assert_eq!(submitted.into_inner().unwrap(), vec!["remote_mid"]);
+            let report = dag.last_report().unwrap();
+            assert_eq!(
+                report.names(NodeOutcome::Computed),
+                vec!["local_leaf", "remote_head", "remote_leaf", "remote_mid"]
+            );
+            let remote_duration = report
+                .nodes
+                .iter()
+                .find(|node| node.name == "remote_mid")
+                .unwrap()
+                .duration;
+            assert!(remote_duration >= Duration::from_millis(50));
+        }
+
+        #[cfg(target_os = "linux")]
+        #[test]
+        fn memory_measurement() {
+            use crate::scheduler::RunStyle;
+            use std::{hint::black_box, thread, time::Duration};
+
+            /// Holds a 64MB buffer for a while
+            #[derive(Debug)]
+            struct Hungry {}
+            impl Task for Hungry {
+                fn get_name(&self) -> String {
+                    "memory_hungry".to_string()
+                }
+
+                fn get_target(&self) -> Result<Box<dyn Target>> {
+                    Ok(Box::new(FileTarget::new(
+                        "/tmp",
+                        "test_dag_memory_hungry.txt",
+                    )))
+                }
+
+                fn compute_output(&self) -> Result<Vec<u8>> {
+                    let buffer = black_box(vec![1u8; 64 << 20]);
+                    thread::sleep(Duration::from_millis(30));
+                    Ok(vec![buffer[buffer.len() - 1]])
+                }
+            }
+
+            let peak_memory = |measure: bool| {
+                Hungry {}.delete_data().unwrap();
+                let mut dag = DAG::new(Box::new(Hungry {})).unwrap();
+                if measure {
+                    dag = dag.with_memory_measurement();
+                }
+                dag.run(&RunStyle::LOCAL).unwrap();
+                dag.last_report().unwrap().nodes[0].peak_memory
+            };
+            let peak = peak_memory(true).expect("memory wasn't measured");
+            assert!(peak > 0);
+            assert_eq!(peak_memory(false), None);
+        }
+
+        #[test]
+        fn run_with_pause() {
+            use crate::scheduler::RunStyle;
+            use std::{
+                sync::{
+                    atomic::{AtomicBool, Ordering},
+                    Arc,
+                },
+                thread,
+                time::Duration,
+            };
+
+            /// Pauses the run while computing
+            #[derive(Debug)]
+            struct Pauser {
+                pause: Arc<AtomicBool>,
+            }
+            impl Task for Pauser {
+                fn get_name(&self) -> String {
+                    "pause_leaf".to_string()
+                }
+
+                fn get_target(&self) -> Result<Box<dyn Target>> {
+                    Ok(Box::new(FileTarget::new("/tmp", "test_dag_pause_leaf.txt")))
+                }
+
+                fn compute_output(&self) -> Result<Vec<u8>> {
+                    self.pause.store(true, Ordering::SeqCst);
+                    Ok("pause_leaf data".as_bytes().to_vec())
+                }
+            }
+
+            #[derive(Debug)]
+            struct Head {
+                pause: Arc<AtomicBool>,
+            }
+            impl Task for Head {
+                fn get_name(&self) -> String {
+                    "pause_head".to_string()
+                }
+
+                fn get_target(&self) -> Result<Box<dyn Target>> {
+                    Ok(Box::new(FileTarget::new("/tmp", "test_dag_pause_head.txt")))
+                }
+
+                fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                    let mut result = HashMap::<String, Box<dyn Task>>::new();
+                    let leaf = Pauser {
+                        pause: self.pause.clone(),
+                    };
+                    result.insert("leaf".to_string(), Box::new(leaf));
+                    Ok(result)
+                }
+            }
+
+            let pause = Arc::new(AtomicBool::new(false));
+            let head = Head {
+                pause: pause.clone(),
+            };
+            head.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(head)).unwrap();
+
+            // once the leaf pauses the run, the head doesn't start until it's resumed
+            let resumer = {
+                let pause = pause.clone();
+                thread::spawn(move || {
+                    while !pause.load(Ordering::SeqCst) {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                    thread::sleep(Duration::from_millis(50));
+                    let leaf = FileTarget::new("/tmp", "test_dag_pause_leaf.txt");
+                    let head = FileTarget::new("/tmp", "test_dag_pause_head.txt");
+                    let halted = leaf.exists().unwrap() && !head.exists().unwrap();
+                    pause.store(false, Ordering::SeqCst);
+                    halted
+                })
+            };
+            dag.run_with_pause(&RunStyle::LOCAL, pause)
+                .expect("Failed to run the DAG");
+            assert!(resumer.join().unwrap(), "the run didn't halt while paused");
+            assert!(dag.nodes.values().all(|node| node.is_done()));
+        }
+
+        #[test]
+        fn topological_order() {
+            let dag = DAG::new(Box::new(FinalTask {})).expect("Failed to construct DAG");
+            let names = dag.task_names_in_order().unwrap();
+            assert_eq!(names.len(), 4);
+            let position = |name: &str| names.iter().position(|n| n == name).unwrap();
+            assert!(position("Dep3") < position("Dep2"));
+            assert!(position("Dep2") < position("FinalTask"));
+            assert!(position("Dep1") < position("FinalTask"));
+            assert_eq!(dag.topological_order().unwrap().len(), dag.nodes.len());
+
+            // a cycle introduced after construction is reported
+            let mut dag = DAG::new(Box::new(FinalTask {})).unwrap();
+            let id_of = |dag: &DAG, name: &str| {
+                dag.nodes
+                    .values()
+                    .find(|node| node.task.get_name() == name)
+                    .unwrap()
+                    .id
+            };
+            let (head, dep3) = (id_of(&dag, "FinalTask"), id_of(&dag, "Dep3"));
+            dag.nodes.get_mut(&dep3).unwrap().children.insert(head);
+            let err = dag.topological_order().unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Dependency cycle among: Dep2, Dep3, FinalTask"
+            );
+        }
+
+        #[test]
+        fn save_and_load_state() {
+            use crate::scheduler::Codec;
+
+            let task = Step::new(
+                "state_head",
+                vec![Step::leaf("state_leaf1"), Step::leaf("state_leaf2")],
+            );
+            task.recursively_delete_data().unwrap();
+            let done_states = |dag: &DAG| {
+                let mut states = dag
+                    .nodes
+                    .values()
+                    .map(|node| (node.task.get_name(), node.is_done()))
+                    .collect::<Vec<_>>();
+                states.sort();
+                states
+            };
+
+            let mut dag = DAG::new(Box::new(task.clone())).unwrap();
+            let leaf = dag
+                .nodes
+                .values()
+                .find(|node| node.task.get_name() == "state_leaf1")
+                .unwrap()
+                .id;
+            dag.mark_done(leaf).unwrap();
+
+            let state = FileTarget::new("/tmp", "test_dag_state.bin");
+            for codec in [Codec::Bincode, Codec::Json, Codec::MessagePack] {
+                dag.save_state(&state, codec).unwrap();
+                let mut loaded = DAG::new(Box::new(task.clone())).unwrap();
+                assert!(loaded.nodes.values().all(|node| !node.is_done()));
+                assert_eq!(loaded.load_state(&state).unwrap(), 3);
+                assert_eq!(done_states(&loaded), done_states(&dag), "{:?}", codec);
+            }
+
+            // untagged JSON is still accepted
+            let untagged = serde_json::json!({
+                "done": { Step::leaf("state_leaf2").identity(): true }
+            });
+            state.write(untagged.to_string().as_bytes()).unwrap();
+            let mut loaded = DAG::new(Box::new(task)).unwrap();
+            assert_eq!(loaded.load_state(&state).unwrap(), 1);
+            assert_eq!(
+                done_states(&loaded),
+                vec![
+                    ("state_head".to_string(), false),
+                    ("state_leaf1".to_string(), false),
+                    ("state_leaf2".to_string(), true),
+                ]
+            );
+
+            for (data, error) in [
+                (&[][..], "Empty encoded data"),
+                (&[9], "Unknown codec tag 9"),
+            ] {
+                state.write(data).unwrap();
+                let err = loaded.load_state(&state).unwrap_err();
+                assert_eq!(err.to_string(), error);
+            }
+        }
+
+        #[test]
+        fn dot_labels_use_description() {
+            #[derive(Debug)]
+            struct Described {
+                name: &'static str,
+                description: Option<&'static str>,
+                deps: Vec<&'static str>,
+            }
+            impl Task for Described {
+                fn get_name(&self) -> String {
+                    self.name.to_string()
+                }
+
+                fn description(&self) -> Option<String> {
+                    self.description.map(|d| d.to_string())
+                }
+
+                fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                    let mut result = HashMap::<String, Box<dyn Task>>::new();
+                    for dep in &self.deps {
+                        let task = Described {
+                            name: dep,
+                            description: None,
+                            deps: Vec::new(),
+                        };
+                        result.insert(dep.to_string(), Box::new(task));
+                    }
+                    Ok(result)
+                }
+            }
+
+            let task = Described {
+                name: "described_head",
+                description: Some("Daily \"sales\" report"),
+                deps: vec!["described_leaf"],
+            };
+            let dag = DAG::new(Box::new(task)).unwrap();
+            let dot = dag.to_dot();
+            assert!(dot.contains("label=\"Daily \\\"sales\\\" report\""));
+            assert!(!dot.contains("described_head"));
+            assert!(dot.contains("label=\"described_leaf\""));
+
+            // Debug shows the description next to the name, and nothing without one
+            for node in dag.nodes.values() {
+                let debug = format!("{:?}", node);
+                if node.task.get_name() == "described_head" {
+                    assert!(debug.contains(
+                        "task: \"described_head\", description: \"Daily \\\"sales\\\" report\""
+                    ));
+                } else {
+                    assert!(!debug.contains("description"));
+                }
+            }
+        }
+
+        #[test]
+        fn node_statuses() {
+            let task = Step::new(
+                "statuses_head",
+                vec![Step::leaf("statuses_b"), Step::leaf("statuses_a")],
+            );
+            task.recursively_delete_data().unwrap();
+            let dag = DAG::new(Box::new(task.clone())).unwrap();
+            Step::leaf("statuses_b")
+                .get_target()
+                .unwrap()
+                .write(b"statuses_b data")
+                .unwrap();
+            // targets are checked when asked, not when the DAG was built
+            assert_eq!(
+                dag.node_statuses().unwrap(),
+                vec![
+                    ("statuses_a".to_string(), false),
+                    ("statuses_b".to_string(), true),
+                    ("statuses_head".to_string(), false),
+                ]
+            );
+        }
+
+        #[test]
+        fn dry_run() {
+            use crate::scheduler::DryRunEntry;
+
+            let cached = Step::leaf("dry_run_cached");
+            let missing = Step::leaf("dry_run_missing");
+            let task = Step::new("dry_run_head", vec![cached.clone(), missing.clone()]);
+            task.recursively_delete_data().unwrap();
+            cached.run().unwrap();
+            let dag = DAG::new(Box::new(task)).unwrap();
+            let report = dag.dry_run().unwrap();
+            let entry = |name: &str, cached| DryRunEntry {
+                name: name.to_string(),
+                cached,
+            };
+            assert_eq!(
+                report.tasks,
+                vec![
+                    entry("dry_run_cached", true),
+                    entry("dry_run_missing", false),
+                    entry("dry_run_head", false),
+                ]
+            );
+            assert_eq!(report.to_compute(), vec!["dry_run_missing", "dry_run_head"]);
+            assert_eq!(
+                report.to_string(),
+                "skip     dry_run_cached\ncompute  dry_run_missing\ncompute  dry_run_head\n"
+            );
+            // nothing was computed
+            assert!(!missing.get_target().unwrap().exists().unwrap());
+        }
+
+        #[test]
+        fn total_cached_bytes() {
+            let cached = Step::leaf("cached_bytes_leaf1");
+            let task = Step::new(
+                "cached_bytes_head",
+                vec![cached.clone(), Step::leaf("cached_bytes_leaf2")],
+            );
+            task.recursively_delete_data().unwrap();
+            let dag = DAG::new(Box::new(task)).unwrap();
+            assert_eq!(dag.total_cached_bytes().unwrap(), 0);
+
+            cached.get_target().unwrap().write(&[0; 1000]).unwrap();
+            assert_eq!(dag.total_cached_bytes().unwrap(), 1000);
+            Step::leaf("cached_bytes_leaf2")
+                .get_target()
+                .unwrap()
+                .write(&[0; 234])
+                .unwrap();
+            assert_eq!(dag.total_cached_bytes().unwrap(), 1234);
+        }
+
+        #[test]
+        fn cache_status() {
+            use crate::scheduler::CacheStatus;
+
+            let cached = Step::leaf("cache_status_cached").weighted(2.0);
+            let task = Step::new(
+                "cache_status_head",
+                vec![
+                    cached.clone(),
+                    Step::leaf("cache_status_missing1").weighted(3.0),
+                    Step::leaf("cache_status_missing2").weighted(0.5),
+                ],
+            );
+            task.recursively_delete_data().unwrap();
+            cached.run().unwrap();
+            let dag = DAG::new(Box::new(task)).unwrap();
+            let status = dag.cache_status();
+            assert_eq!(
+                status,
+                CacheStatus {
+                    cached: 1,
+                    missing: 3,
+                    cached_weight: 2.0,
+                    missing_weight: 4.5,
+                }
+            );
+            assert_eq!(status.hit_ratio(), 2.0 / 6.5);
+        }
+
+        #[test]
+        fn status_summary() {
+            use crate::scheduler::{NodeOutcome, RunStyle, TaskStatus};
+
+            let cached = Step::leaf("status_cached");
+            let task = Step::new(
+                "status_head",
+                vec![cached.clone(), Step::failing("status_failing")],
+            );
+            task.recursively_delete_data().unwrap();
+            cached.run().unwrap();
+            let mut dag = DAG::new(Box::new(task)).unwrap();
+            assert_eq!(
+                dag.status_summary(),
+                HashMap::from([(TaskStatus::Skipped, 1), (TaskStatus::Pending, 2)])
+            );
+
+            assert!(dag.run(&RunStyle::LOCAL).is_err());
+            let status = |name: &str| {
+                dag.nodes
+                    .values()
+                    .find(|node| node.task.get_name() == name)
+                    .unwrap()
+                    .status
+            };
+            assert_eq!(status("status_cached"), TaskStatus::Skipped);
+            assert_eq!(status("status_failing"), TaskStatus::Failed);
+            // blocked by the failed dependency, which the report tells apart from cached
+            assert_eq!(status("status_head"), TaskStatus::Pending);
+            let report = dag.last_report().unwrap();
+            assert_eq!(report.names(NodeOutcome::Blocked), vec!["status_head"]);
+            assert_eq!(report.names(NodeOutcome::Cached), vec!["status_cached"]);
+
+            let recomputed = Step::leaf("status_recomputed");
+            recomputed.delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(recomputed)).unwrap();
+            dag.run(&RunStyle::LOCAL).unwrap();
+            assert_eq!(
+                dag.status_summary(),
+                HashMap::from([(TaskStatus::Completed, 1)])
+            );
+            assert!(dag.nodes.values().all(|node| node.is_done()));
+        }
+
+        #[test]
+        fn to_dot() {
+            let task: Box<dyn Task> = Box::new(FinalTask {});
+            task.recursively_delete_data()
+                .expect("Failed to delete task and dependent task data");
+            Dep3 {}.run().expect("Failed to run Dep3");
+            let dag = DAG::new(task).expect("Failed to construct DAG");
+            let dot = dag.to_dot();
+            assert!(dot.starts_with("digraph DAG {\n"));
+            assert!(dot.ends_with("}\n"));
+            let node_lines = dot
+                .lines()
+                .filter(|line| line.contains("[label="))
+                .collect::<Vec<_>>();
+            assert_eq!(node_lines.len(), 4);
+            assert_eq!(dot.matches(" -> ").count(), 3);
+            assert!(node_lines
+                .iter()
+                .any(|line| line.contains("label=\"Dep3\"") && line.contains("fillcolor=green")));
+            assert!(node_lines
+                .iter()
+                .any(|line| line.contains("label=\"Dep2\"") && line.contains("fillcolor=grey")));
+
+            // names that would break the syntax are escaped
+            let task = Step::new("dot_head", vec![Step::leaf("dot \"quoted\"\nname\\\x07")]);
+            let dot = DAG::new(Box::new(task)).unwrap().to_dot();
+            assert_eq!(dot.lines().count(), 5);
+            assert!(dot.contains("label=\"dot \\\"quoted\\\"\\nname\\\\\\\\u{7}\""));
+            for line in dot.lines() {
+                // every quote outside an escape opens or closes a string
+                let unescaped = line.replace("\\\\", "").replace("\\\"", "");
+                assert_eq!(unescaped.matches('"').count() % 2, 0, "{}", line);
+            }
+        }
+
+        #[test]
+        fn manual_stepping() {
+            let task = Step::new(
+                "stepping_head",
+                vec![
+                    Step::new("stepping_mid", vec![Step::leaf("stepping_leaf1")]),
+                    Step::leaf("stepping_leaf2"),
+                ],
+            );
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task)).expect("Failed to construct DAG");
+
+            let mut frontiers = Vec::new();
+            loop {
+                let ready = dag.ready();
+                if ready.is_empty() {
+                    break;
+                }
+                for id in &ready {
+                    dag.mark_running(*id).unwrap();
+                }
+                // started nodes aren't handed out again
+                assert!(dag.ready().is_empty());
+                let mut names = Vec::new();
+                for id in ready {
+                    dag.nodes[&id].task.run_no_deps().unwrap();
+                    names.push(dag.nodes[&id].task.get_name());
+                    dag.mark_done(id).unwrap();
+                }
+                names.sort();
+                frontiers.push(names);
+            }
+            assert_eq!(
+                frontiers,
+                vec![
+                    vec!["stepping_leaf1", "stepping_leaf2"],
+                    vec!["stepping_mid"],
+                    vec!["stepping_head"],
+                ]
+            );
+            assert!(dag.nodes.values().all(|node| node.is_done()));
+        }
+
+        #[test]
+        fn manual_stepping_failure() {
+            let task = Step::new(
+                "stepping_failure_head",
+                vec![
+                    Step::failing("stepping_failure_leaf1"),
+                    Step::leaf("stepping_failure_leaf2"),
+                ],
+            );
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(Box::new(task)).expect("Failed to construct DAG");
+
+            while let Some(id) = dag.ready().pop() {
+                match dag.nodes[&id].task.run_no_deps() {
+                    Ok(()) => dag.mark_done(id).unwrap(),
+                    Err(_) => dag.mark_failed(id).unwrap(),
+                }
+            }
+            let mut done = dag
+                .nodes
+                .values()
+                .filter(|node| node.is_done())
+                .map(|node| node.task.get_name())
+                .collect::<Vec<_>>();
+            done.sort();
+            assert_eq!(done, vec!["stepping_failure_leaf2"]);
         }
     }
 }
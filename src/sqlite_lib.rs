@@ -0,0 +1,216 @@
+/// SQLite backed targets sharing an r2d2 connection pool.
+///
+/// Under the PARALLEL run style many tasks read and write the same database at
+/// once. With a single connection per target (and the default rollback journal)
+/// this quickly fails with "database is locked". The recommended setup is to
+/// build one pool with [`wal_pool`], which puts the database in WAL mode (readers
+/// don't block the writer) and sets a busy timeout (writers wait for each other
+/// instead of failing), and to hand a clone of the pool to every target.
+pub mod sqlite {
+    use std::{path::Path, time::Duration};
+
+    use anyhow::{anyhow, Result};
+    use r2d2::Pool;
+    use r2d2_sqlite::SqliteConnectionManager;
+    use rusqlite::{params, OptionalExtension};
+
+    use crate::tasks::Target;
+
+    /// How long a connection waits for a lock held by another writer before failing
+    const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Build a connection pool for the database file at path, with WAL mode and a
+    /// busy timeout enabled on every connection
+    pub fn wal_pool<P: AsRef<Path>>(
+        path: P,
+        max_size: u32,
+    ) -> Result<Pool<SqliteConnectionManager>> {
+        let manager = SqliteConnectionManager::file(path).with_init(|conn| {
+            conn.busy_timeout(BUSY_TIMEOUT)?;
+            conn.pragma_update(None, "journal_mode", "WAL")
+        });
+        Ok(Pool::builder().max_size(max_size).build(manager)?)
+    }
+
+    /// SqliteTarget stores the target bytes as a blob in a key/data table. Many
+    /// targets can share a table (and a pool), each using a different key.
+    pub struct SqliteTarget {
+        pool: Pool<SqliteConnectionManager>,
+        table: String,
+        key: String,
+    }
+
+    impl SqliteTarget {
+        /// Create the target, creating the table if it doesn't exist. The table name
+        /// can't be a bound parameter so it's restricted to alphanumerics and underscores.
+        pub fn new(pool: Pool<SqliteConnectionManager>, table: &str, key: &str) -> Result<Self> {
+            if table.is_empty() || !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(anyhow!("Invalid table name {:?}", table));
+            }
+            pool.get()?.execute(
+                &format!(
+                    "create table if not exists {} (key text primary key, data blob not null)",
+                    table
+                ),
+                (),
+            )?;
+            Ok(Self {
+                pool,
+                table: table.to_string(),
+                key: key.to_string(),
+            })
+        }
+    }
+
+    impl Target for SqliteTarget {
+        fn read(&self) -> Result<Vec<u8>> {
+            let conn = self.pool.get()?;
+            let data = conn
+                .query_row(
+                    &format!("select data from {} where key = ?1", self.table),
+                    params![self.key],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            data.ok_or_else(|| anyhow!("No data for key {:?} in {}", self.key, self.table))
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            self.pool.get()?.execute(
+                &format!(
+                    "insert or replace into {} (key, data) values (?1, ?2)",
+                    self.table
+                ),
+                params![self.key, s],
+            )?;
+            Ok(())
+        }
+
+        fn delete(&self) -> Result<()> {
+            self.pool.get()?.execute(
+                &format!("delete from {} where key = ?1", self.table),
+                params![self.key],
+            )?;
+            Ok(())
+        }
+
+        fn exists(&self) -> Result<bool> {
+            let conn = self.pool.get()?;
+            let found = conn
+                .query_row(
+                    &format!("select 1 from {} where key = ?1", self.table),
+                    params![self.key],
+                    |_| Ok(()),
+                )
+                .optional()?;
+            Ok(found.is_some())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::{collections::HashMap, fmt};
+
+        use anyhow::Result;
+        use r2d2::Pool;
+        use r2d2_sqlite::SqliteConnectionManager;
+
+        use super::{wal_pool, SqliteTarget};
+        use crate::{
+            scheduler::{NodeOutcome, RunStyle, DAG},
+            tasks::{Target, Task},
+        };
+
+        struct Writer {
+            pool: Pool<SqliteConnectionManager>,
+            key: String,
+        }
+
+        impl fmt::Debug for Writer {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Writer").field("key", &self.key).finish()
+            }
+        }
+
+        impl Task for Writer {
+            fn get_name(&self) -> String {
+                self.key.clone()
+            }
+
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(SqliteTarget::new(
+                    self.pool.clone(),
+                    "outputs",
+                    &self.key,
+                )?))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok(self.key.repeat(1000).into_bytes())
+            }
+        }
+
+        #[derive(Debug)]
+        struct AllWriters {
+            writers: usize,
+            pool: Pool<SqliteConnectionManager>,
+        }
+
+        impl Task for AllWriters {
+            fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                let mut result = HashMap::<String, Box<dyn Task>>::new();
+                for i in 0..self.writers {
+                    let key = format!("writer_{}", i);
+                    result.insert(
+                        key.clone(),
+                        Box::new(Writer {
+                            pool: self.pool.clone(),
+                            key,
+                        }),
+                    );
+                }
+                Ok(result)
+            }
+        }
+
+        #[test]
+        fn round_trip() {
+            let pool = wal_pool("/tmp/test_sqlite_target_round_trip.db", 2).unwrap();
+            let target = SqliteTarget::new(pool, "outputs", "key").unwrap();
+            target.delete().unwrap();
+            assert!(!target.exists().unwrap());
+            assert!(target.read().is_err());
+            target.write("some data".as_bytes()).unwrap();
+            assert!(target.exists().unwrap());
+            assert_eq!(target.read().unwrap(), "some data".as_bytes().to_vec());
+            target.write("new data".as_bytes()).unwrap();
+            assert_eq!(target.read().unwrap(), "new data".as_bytes().to_vec());
+        }
+
+        #[test]
+        fn invalid_table_name() {
+            let pool = wal_pool("/tmp/test_sqlite_target_round_trip.db", 1).unwrap();
+            assert!(SqliteTarget::new(pool, "outputs; drop table x", "key").is_err());
+        }
+
+        #[test]
+        fn parallel_writers() {
+            let pool = wal_pool("/tmp/test_sqlite_target_parallel.db", 8).unwrap();
+            let task: Box<dyn Task> = Box::new(AllWriters {
+                writers: 32,
+                pool: pool.clone(),
+            });
+            task.recursively_delete_data().unwrap();
+            let mut dag = DAG::new(task).expect("Failed to construct DAG");
+            dag.run(&RunStyle::PARALLEL).expect("Failed to run the DAG");
+
+            let report = dag.last_report().unwrap();
+            assert!(report.names(NodeOutcome::Failed).is_empty());
+            assert_eq!(report.names(NodeOutcome::Computed).len(), 33);
+            let task = AllWriters { writers: 32, pool };
+            for (_, target) in task.get_dep_targets().unwrap() {
+                assert!(target.exists().unwrap());
+            }
+        }
+    }
+}
@@ -0,0 +1,1368 @@
+/// Additional Target implementations, including backends that are enabled with
+/// cargo features.
+pub mod targets {
+    use std::{
+        collections::HashMap,
+        fs,
+        io::{Read, Write},
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use anyhow::{anyhow, Result};
+    use flate2::{
+        read::{GzDecoder, GzEncoder},
+        Compression,
+    };
+    use log::warn;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        resilience::{CircuitBreaker, RetryPolicy},
+        tasks::{sha256_hex, FileTarget, Target},
+    };
+
+    #[cfg(feature = "avro")]
+    pub use crate::avro_lib::avro;
+    #[cfg(feature = "encrypted")]
+    pub use crate::encrypted_lib::encrypted;
+    #[cfg(all(unix, feature = "fifo"))]
+    pub use crate::fifo_lib::fifo;
+    #[cfg(feature = "git")]
+    pub use crate::git_lib::git;
+    #[cfg(feature = "http")]
+    pub use crate::http_lib::http;
+    #[cfg(feature = "mmap")]
+    pub use crate::mmap_lib::mmap;
+    #[cfg(feature = "postgres")]
+    pub use crate::postgres_lib::postgres;
+    #[cfg(feature = "s3")]
+    pub use crate::s3_lib::s3;
+    #[cfg(feature = "sqlite")]
+    pub use crate::sqlite_lib::sqlite;
+
+    /// Pure transformation of target bytes
+    pub type ByteMap = Box<dyn Fn(Vec<u8>) -> Result<Vec<u8>> + Send + Sync>;
+
+    /// MappedTarget transforms the bytes of an inner target on read (and optionally on
+    /// write), e.g., to upgrade caches written in an old format. exists and delete are
+    /// delegated to the inner target.
+    pub struct MappedTarget<T: Target> {
+        inner: T,
+        read_map: ByteMap,
+        write_map: Option<ByteMap>,
+    }
+
+    impl<T: Target> MappedTarget<T> {
+        pub fn new<F>(inner: T, read_map: F) -> Self
+        where
+            F: Fn(Vec<u8>) -> Result<Vec<u8>> + Send + Sync + 'static,
+        {
+            MappedTarget {
+                inner,
+                read_map: Box::new(read_map),
+                write_map: None,
+            }
+        }
+
+        /// Also transform bytes before they are written to the inner target
+        pub fn with_write_map<F>(mut self, write_map: F) -> Self
+        where
+            F: Fn(Vec<u8>) -> Result<Vec<u8>> + Send + Sync + 'static,
+        {
+            self.write_map = Some(Box::new(write_map));
+            self
+        }
+
+        pub fn inner(&self) -> &T {
+            &self.inner
+        }
+    }
+
+    impl<T: Target> Target for MappedTarget<T> {
+        fn read(&self) -> Result<Vec<u8>> {
+            (self.read_map)(self.inner.read()?)
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            match &self.write_map {
+                Some(write_map) => self.inner.write(&write_map(s.to_vec())?),
+                None => self.inner.write(s),
+            }
+        }
+
+        fn delete(&self) -> Result<()> {
+            self.inner.delete()
+        }
+
+        fn exists(&self) -> Result<bool> {
+            self.inner.exists()
+        }
+
+        fn version(&self) -> Result<Option<String>> {
+            self.inner.version()
+        }
+
+        fn last_modified(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+            self.inner.last_modified()
+        }
+
+        fn size(&self) -> Result<Option<u64>> {
+            self.inner.size()
+        }
+
+        fn sidecar(&self, suffix: &str) -> Option<Box<dyn Target>> {
+            self.inner.sidecar(suffix)
+        }
+
+        fn uri(&self) -> Option<String> {
+            self.inner.uri()
+        }
+    }
+
+    /// GzipTarget gzip-compresses data written to an inner target and decompresses it on
+    /// read. exists and delete are delegated to the inner target unchanged.
+    pub struct GzipTarget<T: Target> {
+        inner: T,
+        level: Compression,
+    }
+
+    impl<T: Target> GzipTarget<T> {
+        /// Compress at flate2's default level (6)
+        pub fn new(inner: T) -> Self {
+            GzipTarget {
+                inner,
+                level: Compression::default(),
+            }
+        }
+
+        /// Compression level from 0 (none) to 9 (best), failing for higher levels
+        pub fn with_level(mut self, level: u32) -> Result<Self> {
+            if level > 9 {
+                return Err(anyhow!("Invalid gzip level {}: expected 0 to 9", level));
+            }
+            self.level = Compression::new(level);
+            Ok(self)
+        }
+
+        pub fn inner(&self) -> &T {
+            &self.inner
+        }
+    }
+
+    impl<T: Target> Target for GzipTarget<T> {
+        fn read(&self) -> Result<Vec<u8>> {
+            let mut data = Vec::new();
+            GzDecoder::new(&self.inner.read()?[..]).read_to_end(&mut data)?;
+            Ok(data)
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), self.level);
+            encoder.write_all(s)?;
+            self.inner.write(&encoder.finish()?)
+        }
+
+        fn read_stream(&self) -> Result<Box<dyn Read>> {
+            Ok(Box::new(GzDecoder::new(self.inner.read_stream()?)))
+        }
+
+        fn write_stream(&self, r: &mut dyn Read) -> Result<()> {
+            self.inner.write_stream(&mut GzEncoder::new(r, self.level))
+        }
+
+        fn delete(&self) -> Result<()> {
+            self.inner.delete()
+        }
+
+        fn exists(&self) -> Result<bool> {
+            self.inner.exists()
+        }
+
+        fn version(&self) -> Result<Option<String>> {
+            self.inner.version()
+        }
+
+        fn last_modified(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+            self.inner.last_modified()
+        }
+
+        fn size(&self) -> Result<Option<u64>> {
+            self.inner.size()
+        }
+
+        fn sidecar(&self, suffix: &str) -> Option<Box<dyn Target>> {
+            self.inner.sidecar(suffix)
+        }
+
+        fn uri(&self) -> Option<String> {
+            self.inner.uri()
+        }
+    }
+
+    /// Consumer of the bytes written to a ClosureTarget
+    type ByteSink = Box<dyn Fn(&[u8]) -> Result<()> + Send + Sync>;
+
+    /// ClosureTarget implements Target with a closure per operation, for one-off or test
+    /// backends that don't warrant a struct of their own
+    pub struct ClosureTarget {
+        read: Box<dyn Fn() -> Result<Vec<u8>> + Send + Sync>,
+        write: ByteSink,
+        delete: Box<dyn Fn() -> Result<()> + Send + Sync>,
+        exists: Box<dyn Fn() -> Result<bool> + Send + Sync>,
+    }
+
+    impl ClosureTarget {
+        pub fn new<R, W, D, E>(read: R, write: W, delete: D, exists: E) -> Self
+        where
+            R: Fn() -> Result<Vec<u8>> + Send + Sync + 'static,
+            W: Fn(&[u8]) -> Result<()> + Send + Sync + 'static,
+            D: Fn() -> Result<()> + Send + Sync + 'static,
+            E: Fn() -> Result<bool> + Send + Sync + 'static,
+        {
+            ClosureTarget {
+                read: Box::new(read),
+                write: Box::new(write),
+                delete: Box::new(delete),
+                exists: Box::new(exists),
+            }
+        }
+    }
+
+    impl Target for ClosureTarget {
+        fn read(&self) -> Result<Vec<u8>> {
+            (self.read)()
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            (self.write)(s)
+        }
+
+        fn delete(&self) -> Result<()> {
+            (self.delete)()
+        }
+
+        fn exists(&self) -> Result<bool> {
+            (self.exists)()
+        }
+    }
+
+    /// CachedTarget is a two-tier read-through cache, e.g., a local file in front of a
+    /// remote store. Reads come from the fast tier if it has the data, otherwise from the
+    /// slow tier, populating the fast one. Writes and deletes go to both tiers. The slow
+    /// tier is authoritative for versions and sidecars.
+    pub struct CachedTarget {
+        pub fast: Box<dyn Target>,
+        pub slow: Box<dyn Target>,
+    }
+
+    impl CachedTarget {
+        pub fn new(fast: Box<dyn Target>, slow: Box<dyn Target>) -> Self {
+            CachedTarget { fast, slow }
+        }
+    }
+
+    impl Target for CachedTarget {
+        fn read(&self) -> Result<Vec<u8>> {
+            if self.fast.exists()? {
+                return self.fast.read();
+            }
+            let data = self.slow.read()?;
+            self.fast.write(&data)?;
+            Ok(data)
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            self.slow.write(s)?;
+            self.fast.write(s)
+        }
+
+        fn delete(&self) -> Result<()> {
+            self.fast.delete()?;
+            self.slow.delete()
+        }
+
+        fn exists(&self) -> Result<bool> {
+            Ok(self.fast.exists()? || self.slow.exists()?)
+        }
+
+        fn version(&self) -> Result<Option<String>> {
+            self.slow.version()
+        }
+
+        fn last_modified(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+            self.slow.last_modified()
+        }
+
+        fn size(&self) -> Result<Option<u64>> {
+            self.slow.size()
+        }
+
+        fn sidecar(&self, suffix: &str) -> Option<Box<dyn Target>> {
+            self.slow.sidecar(suffix)
+        }
+
+        fn uri(&self) -> Option<String> {
+            self.slow.uri()
+        }
+    }
+
+    /// Backend of a SizeRoutingTarget
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Route {
+        Small,
+        Large,
+    }
+
+    /// SizeRoutingTarget writes payloads of at most threshold bytes to the small target
+    /// (e.g., a database row) and bigger ones to the large target (e.g., a file). Only
+    /// one backend holds the data: a write removes it from the other one. The backend
+    /// holding the data is remembered, and looked up (small first) when it isn't known
+    /// yet, e.g., in a new process.
+    pub struct SizeRoutingTarget {
+        pub threshold: usize,
+        pub small: Box<dyn Target>,
+        pub large: Box<dyn Target>,
+        route: Mutex<Option<Route>>,
+    }
+
+    impl SizeRoutingTarget {
+        pub fn new(threshold: usize, small: Box<dyn Target>, large: Box<dyn Target>) -> Self {
+            SizeRoutingTarget {
+                threshold,
+                small,
+                large,
+                route: Mutex::new(None),
+            }
+        }
+
+        fn backend(&self, route: Route) -> &dyn Target {
+            match route {
+                Route::Small => self.small.as_ref(),
+                Route::Large => self.large.as_ref(),
+            }
+        }
+
+        // Backend holding the data, if any
+        fn current(&self) -> Result<Option<Route>> {
+            let mut route = self.route.lock().unwrap();
+            if route.is_none() {
+                if self.small.exists()? {
+                    *route = Some(Route::Small);
+                } else if self.large.exists()? {
+                    *route = Some(Route::Large);
+                }
+            }
+            Ok(*route)
+        }
+    }
+
+    impl Target for SizeRoutingTarget {
+        fn read(&self) -> Result<Vec<u8>> {
+            match self.current()? {
+                Some(route) => self.backend(route).read(),
+                None => Err(anyhow!("No data in either backend")),
+            }
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            let (route, other) = if s.len() <= self.threshold {
+                (Route::Small, Route::Large)
+            } else {
+                (Route::Large, Route::Small)
+            };
+            self.backend(route).write(s)?;
+            self.backend(other).delete()?;
+            *self.route.lock().unwrap() = Some(route);
+            Ok(())
+        }
+
+        fn delete(&self) -> Result<()> {
+            self.small.delete()?;
+            self.large.delete()?;
+            *self.route.lock().unwrap() = None;
+            Ok(())
+        }
+
+        fn exists(&self) -> Result<bool> {
+            match self.current()? {
+                Some(route) => self.backend(route).exists(),
+                None => Ok(false),
+            }
+        }
+
+        fn version(&self) -> Result<Option<String>> {
+            match self.current()? {
+                Some(route) => self.backend(route).version(),
+                None => Ok(None),
+            }
+        }
+
+        fn last_modified(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+            match self.current()? {
+                Some(route) => self.backend(route).last_modified(),
+                None => Ok(None),
+            }
+        }
+
+        fn size(&self) -> Result<Option<u64>> {
+            match self.current()? {
+                Some(route) => self.backend(route).size(),
+                None => Ok(None),
+            }
+        }
+
+        /// Sidecars of the backend holding the data (the large one if there's no data)
+        fn sidecar(&self, suffix: &str) -> Option<Box<dyn Target>> {
+            let route = self.current().ok().flatten().unwrap_or(Route::Large);
+            self.backend(route).sidecar(suffix)
+        }
+    }
+
+    /// How many targets of a ReplicatedTarget or MultiTarget must have the data for it
+    /// to exist
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ReplicaQuorum {
+        /// At least one of them
+        Any,
+        /// More than half of them
+        Majority,
+        All,
+    }
+
+    impl ReplicaQuorum {
+        // Do enough of the targets have the data?
+        fn is_met(self, targets: &[Box<dyn Target>]) -> bool {
+            let existing = targets
+                .iter()
+                .filter(|target| target.exists().unwrap_or(false))
+                .count();
+            match self {
+                ReplicaQuorum::Any => existing > 0,
+                ReplicaQuorum::Majority => existing * 2 > targets.len(),
+                ReplicaQuorum::All => !targets.is_empty() && existing == targets.len(),
+            }
+        }
+    }
+
+    /// Run f on every target, failing if any of them failed
+    fn on_all(
+        targets: &[Box<dyn Target>],
+        action: &str,
+        f: impl Fn(&dyn Target) -> Result<()>,
+    ) -> Result<()> {
+        let mut failed = 0;
+        for (i, target) in targets.iter().enumerate() {
+            if let Err(e) = f(target.as_ref()) {
+                warn!("Target {} failed to {}: {:?}", i, action, e);
+                failed += 1;
+            }
+        }
+        if failed > 0 {
+            return Err(anyhow!(
+                "{} of {} targets failed to {}",
+                failed,
+                targets.len(),
+                action
+            ));
+        }
+        Ok(())
+    }
+
+    /// ReplicatedTarget spreads reads of a read-heavy output over several replicas
+    /// (e.g., one per region). Writes and deletes go to every replica. Reads take the
+    /// replicas in round-robin order, failing over to the next one when a read fails.
+    /// A replica that errors in exists counts as not having the data.
+    pub struct ReplicatedTarget {
+        pub replicas: Vec<Box<dyn Target>>,
+        quorum: ReplicaQuorum,
+        next: AtomicUsize,
+    }
+
+    impl ReplicatedTarget {
+        /// exists requires all replicas to have the data, see with_quorum
+        pub fn new(replicas: Vec<Box<dyn Target>>) -> Self {
+            ReplicatedTarget {
+                replicas,
+                quorum: ReplicaQuorum::All,
+                next: AtomicUsize::new(0),
+            }
+        }
+
+        pub fn with_quorum(mut self, quorum: ReplicaQuorum) -> Self {
+            self.quorum = quorum;
+            self
+        }
+    }
+
+    impl Target for ReplicatedTarget {
+        fn read(&self) -> Result<Vec<u8>> {
+            let count = self.replicas.len();
+            let start = self.next.fetch_add(1, Ordering::Relaxed);
+            let mut last_err = anyhow!("No replicas");
+            for i in (start..start + count).map(|i| i % count) {
+                match self.replicas[i].read() {
+                    Ok(data) => return Ok(data),
+                    Err(e) => {
+                        warn!("Replica {} failed to read, trying the next one: {:?}", i, e);
+                        last_err = e;
+                    }
+                }
+            }
+            Err(last_err)
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            on_all(&self.replicas, "write", |replica| replica.write(s))
+        }
+
+        fn delete(&self) -> Result<()> {
+            on_all(&self.replicas, "delete", |replica| replica.delete())
+        }
+
+        fn exists(&self) -> Result<bool> {
+            Ok(self.quorum.is_met(&self.replicas))
+        }
+    }
+
+    /// MultiTarget fans output out to several targets for redundancy, e.g., local disk
+    /// and S3. Writes and deletes go to every target (failing if any of them fails).
+    /// Reads are from the first target, in the order given, that exists, so put the
+    /// cheapest to read first. A target that errors in exists counts as not having the
+    /// data.
+    pub struct MultiTarget {
+        pub targets: Vec<Box<dyn Target>>,
+        quorum: ReplicaQuorum,
+    }
+
+    impl MultiTarget {
+        /// exists requires all targets to have the data, see with_quorum
+        pub fn new(targets: Vec<Box<dyn Target>>) -> Self {
+            MultiTarget {
+                targets,
+                quorum: ReplicaQuorum::All,
+            }
+        }
+
+        pub fn with_quorum(mut self, quorum: ReplicaQuorum) -> Self {
+            self.quorum = quorum;
+            self
+        }
+    }
+
+    impl Target for MultiTarget {
+        fn read(&self) -> Result<Vec<u8>> {
+            for target in &self.targets {
+                if target.exists().unwrap_or(false) {
+                    return target.read();
+                }
+            }
+            Err(anyhow!("None of the {} targets exist", self.targets.len()))
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            on_all(&self.targets, "write", |target| target.write(s))
+        }
+
+        fn delete(&self) -> Result<()> {
+            on_all(&self.targets, "delete", |target| target.delete())
+        }
+
+        fn exists(&self) -> Result<bool> {
+            Ok(self.quorum.is_met(&self.targets))
+        }
+    }
+
+    /// Number of hex digits of the filename hash used to name the shard directory (so
+    /// 256 shards)
+    const SHARD_PREFIX_LEN: usize = 2;
+
+    /// ShardedFileTarget places the file in a subdirectory of the cache directory named
+    /// after a prefix of the SHA-256 of the filename, i.e., cache_dir/<hash prefix>/name,
+    /// to keep the number of files per directory manageable in huge pipelines. The
+    /// shard directory is created on write.
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct ShardedFileTarget {
+        file_target: FileTarget,
+    }
+
+    impl ShardedFileTarget {
+        pub fn new(cache_dir: &str, local_filename: &str) -> Self {
+            let shard = &sha256_hex(local_filename.as_bytes())[..SHARD_PREFIX_LEN];
+            let shard_dir = PathBuf::from(cache_dir).join(shard);
+            ShardedFileTarget {
+                file_target: FileTarget::new(&shard_dir.to_string_lossy(), local_filename),
+            }
+        }
+
+        /// Cache full filename, including the shard directory
+        pub fn filename(&self) -> PathBuf {
+            self.file_target.filename()
+        }
+    }
+
+    impl Target for ShardedFileTarget {
+        fn read(&self) -> Result<Vec<u8>> {
+            self.file_target.read()
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            fs::create_dir_all(&self.file_target.cache_dir)?;
+            self.file_target.write(s)
+        }
+
+        fn read_tail(&self, n: usize) -> Result<Vec<u8>> {
+            self.file_target.read_tail(n)
+        }
+
+        fn read_stream(&self) -> Result<Box<dyn Read>> {
+            self.file_target.read_stream()
+        }
+
+        fn write_stream(&self, r: &mut dyn Read) -> Result<()> {
+            fs::create_dir_all(&self.file_target.cache_dir)?;
+            self.file_target.write_stream(r)
+        }
+
+        fn delete(&self) -> Result<()> {
+            self.file_target.delete()
+        }
+
+        fn exists(&self) -> Result<bool> {
+            self.file_target.exists()
+        }
+
+        fn version(&self) -> Result<Option<String>> {
+            self.file_target.version()
+        }
+
+        fn last_modified(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+            self.file_target.last_modified()
+        }
+
+        fn size(&self) -> Result<Option<u64>> {
+            self.file_target.size()
+        }
+
+        /// Sidecars are in the same shard directory
+        fn sidecar(&self, suffix: &str) -> Option<Box<dyn Target>> {
+            self.file_target.sidecar(suffix)
+        }
+
+        fn uri(&self) -> Option<String> {
+            self.file_target.uri()
+        }
+    }
+
+    /// Serializes updates of CasTarget indexes within the process
+    static CAS_INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Key to hash index of a content-addressed store, with the number of keys
+    /// referencing each blob
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    struct CasIndex {
+        keys: HashMap<String, String>,
+        refs: HashMap<String, usize>,
+    }
+
+    /// CasTarget stores data in a content-addressed store: the payload is written to
+    /// store_dir/blobs/<SHA-256 of the data> and store_dir/index.json maps keys to
+    /// hashes, so identical outputs of different tasks are stored once. Blobs are
+    /// reference counted and removed when no key points at them any more. Index
+    /// updates are serialized within the process only, so a store shouldn't be shared
+    /// between processes writing at the same time.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct CasTarget {
+        store_dir: PathBuf,
+        key: String,
+    }
+
+    impl CasTarget {
+        pub fn new(store_dir: &str, key: &str) -> Self {
+            CasTarget {
+                store_dir: PathBuf::from(store_dir),
+                key: key.to_string(),
+            }
+        }
+
+        /// Blob filename for a content hash
+        pub fn blob_filename(&self, hash: &str) -> PathBuf {
+            self.store_dir.join("blobs").join(hash)
+        }
+
+        fn index_filename(&self) -> PathBuf {
+            self.store_dir.join("index.json")
+        }
+
+        fn load_index(&self) -> Result<CasIndex> {
+            match fs::read(self.index_filename()) {
+                Ok(data) => Ok(serde_json::from_slice(&data)?),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(CasIndex::default()),
+                Err(e) => Err(e.into()),
+            }
+        }
+
+        fn save_index(&self, index: &CasIndex) -> Result<()> {
+            fs::write(self.index_filename(), serde_json::to_vec(index)?)?;
+            Ok(())
+        }
+
+        // Drop the key's reference to its blob, removing the blob if it was the last one
+        fn release(&self, index: &mut CasIndex) -> Result<()> {
+            if let Some(hash) = index.keys.remove(&self.key) {
+                let refs = index.refs.entry(hash.clone()).or_default();
+                *refs = refs.saturating_sub(1);
+                if *refs == 0 {
+                    index.refs.remove(&hash);
+                    let blob = self.blob_filename(&hash);
+                    if blob.is_file() {
+                        fs::remove_file(blob)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Target for CasTarget {
+        fn read(&self) -> Result<Vec<u8>> {
+            let hash = self
+                .content_hash()?
+                .ok_or_else(|| anyhow!("Key {} not in store {:?}", self.key, self.store_dir))?;
+            Ok(fs::read(self.blob_filename(&hash))?)
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            let _lock = CAS_INDEX_LOCK.lock().unwrap();
+            let hash = sha256_hex(s);
+            let mut index = self.load_index()?;
+            if index.keys.get(&self.key) == Some(&hash) {
+                return Ok(());
+            }
+            self.release(&mut index)?;
+            let blob = self.blob_filename(&hash);
+            if !blob.is_file() {
+                fs::create_dir_all(self.store_dir.join("blobs"))?;
+                fs::write(blob, s)?;
+            }
+            *index.refs.entry(hash.clone()).or_default() += 1;
+            index.keys.insert(self.key.clone(), hash);
+            self.save_index(&index)
+        }
+
+        fn delete(&self) -> Result<()> {
+            let _lock = CAS_INDEX_LOCK.lock().unwrap();
+            let mut index = self.load_index()?;
+            if index.keys.contains_key(&self.key) {
+                self.release(&mut index)?;
+                self.save_index(&index)?;
+            }
+            Ok(())
+        }
+
+        fn exists(&self) -> Result<bool> {
+            Ok(self.content_hash()?.is_some())
+        }
+
+        /// The content hash, since a key's data only changes with it
+        fn version(&self) -> Result<Option<String>> {
+            self.content_hash()
+        }
+
+        /// Looked up in the index, without reading the blob
+        fn content_hash(&self) -> Result<Option<String>> {
+            let index = self.load_index()?;
+            Ok(index
+                .keys
+                .get(&self.key)
+                .filter(|hash| self.blob_filename(hash).is_file())
+                .cloned())
+        }
+    }
+
+    /// RetryingTarget retries each operation on an inner target according to a retry
+    /// policy, optionally through a circuit breaker shared with other targets using the
+    /// same backend
+    pub struct RetryingTarget<T: Target> {
+        inner: T,
+        policy: RetryPolicy,
+        breaker: Option<Arc<CircuitBreaker>>,
+    }
+
+    /// RetryingTarget over a boxed target, e.g., to make an S3Target or PostgresTarget
+    /// resilient to transient errors
+    pub type RetryTarget = RetryingTarget<Box<dyn Target>>;
+
+    impl<T: Target> RetryingTarget<T> {
+        pub fn new(inner: T, policy: RetryPolicy) -> Self {
+            RetryingTarget {
+                inner,
+                policy,
+                breaker: None,
+            }
+        }
+
+        /// Retry each operation up to retries times, waiting backoff, then twice as
+        /// long, and so on between attempts
+        pub fn with_retries(inner: T, retries: u32, backoff: Duration) -> Self {
+            RetryingTarget::new(inner, RetryPolicy::new(retries + 1, backoff))
+        }
+
+        pub fn with_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+            self.breaker = Some(breaker);
+            self
+        }
+
+        pub fn inner(&self) -> &T {
+            &self.inner
+        }
+
+        // Run an operation with retries, each attempt going through the breaker
+        fn retry<R>(&self, f: impl Fn(&T) -> Result<R>) -> Result<R> {
+            self.policy.run(|| match &self.breaker {
+                Some(breaker) => breaker.call(|| f(&self.inner)),
+                None => f(&self.inner),
+            })
+        }
+    }
+
+    impl<T: Target> Target for RetryingTarget<T> {
+        fn read(&self) -> Result<Vec<u8>> {
+            self.retry(|t| t.read())
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            self.retry(|t| t.write(s))
+        }
+
+        fn delete(&self) -> Result<()> {
+            self.retry(|t| t.delete())
+        }
+
+        fn exists(&self) -> Result<bool> {
+            self.retry(|t| t.exists())
+        }
+
+        fn version(&self) -> Result<Option<String>> {
+            self.retry(|t| t.version())
+        }
+
+        fn last_modified(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+            self.retry(|t| t.last_modified())
+        }
+
+        fn size(&self) -> Result<Option<u64>> {
+            self.retry(|t| t.size())
+        }
+
+        fn sidecar(&self, suffix: &str) -> Option<Box<dyn Target>> {
+            self.inner.sidecar(suffix)
+        }
+
+        fn uri(&self) -> Option<String> {
+            self.inner.uri()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        };
+
+        use anyhow::{anyhow, Result};
+        use serde::{Deserialize, Serialize};
+
+        use super::{
+            CachedTarget, CasTarget, ClosureTarget, GzipTarget, MappedTarget, MultiTarget,
+            ReplicaQuorum, ReplicatedTarget, RetryingTarget, ShardedFileTarget, SizeRoutingTarget,
+        };
+        use crate::tasks::{FileTarget, InMemoryTarget, Target, Task};
+
+        #[test]
+        fn mapped_target() {
+            /// Current format: "val" was renamed to "value"
+            #[derive(Debug, Serialize, Deserialize, PartialEq)]
+            struct Record {
+                name: String,
+                value: f64,
+            }
+
+            /// Rename the old "val" field
+            fn upgrade(data: Vec<u8>) -> Result<Vec<u8>> {
+                let mut value: serde_json::Value = serde_json::from_slice(&data)?;
+                let record = value
+                    .as_object_mut()
+                    .ok_or_else(|| anyhow!("not an object"))?;
+                if let Some(val) = record.remove("val") {
+                    record.insert("value".to_string(), val);
+                }
+                Ok(serde_json::to_vec(&value)?)
+            }
+
+            #[derive(Debug)]
+            struct RecordTask {}
+            impl Task for RecordTask {
+                fn get_target(&self) -> Result<Box<dyn Target>> {
+                    let inner = FileTarget::new("/tmp", "test_mapped_target.json");
+                    Ok(Box::new(MappedTarget::new(inner, upgrade)))
+                }
+            }
+
+            // a cache written in the old format
+            let inner = FileTarget::new("/tmp", "test_mapped_target.json");
+            inner
+                .write(r#"{"name": "a", "val": 1.5}"#.as_bytes())
+                .unwrap();
+
+            let task = RecordTask {};
+            let record: Record = serde_json::from_slice(&task.get_data().unwrap()).unwrap();
+            assert_eq!(
+                record,
+                Record {
+                    name: "a".to_string(),
+                    value: 1.5
+                }
+            );
+
+            // exists and delete go to the inner target
+            let target = task.get_target().unwrap();
+            assert!(target.exists().unwrap());
+            target.delete().unwrap();
+            assert!(!inner.exists().unwrap());
+        }
+
+        #[test]
+        fn mapped_target_write_map() {
+            let inner = FileTarget::new("/tmp", "test_mapped_target_write.txt");
+            let target = MappedTarget::new(inner, |data| {
+                Ok(data.into_iter().map(|b| b.to_ascii_lowercase()).collect())
+            })
+            .with_write_map(|data| Ok(data.into_iter().map(|b| b.to_ascii_uppercase()).collect()));
+            target.write("Some Data".as_bytes()).unwrap();
+            assert_eq!(
+                target.inner().read().unwrap(),
+                "SOME DATA".as_bytes().to_vec()
+            );
+            assert_eq!(target.read().unwrap(), "some data".as_bytes().to_vec());
+        }
+
+        #[test]
+        fn gzip_target() {
+            use std::io::Read;
+
+            let data = "some repetitive data, ".repeat(500).into_bytes();
+            assert!(data.len() > 10_000);
+            let target = GzipTarget::new(FileTarget::new("/tmp", "test_gzip_target.json.gz"));
+            target.delete().unwrap();
+            assert!(!target.exists().unwrap());
+
+            target.write(&data).unwrap();
+            assert!(target.exists().unwrap());
+            let on_disk = std::fs::metadata(target.inner().filename()).unwrap().len();
+            assert!(
+                on_disk < data.len() as u64 / 10,
+                "{} bytes on disk",
+                on_disk
+            );
+            assert_eq!(target.read().unwrap(), data);
+
+            // streaming goes through the same compression
+            let streamed = GzipTarget::new(FileTarget::new("/tmp", "test_gzip_stream.gz"));
+            streamed.write_stream(&mut &data[..]).unwrap();
+            assert!(streamed.inner().read().unwrap().len() < data.len() / 10);
+            let mut read_back = Vec::new();
+            streamed
+                .read_stream()
+                .unwrap()
+                .read_to_end(&mut read_back)
+                .unwrap();
+            assert_eq!(read_back, data);
+            assert_eq!(streamed.read().unwrap(), data);
+
+            target.delete().unwrap();
+            assert!(!target.exists().unwrap());
+            assert!(!target.inner().exists().unwrap());
+        }
+
+        #[test]
+        fn gzip_level() {
+            let data = (0..20_000)
+                .map(|i| format!("{} ", i * 7919 % 1000))
+                .collect::<String>()
+                .into_bytes();
+            let size = |level| {
+                let target = GzipTarget::new(FileTarget::new(
+                    "/tmp",
+                    &format!("test_gzip_level_{}.gz", level),
+                ))
+                .with_level(level)
+                .unwrap();
+                target.write(&data).unwrap();
+                assert_eq!(target.read().unwrap(), data);
+                target.inner().read().unwrap().len()
+            };
+            assert!(size(9) < size(1));
+            assert!(
+                GzipTarget::new(FileTarget::new("/tmp", "test_gzip_level_10.gz"))
+                    .with_level(10)
+                    .is_err()
+            );
+        }
+
+        #[test]
+        fn closure_target() {
+            let buffer = Arc::new(Mutex::new(None::<Vec<u8>>));
+            let (read_buf, write_buf, delete_buf, exists_buf) = (
+                buffer.clone(),
+                buffer.clone(),
+                buffer.clone(),
+                buffer.clone(),
+            );
+            let target = ClosureTarget::new(
+                move || {
+                    read_buf
+                        .lock()
+                        .unwrap()
+                        .clone()
+                        .ok_or_else(|| anyhow!("Nothing written"))
+                },
+                move |s| {
+                    *write_buf.lock().unwrap() = Some(s.to_vec());
+                    Ok(())
+                },
+                move || {
+                    *delete_buf.lock().unwrap() = None;
+                    Ok(())
+                },
+                move || Ok(exists_buf.lock().unwrap().is_some()),
+            );
+
+            assert!(!target.exists().unwrap());
+            assert!(target.read().is_err());
+            target.write("closure data".as_bytes()).unwrap();
+            assert!(target.exists().unwrap());
+            assert_eq!(target.read().unwrap(), "closure data".as_bytes().to_vec());
+            assert_eq!(
+                buffer.lock().unwrap().as_deref(),
+                Some("closure data".as_bytes())
+            );
+            target.delete().unwrap();
+            assert!(!target.exists().unwrap());
+        }
+
+        #[test]
+        fn retrying_target_with_breaker() {
+            use crate::resilience::{CircuitBreaker, RetryPolicy};
+            use std::time::Duration;
+
+            /// Target whose backend is down
+            struct Down {
+                calls: Arc<AtomicUsize>,
+            }
+            impl Target for Down {
+                fn read(&self) -> Result<Vec<u8>> {
+                    self.calls.fetch_add(1, Ordering::SeqCst);
+                    Err(anyhow!("backend down"))
+                }
+                fn write(&self, _s: &[u8]) -> Result<()> {
+                    Err(anyhow!("backend down"))
+                }
+                fn delete(&self) -> Result<()> {
+                    Ok(())
+                }
+                fn exists(&self) -> Result<bool> {
+                    Ok(true)
+                }
+            }
+
+            let calls = Arc::new(AtomicUsize::new(0));
+            let breaker = Arc::new(CircuitBreaker::new(4, Duration::from_secs(60)));
+            let target = RetryingTarget::new(
+                Down {
+                    calls: calls.clone(),
+                },
+                RetryPolicy::new(3, Duration::from_millis(1)),
+            )
+            .with_breaker(breaker.clone());
+            assert!(target.read().is_err());
+            assert_eq!(calls.load(Ordering::SeqCst), 3);
+            // the breaker opens on the fourth failure and an open circuit isn't retried
+            assert!(target.read().is_err());
+            assert!(target.read().is_err());
+            assert_eq!(calls.load(Ordering::SeqCst), 4);
+            assert!(breaker.is_open());
+        }
+
+        #[test]
+        fn retry_target() {
+            use super::RetryTarget;
+            use crate::tasks::InMemoryTarget;
+            use std::time::Duration;
+
+            /// Target whose reads fail the first failures times
+            struct Flaky {
+                inner: InMemoryTarget,
+                failures: usize,
+                reads: Arc<AtomicUsize>,
+            }
+            impl Target for Flaky {
+                fn read(&self) -> Result<Vec<u8>> {
+                    if self.reads.fetch_add(1, Ordering::SeqCst) < self.failures {
+                        return Err(anyhow!("transient error"));
+                    }
+                    self.inner.read()
+                }
+                fn write(&self, s: &[u8]) -> Result<()> {
+                    self.inner.write(s)
+                }
+                fn delete(&self) -> Result<()> {
+                    self.inner.delete()
+                }
+                fn exists(&self) -> Result<bool> {
+                    self.inner.exists()
+                }
+            }
+
+            let flaky = |failures, reads: &Arc<AtomicUsize>| -> RetryTarget {
+                RetryTarget::with_retries(
+                    Box::new(Flaky {
+                        inner: InMemoryTarget::new(),
+                        failures,
+                        reads: reads.clone(),
+                    }),
+                    3,
+                    Duration::from_millis(1),
+                )
+            };
+            let reads = Arc::new(AtomicUsize::new(0));
+            let target = flaky(2, &reads);
+            target.write("data".as_bytes()).unwrap();
+            assert_eq!(target.read().unwrap(), "data".as_bytes().to_vec());
+            assert_eq!(reads.load(Ordering::SeqCst), 3);
+
+            // more failures than retries
+            let reads = Arc::new(AtomicUsize::new(0));
+            let target = flaky(5, &reads);
+            target.write("data".as_bytes()).unwrap();
+            assert!(target.read().is_err());
+            assert_eq!(reads.load(Ordering::SeqCst), 4);
+        }
+
+        #[test]
+        fn sharded_file_target() {
+            let cache_dir = "/tmp/test_sharded_file_target";
+            let _ = std::fs::remove_dir_all(cache_dir);
+            let first = ShardedFileTarget::new(cache_dir, "first.txt");
+            let second = ShardedFileTarget::new(cache_dir, "second.txt");
+            assert_ne!(first.filename().parent(), second.filename().parent());
+            assert_eq!(
+                first.filename().parent().unwrap().parent().unwrap(),
+                std::path::Path::new(cache_dir)
+            );
+
+            assert!(!first.exists().unwrap());
+            first.write("first data".as_bytes()).unwrap();
+            second.write("second data".as_bytes()).unwrap();
+            // a new target for the same name finds the same shard
+            let first = ShardedFileTarget::new(cache_dir, "first.txt");
+            assert!(first.exists().unwrap());
+            assert_eq!(first.read().unwrap(), "first data".as_bytes().to_vec());
+            assert_eq!(second.read().unwrap(), "second data".as_bytes().to_vec());
+            first.delete().unwrap();
+            assert!(!first.exists().unwrap());
+            assert!(second.exists().unwrap());
+        }
+
+        #[test]
+        fn replicated_target() {
+            let replicas = (0..3).map(|_| InMemoryTarget::new()).collect::<Vec<_>>();
+            let target = ReplicatedTarget::new(
+                replicas
+                    .iter()
+                    .map(|r| Box::new(r.clone()) as Box<dyn Target>)
+                    .collect(),
+            );
+            assert!(!target.exists().unwrap());
+            target.write("data".as_bytes()).unwrap();
+            assert!(replicas.iter().all(|r| r.read().unwrap() == b"data"));
+            assert!(target.exists().unwrap());
+
+            // mark each replica's data to see which one a read hits
+            for (i, replica) in replicas.iter().enumerate() {
+                replica.write(format!("replica{}", i).as_bytes()).unwrap();
+            }
+            let mut reads = (0..3)
+                .map(|_| String::from_utf8(target.read().unwrap()).unwrap())
+                .collect::<Vec<_>>();
+            reads.sort();
+            assert_eq!(reads, vec!["replica0", "replica1", "replica2"]);
+
+            // a failing replica is skipped
+            replicas[1].delete().unwrap();
+            for _ in 0..6 {
+                assert_ne!(target.read().unwrap(), b"replica1");
+            }
+            assert!(!target.exists().unwrap());
+            let target = target.with_quorum(ReplicaQuorum::Majority);
+            assert!(target.exists().unwrap());
+
+            target.delete().unwrap();
+            assert!(replicas.iter().all(|r| !r.exists().unwrap()));
+            assert!(target.read().is_err());
+        }
+
+        #[test]
+        fn multi_target() {
+            let (first, second) = (InMemoryTarget::new(), InMemoryTarget::new());
+            let target = MultiTarget::new(vec![Box::new(first.clone()), Box::new(second.clone())]);
+            assert!(!target.exists().unwrap());
+            assert!(target.read().is_err());
+
+            target.write("data".as_bytes()).unwrap();
+            assert_eq!(first.read().unwrap(), "data".as_bytes().to_vec());
+            assert_eq!(second.read().unwrap(), "data".as_bytes().to_vec());
+            assert!(target.exists().unwrap());
+
+            // reads are from the first target that exists
+            second.write("second".as_bytes()).unwrap();
+            assert_eq!(target.read().unwrap(), "data".as_bytes().to_vec());
+            first.delete().unwrap();
+            assert_eq!(target.read().unwrap(), "second".as_bytes().to_vec());
+            assert!(!target.exists().unwrap());
+            let target = target.with_quorum(ReplicaQuorum::Any);
+            assert!(target.exists().unwrap());
+
+            target.delete().unwrap();
+            assert!(!first.exists().unwrap() && !second.exists().unwrap());
+        }
+
+        #[test]
+        fn cached_target() {
+            /// Counts reads of the inner target
+            struct Counting {
+                inner: FileTarget,
+                reads: Arc<AtomicUsize>,
+            }
+            impl Target for Counting {
+                fn read(&self) -> Result<Vec<u8>> {
+                    self.reads.fetch_add(1, Ordering::SeqCst);
+                    self.inner.read()
+                }
+                fn write(&self, s: &[u8]) -> Result<()> {
+                    self.inner.write(s)
+                }
+                fn delete(&self) -> Result<()> {
+                    self.inner.delete()
+                }
+                fn exists(&self) -> Result<bool> {
+                    self.inner.exists()
+                }
+            }
+
+            let slow_reads = Arc::new(AtomicUsize::new(0));
+            let fast = FileTarget::new("/tmp", "test_cached_target_fast.txt");
+            let slow = FileTarget::new("/tmp", "test_cached_target_slow.txt");
+            fast.delete().unwrap();
+            slow.write("remote data".as_bytes()).unwrap();
+            let target = CachedTarget::new(
+                Box::new(fast),
+                Box::new(Counting {
+                    inner: slow,
+                    reads: slow_reads.clone(),
+                }),
+            );
+            assert!(target.exists().unwrap());
+            assert!(!target.fast.exists().unwrap());
+
+            // the first read populates the fast tier, the second doesn't touch the slow one
+            assert_eq!(target.read().unwrap(), "remote data".as_bytes().to_vec());
+            assert!(target.fast.exists().unwrap());
+            assert_eq!(target.read().unwrap(), "remote data".as_bytes().to_vec());
+            assert_eq!(slow_reads.load(Ordering::SeqCst), 1);
+
+            // writes go to both tiers
+            target.write("new data".as_bytes()).unwrap();
+            assert_eq!(target.fast.read().unwrap(), "new data".as_bytes().to_vec());
+            assert_eq!(target.slow.read().unwrap(), "new data".as_bytes().to_vec());
+
+            target.delete().unwrap();
+            assert!(!target.exists().unwrap());
+        }
+
+        #[test]
+        fn cas_target() {
+            let store_dir = "/tmp/test_cas_target";
+            let _ = std::fs::remove_dir_all(store_dir);
+            let first = CasTarget::new(store_dir, "first");
+            let second = CasTarget::new(store_dir, "second");
+            assert!(!first.exists().unwrap());
+
+            first.write("same data".as_bytes()).unwrap();
+            second.write("same data".as_bytes()).unwrap();
+            let blobs = || {
+                std::fs::read_dir(format!("{}/blobs", store_dir))
+                    .unwrap()
+                    .count()
+            };
+            assert_eq!(blobs(), 1);
+            assert_eq!(first.read().unwrap(), "same data".as_bytes().to_vec());
+            assert_eq!(second.read().unwrap(), "same data".as_bytes().to_vec());
+            assert_eq!(first.version().unwrap(), second.version().unwrap());
+
+            // the blob is kept while another key references it
+            first.delete().unwrap();
+            assert!(!first.exists().unwrap());
+            assert_eq!(second.read().unwrap(), "same data".as_bytes().to_vec());
+            assert_eq!(blobs(), 1);
+
+            // rewriting a key releases its old blob
+            second.write("other data".as_bytes()).unwrap();
+            assert_eq!(blobs(), 1);
+            assert_eq!(second.read().unwrap(), "other data".as_bytes().to_vec());
+            second.delete().unwrap();
+            assert_eq!(blobs(), 0);
+        }
+
+        #[test]
+        fn size_routing_target() {
+            let target = SizeRoutingTarget::new(
+                16,
+                Box::new(FileTarget::new("/tmp", "test_size_routing_small.txt")),
+                Box::new(FileTarget::new("/tmp", "test_size_routing_large.txt")),
+            );
+            target.delete().unwrap();
+            assert!(!target.exists().unwrap());
+            assert!(target.read().is_err());
+
+            target.write("tiny".as_bytes()).unwrap();
+            assert!(target.small.exists().unwrap());
+            assert!(!target.large.exists().unwrap());
+            assert_eq!(target.read().unwrap(), "tiny".as_bytes().to_vec());
+
+            // a large payload moves the data to the large backend
+            let large = "x".repeat(100);
+            target.write(large.as_bytes()).unwrap();
+            assert!(!target.small.exists().unwrap());
+            assert!(target.large.exists().unwrap());
+            assert_eq!(target.read().unwrap(), large.as_bytes().to_vec());
+
+            // a new instance finds the backend holding the data
+            let reopened = SizeRoutingTarget::new(
+                16,
+                Box::new(FileTarget::new("/tmp", "test_size_routing_small.txt")),
+                Box::new(FileTarget::new("/tmp", "test_size_routing_large.txt")),
+            );
+            assert!(reopened.exists().unwrap());
+            assert_eq!(reopened.read().unwrap(), large.as_bytes().to_vec());
+
+            target.delete().unwrap();
+            assert!(!target.exists().unwrap());
+        }
+    }
+}
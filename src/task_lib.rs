@@ -1,7 +1,18 @@
 pub mod tasks {
-    use anyhow::Result;
-    use log::info;
-    use std::{collections::HashMap, fmt, fs, path};
+    use crate::{resilience::RetryPolicy, targets::GzipTarget};
+    use anyhow::{anyhow, Result};
+    use log::{info, warn};
+    use serde::{de::DeserializeOwned, Serialize};
+    use sha2::{Digest, Sha256};
+    use std::{
+        cell::RefCell,
+        collections::{BTreeMap, HashMap, HashSet},
+        fmt, fs,
+        io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+        path,
+        sync::{mpsc, Arc, Condvar, Mutex},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
 
     /// The Target trait represents cached data. The data is stored as a byte slice, and can be used
     /// with serde for serialization of other types.
@@ -12,11 +23,192 @@ pub mod tasks {
         /// Read from target destination to a Vec of bytes
         fn write(&self, s: &[u8]) -> Result<()>;
 
+        /// Read the last n bytes (or all of the data if there are fewer than n bytes)
+        fn read_tail(&self, n: usize) -> Result<Vec<u8>> {
+            let mut data = self.read()?;
+            Ok(data.split_off(data.len().saturating_sub(n)))
+        }
+
+        /// Reader over the stored data. The default reads all of the data with read, so
+        /// backends that can stream should override this.
+        fn read_stream(&self) -> Result<Box<dyn Read>> {
+            Ok(Box::new(io::Cursor::new(self.read()?)))
+        }
+
+        /// Store the data read from r. The default collects all of it and calls write, so
+        /// backends that can stream should override this.
+        fn write_stream(&self, r: &mut dyn Read) -> Result<()> {
+            let mut data = Vec::new();
+            r.read_to_end(&mut data)?;
+            self.write(&data)
+        }
+
         /// Delete the target destination
         fn delete(&self) -> Result<()>;
 
         /// Does the cache exist?
         fn exists(&self) -> Result<bool>;
+
+        /// Version (or etag) of the stored data, used to detect changed dependencies. None means
+        /// the target can't provide one
+        fn version(&self) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        /// When the stored data was last written, for make-style staleness checks. None
+        /// if the target doesn't exist or can't tell
+        fn last_modified(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+            Ok(None)
+        }
+
+        /// Bytes the stored data occupies, for capacity planning. None if the target
+        /// doesn't exist or can't tell
+        fn size(&self) -> Result<Option<u64>> {
+            Ok(None)
+        }
+
+        /// Companion target stored alongside this one for bookkeeping data (e.g., recorded
+        /// dependency versions). None if the target doesn't support sidecars
+        fn sidecar(&self, _suffix: &str) -> Option<Box<dyn Target>> {
+            None
+        }
+
+        /// Hex SHA-256 of the stored data, or None if the target doesn't exist. Backends
+        /// that can get a hash without reading the data should override this.
+        fn content_hash(&self) -> Result<Option<String>> {
+            if !self.exists()? {
+                return Ok(None);
+            }
+            Ok(Some(sha256_hex(&self.read()?)))
+        }
+
+        /// Delete all but the keep newest versions of this target's data, for targets
+        /// that store one version per date or run. Returns the number deleted. The default
+        /// does nothing.
+        fn retain_latest(&self, _keep: usize) -> Result<usize> {
+            Ok(0)
+        }
+
+        /// Location of the data (e.g., file:///tmp/data.json) for messages, or None if
+        /// the target has no natural one
+        fn uri(&self) -> Option<String> {
+            None
+        }
+
+        /// Parse the stored data as JSON, for inspecting it without a concrete type
+        fn read_json(&self) -> Result<serde_json::Value> {
+            let data = self.read()?;
+            serde_json::from_slice(&data).map_err(|e| {
+                let uri = self.uri().unwrap_or_else(|| "target".to_string());
+                anyhow!("Failed to parse {} as JSON: {}", uri, e)
+            })
+        }
+    }
+
+    /// Hex encoded SHA-256 of data
+    pub(crate) fn sha256_hex(data: &[u8]) -> String {
+        Sha256::digest(data)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Hex encoded SHA-256 of the JSON serialization of a task's parameters, e.g., to
+    /// override Task::cache_key with
+    pub fn json_cache_key<T: Serialize + ?Sized>(task: &T) -> Result<String> {
+        Ok(sha256_hex(&serde_json::to_vec(task)?))
+    }
+
+    /// Boxed targets are targets, so wrappers can hold either a concrete or a boxed target.
+    /// Every method is forwarded so the inner target's overrides are kept.
+    impl<T: Target + ?Sized> Target for Box<T> {
+        fn read(&self) -> Result<Vec<u8>> {
+            (**self).read()
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            (**self).write(s)
+        }
+
+        fn read_tail(&self, n: usize) -> Result<Vec<u8>> {
+            (**self).read_tail(n)
+        }
+
+        fn read_stream(&self) -> Result<Box<dyn Read>> {
+            (**self).read_stream()
+        }
+
+        fn write_stream(&self, r: &mut dyn Read) -> Result<()> {
+            (**self).write_stream(r)
+        }
+
+        fn delete(&self) -> Result<()> {
+            (**self).delete()
+        }
+
+        fn exists(&self) -> Result<bool> {
+            (**self).exists()
+        }
+
+        fn version(&self) -> Result<Option<String>> {
+            (**self).version()
+        }
+
+        fn last_modified(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+            (**self).last_modified()
+        }
+
+        fn size(&self) -> Result<Option<u64>> {
+            (**self).size()
+        }
+
+        fn sidecar(&self, suffix: &str) -> Option<Box<dyn Target>> {
+            (**self).sidecar(suffix)
+        }
+
+        fn content_hash(&self) -> Result<Option<String>> {
+            (**self).content_hash()
+        }
+
+        fn retain_latest(&self, keep: usize) -> Result<usize> {
+            (**self).retain_latest(keep)
+        }
+
+        fn uri(&self) -> Option<String> {
+            (**self).uri()
+        }
+    }
+
+    /// Version of a file based on its length and modification time
+    fn file_version(filename: &path::Path) -> Result<Option<String>> {
+        if !filename.is_file() {
+            return Ok(None);
+        }
+        let metadata = fs::metadata(filename)?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+        Ok(Some(format!("{}-{}", metadata.len(), modified.as_nanos())))
+    }
+
+    /// Modification time of a file, or None if it doesn't exist
+    fn file_modified(filename: &path::Path) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+        if !filename.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(fs::metadata(filename)?.modified()?.into()))
+    }
+
+    /// Length of a file, or None if it doesn't exist
+    fn file_size(filename: &path::Path) -> Result<Option<u64>> {
+        if !filename.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(fs::metadata(filename)?.len()))
+    }
+
+    /// file:// URI of a file, absolute if the current directory is known
+    fn file_uri(filename: &path::Path) -> String {
+        let filename = std::path::absolute(filename).unwrap_or_else(|_| filename.to_path_buf());
+        format!("file://{}", filename.display())
     }
 
     /// Target that does nothing, useful for wrapper tasks that exist solely to
@@ -47,6 +239,47 @@ pub mod tasks {
         }
     }
 
+    /// InMemoryTarget keeps the data in memory, e.g., so task tests don't touch the
+    /// filesystem. Clones share the same buffer, so a task can return a clone from
+    /// get_target and the test can inspect the original.
+    #[derive(Debug, Clone, Default)]
+    pub struct InMemoryTarget {
+        data: Arc<Mutex<Option<Vec<u8>>>>,
+    }
+
+    impl InMemoryTarget {
+        pub fn new() -> Self {
+            InMemoryTarget::default()
+        }
+    }
+
+    impl Target for InMemoryTarget {
+        fn read(&self) -> Result<Vec<u8>> {
+            self.data
+                .lock()
+                .unwrap()
+                .clone()
+                .ok_or_else(|| anyhow!("InMemoryTarget is empty"))
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            *self.data.lock().unwrap() = Some(s.to_vec());
+            Ok(())
+        }
+
+        fn delete(&self) -> Result<()> {
+            *self.data.lock().unwrap() = None;
+            Ok(())
+        }
+
+        fn exists(&self) -> Result<bool> {
+            Ok(self.data.lock().unwrap().is_some())
+        }
+    }
+
+    /// Environment variable naming the cache directory used by FileTarget::in_cache_dir
+    pub const CACHE_DIR_ENV: &str = "RUST_TASKS_CACHE_DIR";
+
     /// FileTarget implements Target, using a file as the cache destination.
     #[derive(Debug, PartialEq, Eq)]
     pub struct FileTarget {
@@ -62,20 +295,74 @@ pub mod tasks {
             }
         }
 
+        /// FileTarget in the directory named by RUST_TASKS_CACHE_DIR (the system temp
+        /// directory if it isn't set), creating the directory if needed
+        pub fn in_cache_dir(local_filename: &str) -> Result<Self> {
+            let cache_dir = match std::env::var_os(CACHE_DIR_ENV) {
+                Some(dir) => path::PathBuf::from(dir),
+                None => std::env::temp_dir(),
+            };
+            fs::create_dir_all(&cache_dir)?;
+            let cache_dir = cache_dir
+                .to_str()
+                .ok_or_else(|| anyhow!("Cache directory {:?} isn't valid UTF-8", cache_dir))?;
+            Ok(FileTarget::new(cache_dir, local_filename))
+        }
+
         /// Cache full filename
         pub fn filename(&self) -> path::PathBuf {
             path::Path::new(self.cache_dir.as_str()).join(self.local_filename.as_str())
         }
+
+        /// Buffered writer to the cache file, for output assembled from many small
+        /// pieces. The file is created (or truncated) immediately, so exists() is true
+        /// while it is still being written, and it is only complete once the writer has
+        /// been flushed (call flush() to see errors, as dropping ignores them).
+        pub fn buffered_writer(&self) -> Result<BufWriter<fs::File>> {
+            Ok(BufWriter::new(fs::File::create(self.filename())?))
+        }
     }
 
     /// The implementation just uses std::fs file operations.
     impl Target for FileTarget {
         fn read(&self) -> Result<Vec<u8>> {
-            Ok(fs::read(&self.filename())?)
+            Ok(fs::read(self.filename())?)
         }
 
+        /// Creates the cache directory first if it's missing
         fn write(&self, s: &[u8]) -> Result<()> {
-            Ok(fs::write(self.filename(), s)?)
+            let filename = self.filename();
+            if let Some(dir) = filename.parent().filter(|dir| !dir.is_dir()) {
+                fs::create_dir_all(dir)?;
+            }
+            Ok(fs::write(filename, s)?)
+        }
+
+        /// Seek from the end of the file so only the tail is read
+        fn read_tail(&self, n: usize) -> Result<Vec<u8>> {
+            let mut file = fs::File::open(self.filename())?;
+            let len = file.metadata()?.len();
+            file.seek(SeekFrom::End(-(len.min(n as u64) as i64)))?;
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+            Ok(data)
+        }
+
+        fn read_stream(&self) -> Result<Box<dyn Read>> {
+            Ok(Box::new(io::BufReader::new(fs::File::open(
+                self.filename(),
+            )?)))
+        }
+
+        /// Copy into a temporary file in the cache directory and rename it once all of
+        /// the data is written, so a failed copy leaves any existing file untouched
+        fn write_stream(&self, r: &mut dyn Read) -> Result<()> {
+            let partial = path::Path::new(&self.cache_dir).join(format!(
+                "{}.{}.partial",
+                self.local_filename,
+                uuid::Uuid::new_v4()
+            ));
+            replace_file(&partial, &self.filename(), |w| io::copy(r, w).map(|_| ()))
         }
 
         fn exists(&self) -> Result<bool> {
@@ -89,221 +376,2892 @@ pub mod tasks {
                 Ok(())
             }
         }
+
+        fn version(&self) -> Result<Option<String>> {
+            file_version(&self.filename())
+        }
+
+        fn last_modified(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+            file_modified(&self.filename())
+        }
+
+        fn size(&self) -> Result<Option<u64>> {
+            file_size(&self.filename())
+        }
+
+        /// The sidecar is a file in the same directory with the suffix appended to the filename
+        fn sidecar(&self, suffix: &str) -> Option<Box<dyn Target>> {
+            let local_filename = format!("{}.{}", self.local_filename, suffix);
+            Some(Box::new(FileTarget::new(&self.cache_dir, &local_filename)))
+        }
+
+        fn uri(&self) -> Option<String> {
+            Some(file_uri(&self.filename()))
+        }
+    }
+
+    /// Write to temp with write, then rename temp to filename once the data is flushed
+    /// to disk. temp is removed if anything fails, so filename is either replaced with
+    /// the complete data or left untouched.
+    fn replace_file(
+        temp: &path::Path,
+        filename: &path::Path,
+        write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+    ) -> Result<()> {
+        let written = fs::File::create(temp).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            write(&mut writer)?;
+            writer.into_inner()?.sync_all()
+        });
+        match written.and_then(|_| fs::rename(temp, filename)) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let _ = fs::remove_file(temp);
+                Err(e.into())
+            }
+        }
     }
 
-    /// DatedFileTarget uses dated files (date appended to the front of the
-    /// filename). This implementation uses daily, not intraday dates
+    /// AtomicFileTarget is a FileTarget whose writes never leave a partially written
+    /// file behind (e.g., if the process is killed): data is written to a hidden
+    /// sibling file .<name>.tmp.<pid>, which is renamed onto the cache file once
+    /// complete. Reads, exists and delete work on the cache file like FileTarget.
     #[derive(Debug, PartialEq, Eq)]
-    pub struct DatedFileTarget {
+    pub struct AtomicFileTarget {
         file_target: FileTarget,
-        date: chrono::NaiveDate,
     }
 
-    impl DatedFileTarget {
-        pub fn new(cache_dir: &str, local_filename: &str, date: chrono::NaiveDate) -> Self {
-            let file_target = FileTarget {
-                cache_dir: cache_dir.to_string(),
-                local_filename: local_filename.to_string(),
-            };
-            DatedFileTarget { file_target, date }
+    impl AtomicFileTarget {
+        pub fn new(cache_dir: &str, local_filename: &str) -> Self {
+            AtomicFileTarget {
+                file_target: FileTarget::new(cache_dir, local_filename),
+            }
+        }
+
+        /// Cache full filename
+        pub fn filename(&self) -> path::PathBuf {
+            self.file_target.filename()
         }
 
-        fn filename(&self) -> path::PathBuf {
-            let dstr = self.date.format("%Y%m%d").to_string();
-            let local_filename = format!("{}_{}", dstr, self.file_target.local_filename);
-            path::Path::new(self.file_target.cache_dir.as_str()).join(local_filename)
+        // Sibling file the data is written to before the rename
+        fn temp_filename(&self) -> path::PathBuf {
+            let filename = self.filename();
+            let name = filename.file_name().unwrap_or_default().to_string_lossy();
+            filename.with_file_name(format!(".{}.tmp.{}", name, std::process::id()))
         }
     }
 
-    // TODO: bad code smell - this implementation is the same as for FileTarget - investigate how to fix
-    impl Target for DatedFileTarget {
+    impl Target for AtomicFileTarget {
         fn read(&self) -> Result<Vec<u8>> {
-            Ok(fs::read(&self.filename())?)
+            self.file_target.read()
         }
 
         fn write(&self, s: &[u8]) -> Result<()> {
-            Ok(fs::write(self.filename(), s)?)
+            replace_file(&self.temp_filename(), &self.filename(), |w| w.write_all(s))
         }
 
-        fn exists(&self) -> Result<bool> {
-            Ok(self.filename().is_file())
+        fn read_tail(&self, n: usize) -> Result<Vec<u8>> {
+            self.file_target.read_tail(n)
+        }
+
+        fn read_stream(&self) -> Result<Box<dyn Read>> {
+            self.file_target.read_stream()
+        }
+
+        fn write_stream(&self, r: &mut dyn Read) -> Result<()> {
+            replace_file(&self.temp_filename(), &self.filename(), |w| {
+                io::copy(r, w).map(|_| ())
+            })
         }
 
         fn delete(&self) -> Result<()> {
-            if self.exists()? {
-                Ok(fs::remove_file(self.filename())?)
-            } else {
-                Ok(())
-            }
+            self.file_target.delete()
         }
-    }
 
-    /// The Task trait represents a piece of work with optional Task
-    /// dependencies. This is modeled after the python luigi module.
-    ///
-    /// The pattern is that you put any dependencies in get_dep_tasks,
-    /// set the target in get_target(), and fill in the compute_output() method,
-    /// where you can assume that dependencies' tasks are complete.
-    /// Then to run the task you just invoke the run() method. After run()
-    /// is invoked you can get the output using the self.get_data() method.
-    pub trait Task: fmt::Debug + Sync + Send {
-        /// Target for task output
-        fn get_target(&self) -> Result<Box<dyn Target>> {
-            Ok(Box::new(NullTarget {}))
+        fn exists(&self) -> Result<bool> {
+            self.file_target.exists()
         }
 
-        /// The result of the task. This can use dependent task data as we will
-        /// ensure that these have been run. Don't call this directly unless you
-        /// want to bypass the cache system.
-        fn compute_output(&self) -> Result<Vec<u8>> {
-            Ok(Vec::new())
+        fn version(&self) -> Result<Option<String>> {
+            self.file_target.version()
         }
 
-        /// Return the data from the target cache. If the target cache does not
-        /// exist this will fail
-        fn get_data(&self) -> Result<Vec<u8>> {
-            self.get_target()?.read()
+        fn last_modified(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+            self.file_target.last_modified()
         }
 
-        /// Run the task (i.e., recursively run dependent tasks) and return the
-        /// data from the target cache
-        fn run_and_get_data(&self) -> Result<Vec<u8>> {
-            self.run()?;
-            self.get_data()
+        fn size(&self) -> Result<Option<u64>> {
+            self.file_target.size()
         }
 
-        /// Optional task name
-        fn get_name(&self) -> String {
-            "Unimplemented".to_string()
+        fn sidecar(&self, suffix: &str) -> Option<Box<dyn Target>> {
+            self.file_target.sidecar(suffix)
         }
 
-        /// Dependencies, stored in a HashMap. These will be generated using the
-        /// run method. This is like the requires() method in luigi.
-        fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
-            Ok(HashMap::new())
+        fn uri(&self) -> Option<String> {
+            self.file_target.uri()
         }
+    }
 
-        /// Dependent task targets
-        fn get_dep_targets(&self) -> Result<HashMap<String, Box<dyn Target>>> {
-            let mut result = HashMap::<String, Box<dyn Target>>::new();
-            for (k, task) in self.get_dep_tasks()? {
-                result.insert(k, task.get_target()?);
+    /// HashedFileTarget is a FileTarget whose filename embeds (the first 16 characters
+    /// of) a cache key, e.g., data.json becomes data_<key>.json, so tasks whose
+    /// parameters differ don't share a cache file. Build it with for_task to use the
+    /// task's cache_key.
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct HashedFileTarget {
+        file_target: FileTarget,
+    }
+
+    impl HashedFileTarget {
+        pub fn new(cache_dir: &str, local_filename: &str, cache_key: &str) -> Self {
+            let key = cache_key.chars().take(16).collect::<String>();
+            let hashed_filename = match local_filename.rsplit_once('.') {
+                Some((stem, ext)) if !stem.is_empty() => format!("{}_{}.{}", stem, key, ext),
+                _ => format!("{}_{}", local_filename, key),
+            };
+            HashedFileTarget {
+                file_target: FileTarget::new(cache_dir, &hashed_filename),
             }
-            Ok(result)
         }
 
-        /// Validate the task
-        fn validate(&self, _data: &[u8]) -> Result<()> {
-            info!("{}: invoking validate", self.get_name());
-            Ok(())
+        /// Target for the task's output, keyed by Task::cache_key
+        pub fn for_task<T: Task + ?Sized>(
+            cache_dir: &str,
+            local_filename: &str,
+            task: &T,
+        ) -> Result<Self> {
+            Ok(HashedFileTarget::new(
+                cache_dir,
+                local_filename,
+                &task.cache_key()?,
+            ))
         }
 
-        /// This method recursively generates dependent data, and then calls
-        /// get_data for the Task.
-        fn run(&self) -> Result<()> {
-            info!("{}: invoking run()", self.get_name());
-            // recursively run dependent tasks
-            for (_, dep) in self.get_dep_tasks()? {
-                dep.run()?;
-            }
-            // run get_data() if the target doesn't exist
-            let target = self.get_target()?;
-            if !target.exists()? {
-                info!(
-                    "{}: target does not exist: invoking compute_output()",
-                    self.get_name()
-                );
-                let data = self.compute_output()?;
-                // only write to target destination if validation passes
-                self.validate(&data)?;
-                target.write(&data)?;
-            } else {
-                info!("{}: target exists", self.get_name());
-            }
-            Ok(())
+        /// Cache full filename
+        pub fn filename(&self) -> path::PathBuf {
+            self.file_target.filename()
         }
+    }
 
-        /// Non-dependent run: just save get_data() to get_target(). This will fail if required
-        /// dependencies are not present. For regular use just call run(). This method is used in the
-        /// scheduler run method as dependencies are handled in the code there.
-        fn run_no_deps(&self) -> Result<()> {
-            info!("{}: invoking run_no_deps()", self.get_name());
-            let target = self.get_target()?;
-            if !target.exists()? {
-                info!(
-                    "{}: target does not exist: invoking get_data() without running dependencies",
-                    self.get_name()
-                );
-                let data = self.compute_output()?;
-                // only write to target destination if validation passes
-                self.validate(&data)?;
-                target.write(&data)?;
-            }
-            Ok(())
+    impl Target for HashedFileTarget {
+        fn read(&self) -> Result<Vec<u8>> {
+            self.file_target.read()
         }
 
-        /// Delete target data: this is a convenience method as you can always
-        /// just call self.get_target()?.delete()
-        fn delete_data(&self) -> Result<()> {
-            info!("{}: invoking delete_data()", self.get_name());
-            self.get_target()?.delete()?;
-            Ok(())
+        fn write(&self, s: &[u8]) -> Result<()> {
+            self.file_target.write(s)
         }
 
-        /// Non-recursively delete dependencies, i.e., delete task outputs for
-        /// dependent tasks
-        fn delete_deps(&self) -> Result<()> {
-            info!("{}: invoking delete_deps()", self.get_name());
-            for (_, dep) in self.get_dep_targets()? {
-                dep.delete()?;
-            }
-            Ok(())
+        fn read_tail(&self, n: usize) -> Result<Vec<u8>> {
+            self.file_target.read_tail(n)
         }
 
-        /// Recursively delete dependencies, i.e., delete task outputs for
-        /// dependent tasks and their dependencies as well.
-        fn recursively_delete_data(&self) -> Result<()> {
-            info!("{}: invoking recursively_delete_data()", self.get_name());
-            self.delete_data()?;
-            for (_, dep) in self.get_dep_tasks()? {
-                dep.recursively_delete_data()?;
-            }
-            Ok(())
+        fn read_stream(&self) -> Result<Box<dyn Read>> {
+            self.file_target.read_stream()
         }
-    }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
-    extern crate serde;
-    use anyhow::{anyhow, Result};
-    use serde::{Deserialize, Serialize};
+        fn write_stream(&self, r: &mut dyn Read) -> Result<()> {
+            self.file_target.write_stream(r)
+        }
+
+        fn delete(&self) -> Result<()> {
+            self.file_target.delete()
+        }
+
+        fn exists(&self) -> Result<bool> {
+            self.file_target.exists()
+        }
+
+        fn version(&self) -> Result<Option<String>> {
+            self.file_target.version()
+        }
+
+        fn last_modified(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+            self.file_target.last_modified()
+        }
+
+        fn size(&self) -> Result<Option<u64>> {
+            self.file_target.size()
+        }
+
+        fn sidecar(&self, suffix: &str) -> Option<Box<dyn Target>> {
+            self.file_target.sidecar(suffix)
+        }
+
+        fn uri(&self) -> Option<String> {
+            self.file_target.uri()
+        }
+    }
+
+    /// DatedFileTarget uses dated files (date appended to the front of the
+    /// filename). This implementation uses daily, not intraday dates
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct DatedFileTarget {
+        /// File with the date prefixed to its local_filename
+        file_target: FileTarget,
+        local_filename: String,
+        date: chrono::NaiveDate,
+    }
+
+    impl DatedFileTarget {
+        pub fn new(cache_dir: &str, local_filename: &str, date: chrono::NaiveDate) -> Self {
+            let dated_filename = format!("{}_{}", date.format("%Y%m%d"), local_filename);
+            DatedFileTarget {
+                file_target: FileTarget::new(cache_dir, &dated_filename),
+                local_filename: local_filename.to_string(),
+                date,
+            }
+        }
+
+        /// Cache full filename, including the date
+        pub fn filename(&self) -> path::PathBuf {
+            self.file_target.filename()
+        }
+    }
+
+    impl Target for DatedFileTarget {
+        fn read(&self) -> Result<Vec<u8>> {
+            self.file_target.read()
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            self.file_target.write(s)
+        }
+
+        fn read_tail(&self, n: usize) -> Result<Vec<u8>> {
+            self.file_target.read_tail(n)
+        }
+
+        fn read_stream(&self) -> Result<Box<dyn Read>> {
+            self.file_target.read_stream()
+        }
+
+        fn write_stream(&self, r: &mut dyn Read) -> Result<()> {
+            self.file_target.write_stream(r)
+        }
+
+        fn exists(&self) -> Result<bool> {
+            self.file_target.exists()
+        }
+
+        fn delete(&self) -> Result<()> {
+            self.file_target.delete()
+        }
+
+        fn version(&self) -> Result<Option<String>> {
+            self.file_target.version()
+        }
+
+        fn last_modified(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+            self.file_target.last_modified()
+        }
+
+        fn size(&self) -> Result<Option<u64>> {
+            self.file_target.size()
+        }
+
+        fn sidecar(&self, suffix: &str) -> Option<Box<dyn Target>> {
+            self.file_target.sidecar(suffix)
+        }
+
+        /// Delete the files (and their sidecars) for all but the keep latest dates
+        fn retain_latest(&self, keep: usize) -> Result<usize> {
+            let mut dated = dated_files(&self.file_target.cache_dir, &self.local_filename)?;
+            dated.reverse();
+            let old = dated.split_off(keep.min(dated.len()));
+            for (_, path) in &old {
+                for sidecar in sidecars_of(path) {
+                    fs::remove_file(sidecar)?;
+                }
+                fs::remove_file(path)?;
+            }
+            Ok(old.len())
+        }
+
+        fn uri(&self) -> Option<String> {
+            self.file_target.uri()
+        }
+    }
+
+    /// DateTimeFileTarget is the intraday version of DatedFileTarget: the filename is
+    /// prefixed with the timestamp (e.g., 20210903T101500_data.json), so several runs
+    /// a day get distinct files
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct DateTimeFileTarget {
+        /// File with the timestamp prefixed to its local_filename
+        file_target: FileTarget,
+        datetime: chrono::NaiveDateTime,
+    }
+
+    impl DateTimeFileTarget {
+        pub fn new(cache_dir: &str, local_filename: &str, datetime: chrono::NaiveDateTime) -> Self {
+            let stamped_filename =
+                format!("{}_{}", datetime.format("%Y%m%dT%H%M%S"), local_filename);
+            DateTimeFileTarget {
+                file_target: FileTarget::new(cache_dir, &stamped_filename),
+                datetime,
+            }
+        }
+
+        /// Cache full filename, including the timestamp
+        pub fn filename(&self) -> path::PathBuf {
+            self.file_target.filename()
+        }
+    }
+
+    impl Target for DateTimeFileTarget {
+        fn read(&self) -> Result<Vec<u8>> {
+            self.file_target.read()
+        }
+
+        fn write(&self, s: &[u8]) -> Result<()> {
+            self.file_target.write(s)
+        }
+
+        fn read_tail(&self, n: usize) -> Result<Vec<u8>> {
+            self.file_target.read_tail(n)
+        }
+
+        fn read_stream(&self) -> Result<Box<dyn Read>> {
+            self.file_target.read_stream()
+        }
+
+        fn write_stream(&self, r: &mut dyn Read) -> Result<()> {
+            self.file_target.write_stream(r)
+        }
+
+        fn exists(&self) -> Result<bool> {
+            self.file_target.exists()
+        }
+
+        fn delete(&self) -> Result<()> {
+            self.file_target.delete()
+        }
+
+        fn version(&self) -> Result<Option<String>> {
+            self.file_target.version()
+        }
+
+        fn last_modified(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+            self.file_target.last_modified()
+        }
+
+        fn size(&self) -> Result<Option<u64>> {
+            self.file_target.size()
+        }
+
+        fn sidecar(&self, suffix: &str) -> Option<Box<dyn Target>> {
+            self.file_target.sidecar(suffix)
+        }
+
+        fn uri(&self) -> Option<String> {
+            self.file_target.uri()
+        }
+    }
+
+    /// Files written by DatedFileTargets for local_filename in cache_dir, oldest first
+    fn dated_files(
+        cache_dir: &str,
+        local_filename: &str,
+    ) -> Result<Vec<(chrono::NaiveDate, path::PathBuf)>> {
+        let suffix = format!("_{}", local_filename);
+        let mut dated = Vec::new();
+        for entry in fs::read_dir(cache_dir)? {
+            let path = entry?.path();
+            let date = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(&suffix))
+                .and_then(|dstr| chrono::NaiveDate::parse_from_str(dstr, "%Y%m%d").ok());
+            if let Some(date) = date {
+                if path.is_file() {
+                    dated.push((date, path));
+                }
+            }
+        }
+        dated.sort_by_key(|(date, _)| *date);
+        Ok(dated)
+    }
+
+    /// Invariant over the outputs of consecutive dates: called with the earlier and the
+    /// later output, returning false if the pair violates it
+    pub type SeriesPredicate = Box<dyn Fn(&[u8], &[u8]) -> Result<bool> + Send + Sync>;
+
+    /// SeriesValidator checks an invariant across a dated series of outputs (e.g., each
+    /// day's record count is at least the previous day's), to catch data-quality
+    /// regressions that a single day's validate can't see. The series is the files
+    /// DatedFileTarget wrote for local_filename in cache_dir, in date order. Missing
+    /// dates are skipped, so the predicate sees the nearest earlier output.
+    pub struct SeriesValidator {
+        cache_dir: String,
+        local_filename: String,
+        predicate: SeriesPredicate,
+    }
+
+    impl SeriesValidator {
+        pub fn new<F>(cache_dir: &str, local_filename: &str, predicate: F) -> Self
+        where
+            F: Fn(&[u8], &[u8]) -> Result<bool> + Send + Sync + 'static,
+        {
+            SeriesValidator {
+                cache_dir: cache_dir.to_string(),
+                local_filename: local_filename.to_string(),
+                predicate: Box::new(predicate),
+            }
+        }
+
+        /// Run the predicate over each consecutive pair of outputs, failing with a
+        /// SeriesViolation for the first pair that violates it
+        pub fn validate(&self) -> Result<()> {
+            let dated = dated_files(&self.cache_dir, &self.local_filename)?;
+            let mut previous: Option<(chrono::NaiveDate, Vec<u8>)> = None;
+            for (date, path) in dated {
+                let data = fs::read(&path)?;
+                if let Some((previous_date, previous_data)) = &previous {
+                    if !(self.predicate)(previous_data, &data)? {
+                        return Err(SeriesViolation {
+                            local_filename: self.local_filename.clone(),
+                            previous: *previous_date,
+                            date,
+                        }
+                        .into());
+                    }
+                }
+                previous = Some((date, data));
+            }
+            Ok(())
+        }
+    }
+
+    /// Error from SeriesValidator::validate: the outputs for previous and date violate
+    /// the invariant
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct SeriesViolation {
+        pub local_filename: String,
+        pub previous: chrono::NaiveDate,
+        pub date: chrono::NaiveDate,
+    }
+
+    impl fmt::Display for SeriesViolation {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "{}: series invariant violated between {} and {}",
+                self.local_filename, self.previous, self.date
+            )
+        }
+    }
+
+    impl std::error::Error for SeriesViolation {}
+
+    /// Environment variable prefix for storage class directories, e.g.,
+    /// RUST_TASKS_STORAGE_SHARED=/mnt/shared maps the "shared" storage class
+    pub const STORAGE_ENV_PREFIX: &str = "RUST_TASKS_STORAGE_";
+
+    /// TargetConfig maps logical storage classes (e.g., "scratch", "shared", "archive")
+    /// to cache directories. Tasks ask for a storage class rather than a literal path,
+    /// so the deployment layout can change without touching task code.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct TargetConfig {
+        dirs: HashMap<String, String>,
+    }
+
+    impl TargetConfig {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Config with a storage class for each RUST_TASKS_STORAGE_<CLASS> environment
+        /// variable. Class names are lower case.
+        pub fn from_env() -> Self {
+            let mut config = Self::new();
+            for (key, value) in std::env::vars() {
+                if let Some(class) = key.strip_prefix(STORAGE_ENV_PREFIX) {
+                    config = config.with_class(&class.to_lowercase(), &value);
+                }
+            }
+            config
+        }
+
+        /// Map a storage class to a directory, replacing any existing mapping
+        pub fn with_class(mut self, class: &str, cache_dir: &str) -> Self {
+            self.dirs.insert(class.to_string(), cache_dir.to_string());
+            self
+        }
+
+        /// Cache directory for a storage class
+        pub fn cache_dir(&self, class: &str) -> Result<&str> {
+            self.dirs
+                .get(class)
+                .map(|dir| dir.as_str())
+                .ok_or_else(|| anyhow!("Unknown storage class {:?}", class))
+        }
+
+        /// FileTarget in the storage class's directory
+        pub fn file_target(&self, class: &str, local_filename: &str) -> Result<FileTarget> {
+            Ok(FileTarget::new(self.cache_dir(class)?, local_filename))
+        }
+
+        /// DatedFileTarget in the storage class's directory
+        pub fn dated_file_target(
+            &self,
+            class: &str,
+            local_filename: &str,
+            date: chrono::NaiveDate,
+        ) -> Result<DatedFileTarget> {
+            Ok(DatedFileTarget::new(
+                self.cache_dir(class)?,
+                local_filename,
+                date,
+            ))
+        }
+    }
+
+    /// Checkpoint persists intermediate state of a long compute_output in a scratch
+    /// target, so a task restarted after a crash can resume from the last save instead
+    /// of from scratch. run clears the task's checkpoint once the output is written.
+    pub struct Checkpoint {
+        target: Box<dyn Target>,
+    }
+
+    impl Checkpoint {
+        pub fn new(target: Box<dyn Target>) -> Self {
+            Checkpoint { target }
+        }
+
+        /// Replace the saved state
+        pub fn save(&self, bytes: &[u8]) -> Result<()> {
+            self.target.write(bytes)
+        }
+
+        /// The last saved state, or None if nothing was saved
+        pub fn load(&self) -> Result<Option<Vec<u8>>> {
+            if self.target.exists()? {
+                Ok(Some(self.target.read()?))
+            } else {
+                Ok(None)
+            }
+        }
+
+        /// Discard the saved state
+        pub fn clear(&self) -> Result<()> {
+            self.target.delete()
+        }
+    }
+
+    /// Serialization format for DAG::save_state and TaskProfile. The serialized data
+    /// starts with a byte naming the codec, so it can be read back whichever was used.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Codec {
+        Json,
+        Bincode,
+        MessagePack,
+    }
+
+    impl Codec {
+        const CODECS: [Codec; 3] = [Codec::Json, Codec::Bincode, Codec::MessagePack];
+
+        /// Byte identifying the codec at the start of a saved blob
+        fn tag(self) -> u8 {
+            match self {
+                Codec::Json => 1,
+                Codec::Bincode => 2,
+                Codec::MessagePack => 3,
+            }
+        }
+
+        /// Tagged serialization of value
+        pub(crate) fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>> {
+            let mut data = vec![self.tag()];
+            match self {
+                Codec::Json => serde_json::to_writer(&mut data, value)?,
+                Codec::Bincode => bincode::serialize_into(&mut data, value)?,
+                Codec::MessagePack => rmp_serde::encode::write(&mut data, value)?,
+            }
+            Ok(data)
+        }
+
+        /// Deserialize a blob written by encode. Untagged data is taken to be JSON.
+        pub(crate) fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+            let Some((&tag, payload)) = data.split_first() else {
+                return Err(anyhow!("Empty encoded data"));
+            };
+            let codec = Codec::CODECS.into_iter().find(|c| c.tag() == tag);
+            Ok(match codec {
+                Some(Codec::Json) => serde_json::from_slice(payload)?,
+                Some(Codec::Bincode) => bincode::deserialize(payload)?,
+                Some(Codec::MessagePack) => rmp_serde::from_slice(payload)?,
+                None if tag == b'{' => serde_json::from_slice(data)?,
+                None => return Err(anyhow!("Unknown codec tag {}", tag)),
+            })
+        }
+    }
+
+    /// Check of a task's output, run by TaskProfile before the output is written
+    pub type Validator = fn(&[u8]) -> Result<()>;
+
+    /// TaskProfile bundles settings that many tasks share, e.g., organization-wide
+    /// defaults kept in a constant and returned from Task::profile. The run machinery
+    /// applies the validators (after Task::validate) and the compression, and the
+    /// retention and retry defaults of Task come from the profile. Typed output is
+    /// written with the codec by encode_with_profile and read by read_with_profile.
+    #[derive(Debug, Clone, Copy)]
+    pub struct TaskProfile {
+        pub codec: Codec,
+        pub validators: &'static [Validator],
+        pub retention: Option<usize>,
+        /// Default of Task::retry_policy
+        pub retry: RetryPolicy,
+        /// Gzip level (0-9) for output written by run, None to store it uncompressed
+        pub compression: Option<u32>,
+    }
+
+    impl TaskProfile {
+        /// JSON, no extra validation, compression or retries, and keep every output
+        pub const DEFAULT: TaskProfile = TaskProfile {
+            codec: Codec::Json,
+            validators: &[],
+            retention: None,
+            retry: RetryPolicy::new(1, Duration::from_secs(1)),
+            compression: None,
+        };
+
+        /// Run every validator, returning the first error
+        pub fn validate(&self, data: &[u8]) -> Result<()> {
+            self.validators
+                .iter()
+                .try_for_each(|validator| validator(data))
+        }
+
+        /// The target wrapped in a GzipTarget if the profile compresses output, failing
+        /// if the compression level is invalid
+        pub fn wrap_target(&self, target: Box<dyn Target>) -> Result<Box<dyn Target>> {
+            Ok(match self.compression {
+                Some(level) => Box::new(GzipTarget::new(target).with_level(level)?),
+                None => target,
+            })
+        }
+    }
+
+    impl Default for TaskProfile {
+        fn default() -> Self {
+            TaskProfile::DEFAULT
+        }
+    }
+
+    /// Serialize a value with the codec of the task's profile, e.g., in compute_output
+    pub fn encode_with_profile<K: Task + ?Sized, T: Serialize>(
+        task: &K,
+        value: &T,
+    ) -> Result<Vec<u8>> {
+        task.profile().codec.encode(value)
+    }
+
+    /// Read and deserialize output written with encode_with_profile
+    pub fn read_with_profile<K: Task + ?Sized, T: DeserializeOwned>(task: &K) -> Result<T> {
+        Codec::decode(&task.get_data()?)
+    }
+
+    /// When run recomputes a target that already exists (besides when the dependency
+    /// versions changed)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum RebuildPolicy {
+        /// Never: an existing target is up to date
+        #[default]
+        ExistsOnly,
+        /// When a dependency target was modified after the target (like make)
+        Newer,
+    }
+
+    /// Result of comparing a task's cached output with freshly computed output
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum CacheVerification {
+        Matches,
+        /// SHA-256 (hex) of the cached and fresh outputs
+        Differs {
+            cached_hash: String,
+            fresh_hash: String,
+        },
+        /// Nothing is cached
+        Absent,
+    }
+
+    /// Magic bytes starting a schema version header
+    const SCHEMA_MAGIC: &[u8; 4] = b"TSCH";
+
+    /// Error reading cached output written with a different schema version than the task
+    /// expects. Outputs written without a header count as version 0.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct SchemaMismatch {
+        pub task: String,
+        pub expected: u32,
+        pub found: u32,
+    }
+
+    impl fmt::Display for SchemaMismatch {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "{}: cached output has schema version {} but version {} is expected",
+                self.task, self.found, self.expected
+            )
+        }
+    }
+
+    impl std::error::Error for SchemaMismatch {}
+
+    /// Serialize a value as JSON behind a header holding the task's output schema version.
+    /// Use this in compute_output and read the data back with read_typed.
+    pub fn encode_typed<K: Task + ?Sized, T: Serialize>(task: &K, value: &T) -> Result<Vec<u8>> {
+        let mut data = SCHEMA_MAGIC.to_vec();
+        data.extend(task.output_schema_version().to_be_bytes());
+        data.extend(serde_json::to_vec(value)?);
+        Ok(data)
+    }
+
+    /// Read and deserialize the task's cached output, checking the schema version header.
+    /// Older outputs are passed through the task's upgrade_output hook; newer ones fail
+    /// with SchemaMismatch.
+    pub fn read_typed<K: Task + ?Sized, T: DeserializeOwned>(task: &K) -> Result<T> {
+        let data = task.get_data()?;
+        let (found, body) = match data.strip_prefix(SCHEMA_MAGIC) {
+            Some(rest) if rest.len() >= 4 => {
+                let (version, body) = rest.split_at(4);
+                (u32::from_be_bytes(version.try_into()?), body.to_vec())
+            }
+            _ => (0, data),
+        };
+        let expected = task.output_schema_version();
+        let body = if found < expected {
+            task.upgrade_output(found, body)?
+        } else if found > expected {
+            return Err(SchemaMismatch {
+                task: task.get_name(),
+                expected,
+                found,
+            }
+            .into());
+        } else {
+            body
+        };
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Sidecar suffix for the dependency versions a task's output was computed from
+    const DEP_VERSIONS_SUFFIX: &str = "deps";
+
+    /// Sidecar suffix for the SHA-256 of the last output written by run
+    const SIGNATURE_SUFFIX: &str = "sig";
+
+    /// Sidecar suffix for lock files
+    const LOCK_SUFFIX: &str = "lock";
+
+    /// Sidecar suffix for the default Task::checkpoint
+    const CHECKPOINT_SUFFIX: &str = "checkpoint";
+
+    /// Sidecar suffix marking a file that enforce_cache_limit must never evict
+    pub const KEEP_SUFFIX: &str = "keep";
+
+    /// Suffixes of files that belong to another file in the same directory
+    const SIDECAR_SUFFIXES: [&str; 6] = [
+        DEP_VERSIONS_SUFFIX,
+        SIGNATURE_SUFFIX,
+        LOCK_SUFFIX,
+        CHECKPOINT_SUFFIX,
+        KEEP_SUFFIX,
+        "marker",
+    ];
+
+    /// Is the file a sidecar of another file?
+    fn is_sidecar(path: &path::Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| SIDECAR_SUFFIXES.contains(&ext))
+    }
+
+    /// Existing sidecar files of a file
+    fn sidecars_of(path: &path::Path) -> Vec<path::PathBuf> {
+        SIDECAR_SUFFIXES
+            .iter()
+            .map(|suffix| {
+                let mut sidecar = path.as_os_str().to_owned();
+                sidecar.push(format!(".{}", suffix));
+                path::PathBuf::from(sidecar)
+            })
+            .filter(|sidecar| sidecar.is_file())
+            .collect()
+    }
+
+    /// Keep the total size of the files under cache_dir (recursively) at most max_bytes
+    /// by deleting the least recently used files, by the later of their access and
+    /// modification times. A file's sidecars count towards its size and are deleted with
+    /// it, and files with a KEEP_SUFFIX sidecar are never evicted. Returns the evicted
+    /// files, oldest first.
+    pub fn enforce_cache_limit(cache_dir: &str, max_bytes: u64) -> Result<Vec<path::PathBuf>> {
+        let mut total = 0;
+        let mut candidates = Vec::new();
+        let mut dirs = vec![path::PathBuf::from(cache_dir)];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                let metadata = fs::metadata(&path)?;
+                if metadata.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                total += metadata.len();
+                if is_sidecar(&path) {
+                    continue;
+                }
+                let sidecars = sidecars_of(&path);
+                if sidecars
+                    .iter()
+                    .any(|s| s.extension().is_some_and(|e| e == KEEP_SUFFIX))
+                {
+                    continue;
+                }
+                let used = metadata.accessed()?.max(metadata.modified()?);
+                candidates.push((used, path, sidecars));
+            }
+        }
+        candidates.sort();
+        let mut evicted = Vec::new();
+        for (_, path, sidecars) in candidates {
+            if total <= max_bytes {
+                break;
+            }
+            for file in sidecars.iter().chain([&path]) {
+                total -= fs::metadata(file)?.len();
+                fs::remove_file(file)?;
+            }
+            evicted.push(path);
+        }
+        Ok(evicted)
+    }
+
+    /// Find stale sidecar files in cache_dir (not recursively): sidecars whose primary
+    /// file is gone, and locks last modified more than stale_lock_age ago (e.g., left by a
+    /// crashed process). If remove is true they are also deleted. Returns the paths found,
+    /// sorted.
+    pub fn clean_sidecars(
+        cache_dir: &str,
+        stale_lock_age: Duration,
+        remove: bool,
+    ) -> Result<Vec<path::PathBuf>> {
+        let mut stale = Vec::new();
+        for entry in fs::read_dir(cache_dir)? {
+            let path = entry?.path();
+            if !is_sidecar(&path) {
+                continue;
+            }
+            if !path.is_file() {
+                continue;
+            }
+            let orphaned = !path.with_extension("").exists();
+            let stale_lock = path.extension().is_some_and(|ext| ext == LOCK_SUFFIX)
+                && SystemTime::now()
+                    .duration_since(fs::metadata(&path)?.modified()?)
+                    .is_ok_and(|age| age > stale_lock_age);
+            if orphaned || stale_lock {
+                stale.push(path);
+            }
+        }
+        stale.sort();
+        if remove {
+            for path in &stale {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(stale)
+    }
+
+    /// Current versions of a task's dependency targets, or None if any of them is unversioned
+    fn dep_versions<T: Task + ?Sized>(task: &T) -> Result<Option<BTreeMap<String, String>>> {
+        let mut versions = BTreeMap::new();
+        for (name, target) in task.get_dep_targets()? {
+            match target.version()? {
+                Some(version) => versions.insert(name, version),
+                None => return Ok(None),
+            };
+        }
+        Ok(Some(versions))
+    }
+
+    /// Have the dependency targets changed since the task output was last computed? This is false
+    /// if there is no record of the dependency versions to compare against.
+    fn deps_changed<T: Task + ?Sized>(task: &T, target: &dyn Target) -> Result<bool> {
+        let sidecar = match target.sidecar(DEP_VERSIONS_SUFFIX) {
+            Some(sidecar) if sidecar.exists()? => sidecar,
+            _ => return Ok(false),
+        };
+        let recorded: BTreeMap<String, String> = serde_json::from_slice(&sidecar.read()?)?;
+        Ok(match dep_versions(task)? {
+            Some(current) => current != recorded,
+            None => false,
+        })
+    }
+
+    /// Does the task's rebuild policy call for recomputing its existing target?
+    fn deps_newer<T: Task + ?Sized>(task: &T) -> Result<bool> {
+        match task.rebuild_policy() {
+            RebuildPolicy::ExistsOnly => Ok(false),
+            RebuildPolicy::Newer => {
+                let deps = task.get_dep_targets()?.into_values().collect::<Vec<_>>();
+                task.is_stale_relative_to(&deps)
+            }
+        }
+    }
+
+    /// Counting semaphore limiting how many target writes run at once
+    pub(crate) struct WritePermits {
+        available: Mutex<usize>,
+        freed: Condvar,
+    }
+
+    impl WritePermits {
+        pub(crate) fn new(permits: usize) -> Self {
+            WritePermits {
+                available: Mutex::new(permits),
+                freed: Condvar::new(),
+            }
+        }
+
+        fn acquire(&self) -> WritePermit<'_> {
+            let mut available = self.available.lock().unwrap();
+            while *available == 0 {
+                available = self.freed.wait(available).unwrap();
+            }
+            *available -= 1;
+            WritePermit { permits: self }
+        }
+    }
+
+    /// Held while writing, returned to the semaphore on drop
+    struct WritePermit<'a> {
+        permits: &'a WritePermits,
+    }
+
+    impl Drop for WritePermit<'_> {
+        fn drop(&mut self) {
+            *self.permits.available.lock().unwrap() += 1;
+            self.permits.freed.notify_one();
+        }
+    }
+
+    thread_local! {
+        /// Write permits for the task being run on this thread, if writes are limited
+        static WRITE_PERMITS: RefCell<Option<Arc<WritePermits>>> = const { RefCell::new(None) };
+    }
+
+    /// Run f with the task output writes made on this thread limited by permits
+    pub(crate) fn with_write_permits<R>(
+        permits: Option<Arc<WritePermits>>,
+        f: impl FnOnce() -> R,
+    ) -> R {
+        let previous = WRITE_PERMITS.with(|p| p.replace(permits));
+        let result = f();
+        WRITE_PERMITS.with(|p| p.replace(previous));
+        result
+    }
+
+    /// Compute, validate and write the task output, then record the dependency versions it was
+    /// computed from (if the target supports sidecars and the dependencies are versioned)
+    fn materialize<T: Task + ?Sized>(task: &T, target: &dyn Target) -> Result<()> {
+        with_task_retries(task, || store(task, target, compute_with_timeout(task)?))
+    }
+
+    /// Fail if the task has a timeout but no boxed_clone to run compute_output on, so that
+    /// runs reject it before doing any work rather than on every attempt to compute it
+    pub(crate) fn check_timeout<T: Task + ?Sized>(task: &T) -> Result<()> {
+        if task.timeout().is_some() && task.boxed_clone().is_none() {
+            return Err(anyhow!(
+                "{}: tasks with a timeout must implement boxed_clone()",
+                task.display_name()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Call compute_output, on a detached worker thread if the task has a timeout,
+    /// failing as soon as the timeout passes. The worker runs the task's boxed_clone,
+    /// and may keep running after that; its late output is discarded.
+    fn compute_with_timeout<T: Task + ?Sized>(task: &T) -> Result<Vec<u8>> {
+        let timeout = match task.timeout() {
+            Some(timeout) => timeout,
+            None => return task.compute_output(),
+        };
+        check_timeout(task)?;
+        let worker_task = task.boxed_clone().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            // the receiver is gone if the timeout passed
+            let _ = sender.send(worker_task.compute_output());
+        });
+        match receiver.recv_timeout(timeout) {
+            Ok(res) => res,
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(anyhow!(
+                "{}: compute_output() timed out after {:?}",
+                task.display_name(),
+                timeout
+            )),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(anyhow!(
+                "{}: compute_output() panicked",
+                task.display_name()
+            )),
+        }
+    }
+
+    /// Call f, retrying it as Task::retry_policy says
+    fn with_task_retries<T: Task + ?Sized>(task: &T, f: impl Fn() -> Result<()>) -> Result<()> {
+        task.retry_policy().run(f)
+    }
+
+    /// Compute the output and its target with compute_with_target, and store the output
+    /// unless the target already exists (or force is set)
+    fn materialize_computed_target<T: Task + ?Sized>(task: &T, force: bool) -> Result<()> {
+        with_task_retries(task, || {
+            info!("{}: invoking compute_with_target()", task.display_name());
+            let (target, data) = task.compute_with_target()?;
+            let target = task.profile().wrap_target(target)?;
+            if !force && target.exists()? {
+                info!("{}: computed target exists", task.display_name());
+                return Ok(());
+            }
+            store(task, target.as_ref(), data)
+        })
+    }
+
+    /// Validate the output and write it to the target with its sidecars
+    fn store<T: Task + ?Sized>(task: &T, target: &dyn Target, data: Vec<u8>) -> Result<()> {
+        // only write to target destination if validation passes
+        let validated = task
+            .validate(&data)
+            .and_then(|_| task.profile().validate(&data));
+        if let Err(e) = validated {
+            if task.delete_on_validation_failure() {
+                info!(
+                    "{}: validation failed: deleting existing target",
+                    task.display_name()
+                );
+                target.delete()?;
+            }
+            return Err(e);
+        }
+        let permits = WRITE_PERMITS.with(|p| p.borrow().clone());
+        let _permit = permits.as_ref().map(|p| p.acquire());
+        target.write(&data)?;
+        if let Some(sidecar) = target.sidecar(SIGNATURE_SUFFIX) {
+            sidecar.write(sha256_hex(&data).as_bytes())?;
+        }
+        if let Some(sidecar) = target.sidecar(DEP_VERSIONS_SUFFIX) {
+            match dep_versions(task)? {
+                Some(versions) if !versions.is_empty() => {
+                    sidecar.write(&serde_json::to_vec(&versions)?)?
+                }
+                _ => sidecar.delete()?,
+            }
+        }
+        if let Some(checkpoint) = task.checkpoint()? {
+            checkpoint.clear()?;
+        }
+        if let Some(keep) = task.retention() {
+            let removed = target.retain_latest(keep)?;
+            if removed > 0 {
+                info!("{}: removed {} old outputs", task.display_name(), removed);
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete the task data and then the data of its dependencies, skipping tasks whose
+    /// target has already been visited. Targets are told apart by their uri, or by the
+    /// task's identity for targets without one.
+    fn recursively_delete<T: Task + ?Sized>(task: &T, visited: &mut HashSet<String>) -> Result<()> {
+        let key = match task.get_target()?.uri() {
+            Some(uri) => uri,
+            None => task.identity(),
+        };
+        if !visited.insert(key) {
+            return Ok(());
+        }
+        task.delete_data()?;
+        for (_, dep) in task.get_dep_tasks()? {
+            recursively_delete(dep.as_ref(), visited)?;
+        }
+        for (_, dep) in task.soft_dep_tasks()? {
+            recursively_delete(dep.as_ref(), visited)?;
+        }
+        Ok(())
+    }
+
+    /// The Task trait represents a piece of work with optional Task
+    /// dependencies. This is modeled after the python luigi module.
+    ///
+    /// The pattern is that you put any dependencies in get_dep_tasks,
+    /// set the target in get_target(), and fill in the compute_output() method,
+    /// where you can assume that dependencies' tasks are complete.
+    /// Then to run the task you just invoke the run() method. After run()
+    /// is invoked you can get the output using the self.get_data() method.
+    pub trait Task: fmt::Debug + Sync + Send {
+        /// Target for task output
+        fn get_target(&self) -> Result<Box<dyn Target>> {
+            Ok(Box::new(NullTarget {}))
+        }
+
+        /// The result of the task. This can use dependent task data as we will
+        /// ensure that these have been run. Don't call this directly unless you
+        /// want to bypass the cache system.
+        fn compute_output(&self) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+
+        /// Compute the output together with the target it should be written to, for tasks
+        /// that only know where their output goes once it's computed (e.g., a filename
+        /// derived from the data). Only used if target_from_compute is true. The default
+        /// pairs get_target with compute_output.
+        fn compute_with_target(&self) -> Result<(Box<dyn Target>, Vec<u8>)> {
+            Ok((self.get_target()?, self.compute_output()?))
+        }
+
+        /// If true, run and run_no_deps call compute_with_target every time and write the
+        /// output unless the returned target exists, instead of checking get_target first.
+        /// The scheduler (and tasks depending on this one) still use get_target, to
+        /// decide whether the node is done and to read the output, so return a target
+        /// that only exists once the output has been written (e.g., an index the
+        /// computed target is recorded in) or a NullTarget to always run.
+        fn target_from_compute(&self) -> bool {
+            false
+        }
+
+        /// Write the output to a sink (e.g., stdout or a socket) instead of the target.
+        /// By default this writes the compute_output bytes; override it to stream output
+        /// that is too large to hold in memory. Dependencies must already be done.
+        fn compute_to_writer(&self, w: &mut dyn Write) -> Result<()> {
+            w.write_all(&self.compute_output()?)?;
+            Ok(())
+        }
+
+        /// Return the data from the target cache. If the target cache does not
+        /// exist this will fail
+        fn get_data(&self) -> Result<Vec<u8>> {
+            self.output_target()?.read()
+        }
+
+        /// Settings shared with other tasks, see TaskProfile
+        fn profile(&self) -> TaskProfile {
+            TaskProfile::DEFAULT
+        }
+
+        /// get_target as run reads and writes it, i.e., wrapped by the profile (e.g., to
+        /// compress the output)
+        fn output_target(&self) -> Result<Box<dyn Target>> {
+            self.profile().wrap_target(self.get_target()?)
+        }
+
+        /// Run the task (i.e., recursively run dependent tasks) and return the
+        /// data from the target cache
+        fn run_and_get_data(&self) -> Result<Vec<u8>> {
+            self.run()?;
+            self.get_data()
+        }
+
+        /// Run the dependencies and return the output without writing it to the target,
+        /// leaving any existing cache untouched. Unlike run_and_get_data the output isn't
+        /// cached, but the dependencies still are.
+        fn compute_only(&self) -> Result<Vec<u8>> {
+            self.run_deps_only()?;
+            self.compute_output()
+        }
+
+        /// Recursively run the dependencies without computing this task's output, e.g.,
+        /// to prime the caches before running the task by hand. Failed soft dependencies
+        /// are logged and ignored.
+        fn run_deps_only(&self) -> Result<()> {
+            for (_, dep) in self.get_dep_tasks()? {
+                dep.run()?;
+            }
+            for (key, dep) in self.soft_dep_tasks()? {
+                if let Err(e) = dep.run() {
+                    warn!(
+                        "{}: soft dependency {} failed: {:?}",
+                        self.display_name(),
+                        key,
+                        e
+                    );
+                }
+            }
+            Ok(())
+        }
+
+        /// Task name, by default the name of the implementing type without its module
+        /// path or generic parameters (e.g., "Daily" for my_crate::Daily<u32>)
+        fn get_name(&self) -> String {
+            let name = std::any::type_name::<Self>();
+            let name = name.split('<').next().unwrap_or(name);
+            name.rsplit("::").next().unwrap_or(name).to_string()
+        }
+
+        /// Key identifying the task: a DAG holds one node per identity, so tasks that
+        /// several others depend on run once. Defaults to the Debug representation, so
+        /// instances of a type with different parameters are different tasks. Debug
+        /// shouldn't include state that changes while the DAG is built or run.
+        fn identity(&self) -> String {
+            format!("{:?}", self)
+        }
+
+        /// Checkpoint for compute_output to save progress to, by default in a sidecar of
+        /// the target (None if the target has no sidecars)
+        fn checkpoint(&self) -> Result<Option<Checkpoint>> {
+            Ok(self
+                .get_target()?
+                .sidecar(CHECKPOINT_SUFFIX)
+                .map(Checkpoint::new))
+        }
+
+        /// Key identifying the task's parameters, used by HashedFileTarget so that changing
+        /// a parameter doesn't reuse a stale cache. Defaults to the SHA-256 of the Debug
+        /// representation; tasks implementing Serialize can return json_cache_key(self)
+        /// instead.
+        fn cache_key(&self) -> Result<String> {
+            Ok(sha256_hex(format!("{:?}", self).as_bytes()))
+        }
+
+        /// Human readable description, shown in logs and DAG::to_dot instead of the name
+        fn description(&self) -> Option<String> {
+            None
+        }
+
+        /// Skip the task (and, in run, its dependencies) whatever the state of its target,
+        /// e.g., while a sentinel file says the pipeline is in maintenance mode. Checked
+        /// by run and run_no_deps before anything else, but not by run_forced.
+        fn should_skip(&self) -> Result<bool> {
+            Ok(false)
+        }
+
+        /// The description if there is one, otherwise the name
+        fn display_name(&self) -> String {
+            self.description().unwrap_or_else(|| self.get_name())
+        }
+
+        /// Dependencies, stored in a HashMap. These will be generated using the
+        /// run method. This is like the requires() method in luigi.
+        fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+            Ok(HashMap::new())
+        }
+
+        /// Dependent task targets
+        fn get_dep_targets(&self) -> Result<HashMap<String, Box<dyn Target>>> {
+            let mut result = HashMap::<String, Box<dyn Target>>::new();
+            for (k, task) in self.get_dep_tasks()? {
+                result.insert(k, task.output_target()?);
+            }
+            Ok(result)
+        }
+
+        /// Version of the output format written with encode_typed. Increase it when the
+        /// format changes so read_typed detects caches written by older code.
+        fn output_schema_version(&self) -> u32 {
+            0
+        }
+
+        /// Convert output (without its header) written at an older schema version to the
+        /// current format. By default old outputs are rejected with SchemaMismatch.
+        fn upgrade_output(&self, from_version: u32, _data: Vec<u8>) -> Result<Vec<u8>> {
+            Err(SchemaMismatch {
+                task: self.get_name(),
+                expected: self.output_schema_version(),
+                found: from_version,
+            }
+            .into())
+        }
+
+        /// Soft dependencies: enrichment-only tasks that are run before this task, but
+        /// whose failure is logged rather than stopping this task from running. Their
+        /// targets may be missing, so compute_output must check exists() before reading.
+        fn soft_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+            Ok(HashMap::new())
+        }
+
+        /// Soft dependency targets, which may not exist
+        fn soft_dep_targets(&self) -> Result<HashMap<String, Box<dyn Target>>> {
+            let mut result = HashMap::<String, Box<dyn Target>>::new();
+            for (k, task) in self.soft_dep_tasks()? {
+                result.insert(k, task.output_target()?);
+            }
+            Ok(result)
+        }
+
+        /// Validate the task
+        fn validate(&self, _data: &[u8]) -> Result<()> {
+            info!("{}: invoking validate", self.display_name());
+            Ok(())
+        }
+
+        /// If true, an existing target is deleted when newly computed output fails
+        /// validation, so downstream tasks don't read stale data. Errors computing
+        /// or writing the output leave the target untouched.
+        fn delete_on_validation_failure(&self) -> bool {
+            false
+        }
+
+        /// Number of dated outputs to keep: after each successful write the older ones
+        /// are deleted (see Target::retain_latest). None keeps them all. Defaults to the
+        /// profile's retention.
+        fn retention(&self) -> Option<usize> {
+            self.profile().retention
+        }
+
+        /// Number of times run and run_no_deps retry computing, validating and writing the
+        /// output after a failure, e.g., for tasks reading flaky remote resources.
+        /// Defaults to the retries of the profile's retry policy.
+        fn max_retries(&self) -> u32 {
+            self.profile().retry.max_attempts.saturating_sub(1)
+        }
+
+        /// Base delay between retries, doubling with each retry. Defaults to the base
+        /// delay of the profile's retry policy.
+        fn retry_backoff(&self) -> Duration {
+            self.profile().retry.base_delay
+        }
+
+        /// Retries of run and run_no_deps: the profile's retry policy, with max_retries
+        /// retries starting at retry_backoff. Override it for a full RetryPolicy, e.g.,
+        /// with jitter or only retrying some errors.
+        fn retry_policy(&self) -> RetryPolicy {
+            let mut policy = self.profile().retry;
+            policy.max_attempts = self.max_retries() + 1;
+            policy.base_delay = self.retry_backoff();
+            policy
+        }
+
+        /// Longest compute_output may take before run fails (each retry gets the full
+        /// timeout). None (the default) means no limit. With a timeout compute_output
+        /// runs on a detached thread, on the task's boxed_clone, which must return Some:
+        /// runs and DAG::new fail up front otherwise.
+        /// run fails as soon as the timeout passes, but threads can't be killed, so a
+        /// computation that overruns keeps going in the background until it returns,
+        /// and its output is discarded.
+        fn timeout(&self) -> Option<Duration> {
+            None
+        }
+
+        /// Owned copy of the task, for running compute_output on a detached thread (see
+        /// timeout). Tasks deriving Clone can return Some(Box::new(self.clone())).
+        fn boxed_clone(&self) -> Option<Box<dyn Task>> {
+            None
+        }
+
+        /// Tags for addressing groups of tasks, e.g., with DAG::run_tagged
+        fn tags(&self) -> Vec<String> {
+            Vec::new()
+        }
+
+        /// Relative cost of computing the task, e.g., for DAG::cache_status
+        fn weight(&self) -> f64 {
+            1.0
+        }
+
+        /// If true, the scheduler runs this task in a child process (see
+        /// PipelineRegistry::run_isolated_child), so a crash in e.g. a C library doesn't
+        /// take down the whole run. Without a pipeline to re-invoke it runs in process.
+        fn run_isolated(&self) -> bool {
+            false
+        }
+
+        /// If true, DAG::run_with_remote hands the task to its submit callback instead of
+        /// running it, and polls the target until it exists. Other runs ignore this.
+        fn is_remote(&self) -> bool {
+            false
+        }
+
+        /// When an existing target is recomputed, see RebuildPolicy
+        fn rebuild_policy(&self) -> RebuildPolicy {
+            RebuildPolicy::ExistsOnly
+        }
+
+        /// Is any of the deps newer than this task's target (make-style)? False if the
+        /// target doesn't exist or has no modification time, and deps without one are
+        /// ignored.
+        fn is_stale_relative_to(&self, deps: &[Box<dyn Target>]) -> Result<bool> {
+            let modified = match self.output_target()?.last_modified()? {
+                Some(modified) => modified,
+                None => return Ok(false),
+            };
+            for dep in deps {
+                if dep
+                    .last_modified()?
+                    .is_some_and(|dep_modified| dep_modified > modified)
+                {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+
+        /// This method recursively generates dependent data, and then calls
+        /// compute_output for the Task. If the target exists the output is only
+        /// recomputed when the dependency target versions differ from the ones
+        /// recorded when it was last computed, or the rebuild policy says so.
+        fn run(&self) -> Result<()> {
+            info!("{}: invoking run()", self.display_name());
+            check_timeout(self)?;
+            if self.should_skip()? {
+                info!("{}: should_skip() is true: skipping", self.display_name());
+                return Ok(());
+            }
+            // recursively run dependent tasks
+            self.run_deps_only()?;
+            if self.target_from_compute() {
+                return materialize_computed_target(self, false);
+            }
+            // run compute_output() if the target doesn't exist
+            let target = self.output_target()?;
+            if !target.exists()? {
+                info!(
+                    "{}: target does not exist: invoking compute_output()",
+                    self.display_name()
+                );
+                materialize(self, target.as_ref())?;
+            } else if deps_changed(self, target.as_ref())? {
+                info!(
+                    "{}: dependencies changed: invoking compute_output()",
+                    self.display_name()
+                );
+                materialize(self, target.as_ref())?;
+            } else if deps_newer(self)? {
+                info!(
+                    "{}: dependencies are newer than the target: invoking compute_output()",
+                    self.display_name()
+                );
+                materialize(self, target.as_ref())?;
+            } else {
+                info!("{}: target exists", self.display_name());
+            }
+            Ok(())
+        }
+
+        /// Non-dependent run: just save get_data() to get_target(). This will fail if required
+        /// dependencies are not present. For regular use just call run(). This method is used in the
+        /// scheduler run method as dependencies are handled in the code there.
+        fn run_no_deps(&self) -> Result<()> {
+            info!("{}: invoking run_no_deps()", self.display_name());
+            check_timeout(self)?;
+            if self.should_skip()? {
+                info!("{}: should_skip() is true: skipping", self.display_name());
+                return Ok(());
+            }
+            if self.target_from_compute() {
+                return materialize_computed_target(self, false);
+            }
+            let target = self.output_target()?;
+            if !target.exists()? || deps_changed(self, target.as_ref())? || deps_newer(self)? {
+                info!(
+                    "{}: invoking compute_output() without running dependencies",
+                    self.display_name()
+                );
+                materialize(self, target.as_ref())?;
+            }
+            Ok(())
+        }
+
+        /// Like run(), but ignores existing targets: the dependencies are run forced and
+        /// the output is always computed, validated and written over the old one in place
+        /// (it isn't deleted first, so readers never see it missing). Dependencies shared
+        /// by several tasks are recomputed once per path to them.
+        fn run_forced(&self) -> Result<()> {
+            info!("{}: invoking run_forced()", self.display_name());
+            check_timeout(self)?;
+            for (_, dep) in self.get_dep_tasks()? {
+                dep.run_forced()?;
+            }
+            for (key, dep) in self.soft_dep_tasks()? {
+                if let Err(e) = dep.run_forced() {
+                    warn!(
+                        "{}: soft dependency {} failed: {:?}",
+                        self.display_name(),
+                        key,
+                        e
+                    );
+                }
+            }
+            self.run_forced_no_deps()
+        }
+
+        /// Non-dependent run_forced(): compute and write the output whether or not the
+        /// target exists. Used by the scheduler for forced runs.
+        fn run_forced_no_deps(&self) -> Result<()> {
+            check_timeout(self)?;
+            if self.target_from_compute() {
+                return materialize_computed_target(self, true);
+            }
+            materialize(self, self.output_target()?.as_ref())
+        }
+
+        /// Compute the output again (without writing it) and compare it with the cached
+        /// output, e.g., to catch non-determinism or drift. Dependencies must be done.
+        fn verify_cache(&self) -> Result<CacheVerification> {
+            let cached_hash = match self.output_target()?.content_hash()? {
+                Some(hash) => hash,
+                None => return Ok(CacheVerification::Absent),
+            };
+            let fresh_hash = sha256_hex(&self.compute_output()?);
+            if fresh_hash == cached_hash {
+                Ok(CacheVerification::Matches)
+            } else {
+                Ok(CacheVerification::Differs {
+                    cached_hash,
+                    fresh_hash,
+                })
+            }
+        }
+
+        /// SHA-256 (hex) of the output written by the last run, read from the signature
+        /// sidecar rather than hashing the target. None if the target doesn't exist or
+        /// doesn't support sidecars. Downstream tasks can record these signatures and
+        /// compare them to detect upstream changes cheaply.
+        fn last_output_signature(&self) -> Result<Option<String>> {
+            let target = self.output_target()?;
+            if !target.exists()? {
+                return Ok(None);
+            }
+            match target.sidecar(SIGNATURE_SUFFIX) {
+                Some(sidecar) if sidecar.exists()? => Ok(Some(String::from_utf8(sidecar.read()?)?)),
+                _ => Ok(None),
+            }
+        }
+
+        /// Delete target data, along with the signature, dependency versions and
+        /// checkpoint sidecars that run keeps next to it
+        fn delete_data(&self) -> Result<()> {
+            info!("{}: invoking delete_data()", self.display_name());
+            let target = self.get_target()?;
+            target.delete()?;
+            for suffix in [SIGNATURE_SUFFIX, DEP_VERSIONS_SUFFIX, CHECKPOINT_SUFFIX] {
+                if let Some(sidecar) = target.sidecar(suffix) {
+                    sidecar.delete()?;
+                }
+            }
+            Ok(())
+        }
+
+        /// Non-recursively delete dependencies, i.e., delete task outputs for
+        /// dependent tasks
+        fn delete_deps(&self) -> Result<()> {
+            info!("{}: invoking delete_deps()", self.display_name());
+            for (_, dep) in self.get_dep_targets()? {
+                dep.delete()?;
+            }
+            Ok(())
+        }
+
+        /// Recursively delete dependencies, i.e., delete task outputs for
+        /// dependent tasks and their dependencies as well. Dependencies shared by
+        /// several tasks are only deleted once.
+        fn recursively_delete_data(&self) -> Result<()> {
+            info!(
+                "{}: invoking recursively_delete_data()",
+                self.display_name()
+            );
+            recursively_delete(self, &mut HashSet::new())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+    extern crate serde;
+    use anyhow::{anyhow, Result};
+    use serde::{Deserialize, Serialize};
+
+    use crate::tasks::{
+        encode_with_profile, json_cache_key, read_with_profile, AtomicFileTarget,
+        DateTimeFileTarget, DatedFileTarget, FileTarget, HashedFileTarget, InMemoryTarget,
+        RebuildPolicy, SeriesValidator, SeriesViolation, Target, TargetConfig, Task, TaskProfile,
+    };
+
+    #[test]
+    fn file_target() {
+        let ft = FileTarget::new("/tmp", "test_target.txt");
+        ft.delete().unwrap();
+        assert!(!ft.exists().expect("exists failed"));
+        ft.write("test data".as_bytes()).unwrap();
+        assert!(ft.exists().expect("exists failed"));
+        assert_eq!(ft.read().unwrap(), "test data".as_bytes().to_vec());
+    }
+
+    #[test]
+    fn file_target_buffered_writer() {
+        use std::io::Write;
+
+        let ft = FileTarget::new("/tmp", "test_buffered_writer_target.txt");
+        let mut expected: Vec<u8> = Vec::new();
+        let mut writer = ft.buffered_writer().unwrap();
+        for i in 0..10_000 {
+            let chunk = format!("{},", i);
+            writer.write_all(chunk.as_bytes()).unwrap();
+            expected.extend(chunk.as_bytes());
+        }
+        writer.flush().unwrap();
+        drop(writer);
+        assert_eq!(ft.read().unwrap(), expected);
+    }
+
+    #[test]
+    fn file_target_read_tail() {
+        let ft = FileTarget::new("/tmp", "test_read_tail_target.txt");
+        ft.write("0123456789".as_bytes()).unwrap();
+        assert_eq!(ft.read_tail(4).unwrap(), "6789".as_bytes().to_vec());
+        assert_eq!(ft.read_tail(0).unwrap(), Vec::<u8>::new());
+        // asking for more than the file holds returns the whole content
+        assert_eq!(ft.read_tail(100).unwrap(), "0123456789".as_bytes().to_vec());
+        // the default implementation slices the result of read()
+        let dft = DatedFileTarget::new(
+            "/tmp",
+            "test_read_tail_target.txt",
+            chrono::NaiveDate::from_ymd(2021, 9, 3),
+        );
+        dft.write("0123456789".as_bytes()).unwrap();
+        assert_eq!(dft.read_tail(4).unwrap(), "6789".as_bytes().to_vec());
+        assert_eq!(
+            dft.read_tail(100).unwrap(),
+            "0123456789".as_bytes().to_vec()
+        );
+    }
+
+    #[test]
+    fn file_target_streams() {
+        use std::io::{self, Read};
+
+        /// Yields some data, then fails
+        struct Failing {
+            sent: bool,
+        }
+        impl Read for Failing {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.sent {
+                    return Err(io::Error::other("connection reset"));
+                }
+                self.sent = true;
+                buf[..4].copy_from_slice(b"half");
+                Ok(4)
+            }
+        }
+
+        let dir = "/tmp/test_stream_target";
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        let ft = FileTarget::new(dir, "streamed.txt");
+        let data = "0123456789".repeat(10_000);
+        ft.write_stream(&mut data.as_bytes()).unwrap();
+        let mut read = String::new();
+        ft.read_stream().unwrap().read_to_string(&mut read).unwrap();
+        assert_eq!(read, data);
+
+        // a failed write leaves the previous data, and no partial file
+        assert!(ft.write_stream(&mut Failing { sent: false }).is_err());
+        assert_eq!(ft.read().unwrap(), data.as_bytes().to_vec());
+        assert_eq!(std::fs::read_dir(dir).unwrap().count(), 1);
+
+        // the defaults bridge to read and write
+        let dft =
+            DatedFileTarget::new(dir, "streamed.txt", chrono::NaiveDate::from_ymd(2021, 9, 3));
+        dft.write_stream(&mut "dated".as_bytes()).unwrap();
+        let mut read = Vec::new();
+        dft.read_stream().unwrap().read_to_end(&mut read).unwrap();
+        assert_eq!(read, "dated".as_bytes().to_vec());
+    }
+
+    #[test]
+    fn task_profile() {
+        use crate::{resilience::RetryPolicy, targets::GzipTarget, tasks::Codec};
+        use std::time::Duration;
+
+        /// Org-wide limit on the size of an output
+        fn at_most_64_bytes(data: &[u8]) -> Result<()> {
+            if data.len() <= 64 {
+                Ok(())
+            } else {
+                Err(anyhow!("output is {} bytes", data.len()))
+            }
+        }
+
+        const COMPACT: TaskProfile = TaskProfile {
+            codec: Codec::MessagePack,
+            validators: &[at_most_64_bytes],
+            retry: RetryPolicy::new(3, Duration::from_millis(1)),
+            compression: Some(9),
+            ..TaskProfile::DEFAULT
+        };
+
+        #[derive(Debug)]
+        struct Readings(usize);
+        impl Task for Readings {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new("/tmp", "test_profile_readings")))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                encode_with_profile(self, &vec![1.5f64; self.0])
+            }
+
+            fn profile(&self) -> TaskProfile {
+                COMPACT
+            }
+        }
+
+        #[derive(Debug)]
+        struct Labels(usize);
+        impl Task for Labels {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new("/tmp", "test_profile_labels")))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                encode_with_profile(self, &vec!["label"; self.0])
+            }
+
+            fn profile(&self) -> TaskProfile {
+                COMPACT
+            }
+        }
+
+        let (readings, labels) = (Readings(3), Labels(3));
+        for task in [&readings as &dyn Task, &labels] {
+            assert_eq!(task.max_retries(), 2);
+            assert_eq!(task.retry_policy().base_delay, Duration::from_millis(1));
+        }
+        readings.delete_data().unwrap();
+        labels.delete_data().unwrap();
+        readings.run().unwrap();
+        labels.run().unwrap();
+        let values: Vec<f64> = read_with_profile(&readings).unwrap();
+        assert_eq!(values, vec![1.5; 3]);
+        let values: Vec<String> = read_with_profile(&labels).unwrap();
+        assert_eq!(values, vec!["label"; 3]);
+
+        // both are stored gzipped in the shared codec
+        for (task, expected) in [
+            (
+                &readings as &dyn Task,
+                Codec::MessagePack.encode(&vec![1.5f64; 3]).unwrap(),
+            ),
+            (
+                &labels,
+                Codec::MessagePack.encode(&vec!["label"; 3]).unwrap(),
+            ),
+        ] {
+            let raw = task.get_target().unwrap();
+            assert!(raw.read().unwrap().starts_with(&[0x1f, 0x8b]));
+            assert_eq!(GzipTarget::new(raw).read().unwrap(), expected);
+        }
+
+        // and both are held to the shared validator
+        for task in [&Readings(20) as &dyn Task, &Labels(20)] {
+            task.delete_data().unwrap();
+            assert!(task.run().is_err());
+            assert!(!task.get_target().unwrap().exists().unwrap());
+        }
+    }
+
+    #[test]
+    fn checkpoint() {
+        /// Sums 1..=10 one step at a time, checkpointing the step and sum after each, and
+        /// crashing after crash_after steps
+        #[derive(Debug)]
+        struct LongSum {
+            crash_after: Option<usize>,
+            steps_run: AtomicUsize,
+        }
+        impl Task for LongSum {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new("/tmp", "test_checkpoint_sum.txt")))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                let checkpoint = self.checkpoint()?.unwrap();
+                let (mut step, mut sum): (usize, usize) = match checkpoint.load()? {
+                    Some(state) => serde_json::from_slice(&state)?,
+                    None => (0, 0),
+                };
+                while step < 10 {
+                    if Some(self.steps_run.load(Ordering::SeqCst)) == self.crash_after {
+                        return Err(anyhow!("crashed at step {}", step));
+                    }
+                    step += 1;
+                    sum += step;
+                    self.steps_run.fetch_add(1, Ordering::SeqCst);
+                    checkpoint.save(&serde_json::to_vec(&(step, sum))?)?;
+                }
+                Ok(sum.to_string().into_bytes())
+            }
+        }
+
+        let crashing = LongSum {
+            crash_after: Some(4),
+            steps_run: AtomicUsize::new(0),
+        };
+        crashing.delete_data().unwrap();
+        crashing.checkpoint().unwrap().unwrap().clear().unwrap();
+        assert!(crashing.run().is_err());
+        let saved = crashing.checkpoint().unwrap().unwrap().load().unwrap();
+        assert_eq!(saved, Some("[4,10]".as_bytes().to_vec()));
+
+        // the restarted task only runs the remaining steps, then clears the checkpoint
+        let restarted = LongSum {
+            crash_after: None,
+            steps_run: AtomicUsize::new(0),
+        };
+        restarted.run().unwrap();
+        assert_eq!(restarted.steps_run.load(Ordering::SeqCst), 6);
+        assert_eq!(restarted.get_data().unwrap(), "55".as_bytes().to_vec());
+        assert_eq!(
+            restarted.checkpoint().unwrap().unwrap().load().unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn hashed_file_target() {
+        #[derive(Debug, Serialize)]
+        struct Scaled {
+            factor: f64,
+        }
+        impl Task for Scaled {
+            fn get_name(&self) -> String {
+                "Scaled".to_string()
+            }
+
+            fn cache_key(&self) -> Result<String> {
+                json_cache_key(self)
+            }
+
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(HashedFileTarget::for_task(
+                    "/tmp",
+                    "test_hashed_scaled.txt",
+                    self,
+                )?))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok((10.0 * self.factor).to_string().into_bytes())
+            }
+        }
+
+        let (double, triple) = (Scaled { factor: 2.0 }, Scaled { factor: 3.0 });
+        let key = json_cache_key(&double).unwrap();
+        assert_eq!(key.len(), 64);
+        assert_ne!(key, triple.cache_key().unwrap());
+        let filename = HashedFileTarget::for_task("/tmp", "test_hashed_scaled.txt", &double)
+            .unwrap()
+            .filename();
+        assert_eq!(
+            filename.to_str().unwrap(),
+            format!("/tmp/test_hashed_scaled_{}.txt", &key[..16])
+        );
+
+        // the second task doesn't pick up the first one's output
+        double.delete_data().unwrap();
+        triple.delete_data().unwrap();
+        double.run().unwrap();
+        assert!(!triple.get_target().unwrap().exists().unwrap());
+        triple.run().unwrap();
+        assert_eq!(double.get_data().unwrap(), "20".as_bytes().to_vec());
+        assert_eq!(triple.get_data().unwrap(), "30".as_bytes().to_vec());
+
+        // keys are cut by characters, so a multi-byte one at the boundary is kept whole
+        let target = HashedFileTarget::new("/tmp", "data.txt", "fifteen_chars__é_rest");
+        assert_eq!(
+            target.filename().to_str().unwrap(),
+            "/tmp/data_fifteen_chars__é.txt"
+        );
+    }
+
+    #[test]
+    fn atomic_file_target() {
+        let dir = "/tmp/test_atomic_file_target";
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        let stray_files = || {
+            std::fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+                .filter(|name| name.contains(".tmp"))
+                .collect::<Vec<_>>()
+        };
+
+        let target = AtomicFileTarget::new(dir, "data.txt");
+        assert!(!target.exists().unwrap());
+        target.write("first".as_bytes()).unwrap();
+        target.write("second".as_bytes()).unwrap();
+        assert!(target.exists().unwrap());
+        assert_eq!(target.read().unwrap(), "second".as_bytes().to_vec());
+        assert!(stray_files().is_empty());
+
+        // the write fails as the directory doesn't exist
+        let missing = AtomicFileTarget::new(&format!("{}/missing", dir), "data.txt");
+        assert!(missing.write("data".as_bytes()).is_err());
+        assert!(!missing.exists().unwrap());
+        assert!(stray_files().is_empty());
+
+        target.delete().unwrap();
+        assert!(!target.exists().unwrap());
+    }
+
+    #[test]
+    fn read_json() {
+        #[derive(Serialize)]
+        struct Config {
+            name: String,
+            limits: Vec<u32>,
+        }
+
+        let ft = FileTarget::new("/tmp", "test_read_json.json");
+        let config = Config {
+            name: "nightly".to_string(),
+            limits: vec![10, 20],
+        };
+        ft.write(&serde_json::to_vec(&config).unwrap()).unwrap();
+        let value = ft.read_json().unwrap();
+        assert_eq!(value["name"], "nightly");
+        assert_eq!(value["limits"][1], 20);
+
+        ft.write("not json".as_bytes()).unwrap();
+        let err = ft.read_json().unwrap_err().to_string();
+        assert!(err.contains("file:///tmp/test_read_json.json"), "{}", err);
+    }
+
+    #[test]
+    fn content_hash() {
+        let ft1 = FileTarget::new("/tmp", "test_content_hash_1.txt");
+        let ft2 = FileTarget::new("/tmp", "test_content_hash_2.txt");
+        let ft3 = FileTarget::new("/tmp", "test_content_hash_3.txt");
+        ft1.write("same data".as_bytes()).unwrap();
+        ft2.write("same data".as_bytes()).unwrap();
+        ft3.write("other data".as_bytes()).unwrap();
+        let hash1 = ft1.content_hash().unwrap().expect("missing hash");
+        assert_eq!(hash1.len(), 64);
+        assert_eq!(ft2.content_hash().unwrap(), Some(hash1.clone()));
+        assert_ne!(ft3.content_hash().unwrap(), Some(hash1));
+
+        ft3.delete().unwrap();
+        assert_eq!(ft3.content_hash().unwrap(), None);
+    }
+
+    #[test]
+    fn output_signature() {
+        #[derive(Debug)]
+        struct Signed {
+            data: &'static str,
+        }
+        impl Task for Signed {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new(
+                    "/tmp",
+                    "test_output_signature.txt",
+                )))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok(self.data.as_bytes().to_vec())
+            }
+        }
+
+        let task = Signed { data: "first" };
+        task.delete_data().unwrap();
+        assert_eq!(task.last_output_signature().unwrap(), None);
+        task.run().unwrap();
+        let signature = task
+            .last_output_signature()
+            .unwrap()
+            .expect("missing signature");
+        assert_eq!(
+            Some(signature.clone()),
+            task.get_target().unwrap().content_hash().unwrap()
+        );
+        let sidecar = FileTarget::new("/tmp", "test_output_signature.txt.sig");
+        assert_eq!(sidecar.read().unwrap(), signature.as_bytes().to_vec());
+
+        // new output, new signature
+        let task = Signed { data: "second" };
+        task.delete_data().unwrap();
+        task.run().unwrap();
+        let new_signature = task.last_output_signature().unwrap().unwrap();
+        assert_ne!(new_signature, signature);
+        assert_eq!(
+            Some(new_signature),
+            task.get_target().unwrap().content_hash().unwrap()
+        );
+
+        // the signature is of the target run writes to
+        #[derive(Debug)]
+        struct Redirected {}
+        impl Task for Redirected {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new(
+                    "/tmp",
+                    "test_output_signature_unused.txt",
+                )))
+            }
+
+            fn output_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new(
+                    "/tmp",
+                    "test_output_signature_redirected.txt",
+                )))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok("redirected".as_bytes().to_vec())
+            }
+        }
+        let task = Redirected {};
+        task.delete_data().unwrap();
+        task.output_target().unwrap().delete().unwrap();
+        task.run().unwrap();
+        assert!(!task.get_target().unwrap().exists().unwrap());
+        assert_eq!(
+            task.last_output_signature().unwrap(),
+            task.output_target().unwrap().content_hash().unwrap()
+        );
+        assert!(task.last_output_signature().unwrap().is_some());
+    }
+
+    #[test]
+    fn default_name() {
+        #[derive(Debug)]
+        struct Prices {}
+        impl Task for Prices {
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok(Vec::new())
+            }
+        }
+
+        #[derive(Debug)]
+        struct Volumes<T> {
+            unit: T,
+        }
+        impl<T: std::fmt::Debug + Send + Sync> Task for Volumes<T> {
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok(format!("{:?}", self.unit).into_bytes())
+            }
+        }
+
+        assert_eq!(Prices {}.get_name(), "Prices");
+        // through a trait object the default still sees the concrete type
+        let boxed: Box<dyn Task> = Box::new(Volumes { unit: 1u32 });
+        assert_eq!(boxed.get_name(), "Volumes");
+    }
+
+    #[test]
+    fn task_timeout() {
+        use std::time::Duration;
+
+        #[derive(Debug, Clone)]
+        struct Slow {
+            sleep: Duration,
+        }
+        impl Task for Slow {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new("/tmp", "test_task_timeout.txt")))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                std::thread::sleep(self.sleep);
+                Ok("slow data".as_bytes().to_vec())
+            }
+
+            fn timeout(&self) -> Option<Duration> {
+                Some(Duration::from_millis(50))
+            }
+
+            fn boxed_clone(&self) -> Option<Box<dyn Task>> {
+                Some(Box::new(self.clone()))
+            }
+        }
+
+        let slow = Slow {
+            sleep: Duration::from_secs(2),
+        };
+        slow.delete_data().unwrap();
+        let start = std::time::Instant::now();
+        let err = slow.run().unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+        // run doesn't wait for the computation to finish
+        assert!(
+            start.elapsed() < Duration::from_secs(1),
+            "{:?}",
+            start.elapsed()
+        );
+        // the late output isn't written
+        assert!(!slow.get_target().unwrap().exists().unwrap());
+
+        let fast = Slow {
+            sleep: Duration::from_millis(1),
+        };
+        fast.run().unwrap();
+        assert_eq!(fast.get_data().unwrap(), "slow data".as_bytes().to_vec());
+
+        // a timeout without boxed_clone is rejected before computing or retrying
+        #[derive(Debug)]
+        struct Unclonable {
+            computed: Arc<AtomicUsize>,
+        }
+        impl Task for Unclonable {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new(
+                    "/tmp",
+                    "test_task_timeout_unclonable.txt",
+                )))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                self.computed.fetch_add(1, Ordering::SeqCst);
+                Ok("data".as_bytes().to_vec())
+            }
+
+            fn timeout(&self) -> Option<Duration> {
+                Some(Duration::from_millis(50))
+            }
+
+            fn max_retries(&self) -> u32 {
+                2
+            }
+        }
+        let computed = Arc::new(AtomicUsize::new(0));
+        let unclonable = Unclonable {
+            computed: computed.clone(),
+        };
+        unclonable.delete_data().unwrap();
+        for res in [
+            unclonable.run(),
+            unclonable.run_no_deps(),
+            unclonable.run_forced(),
+        ] {
+            assert!(res.unwrap_err().to_string().contains("boxed_clone"));
+        }
+        assert_eq!(computed.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn run_forced() {
+        #[derive(Debug)]
+        struct Dep {}
+        impl Task for Dep {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new("/tmp", "test_run_forced_dep.txt")))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok("fresh dep".as_bytes().to_vec())
+            }
+        }
+
+        #[derive(Debug)]
+        struct Forced {}
+        impl Task for Forced {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new("/tmp", "test_run_forced.txt")))
+            }
+
+            fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                let mut deps = HashMap::<String, Box<dyn Task>>::new();
+                deps.insert("dep".to_string(), Box::new(Dep {}));
+                Ok(deps)
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok("fresh data".as_bytes().to_vec())
+            }
+        }
+
+        let task = Forced {};
+        task.recursively_delete_data().unwrap();
+        Dep {}.get_target().unwrap().write(b"stale dep").unwrap();
+        task.get_target().unwrap().write(b"stale data").unwrap();
+        // a normal run keeps the existing targets
+        task.run().unwrap();
+        assert_eq!(task.get_data().unwrap(), b"stale data".to_vec());
+
+        task.run_forced().unwrap();
+        assert_eq!(task.get_data().unwrap(), b"fresh data".to_vec());
+        assert_eq!(Dep {}.get_data().unwrap(), b"fresh dep".to_vec());
+    }
+
+    #[test]
+    fn task_retries() {
+        use crate::resilience::RetryPolicy;
+        use std::{
+            sync::atomic::{AtomicUsize, Ordering},
+            time::Duration,
+        };
+
+        #[derive(Debug)]
+        struct Flaky {
+            name: &'static str,
+            calls: AtomicUsize,
+            max_retries: u32,
+            // fail validation instead of compute_output
+            invalid: bool,
+        }
+        impl Task for Flaky {
+            fn get_name(&self) -> String {
+                self.name.to_string()
+            }
+
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new(
+                    "/tmp",
+                    &format!("test_{}.txt", self.name),
+                )))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+                match call {
+                    1 | 2 if self.invalid => Ok("invalid".as_bytes().to_vec()),
+                    1 | 2 => Err(anyhow!("call {} failed", call)),
+                    _ => Ok("flaky data".as_bytes().to_vec()),
+                }
+            }
+
+            fn validate(&self, data: &[u8]) -> Result<()> {
+                if data == "flaky data".as_bytes() {
+                    Ok(())
+                } else {
+                    Err(anyhow!("invalid output"))
+                }
+            }
+
+            fn max_retries(&self) -> u32 {
+                self.max_retries
+            }
+
+            fn retry_backoff(&self) -> Duration {
+                Duration::from_millis(1)
+            }
+        }
+
+        let flaky = |name, max_retries, invalid| Flaky {
+            name,
+            calls: AtomicUsize::new(0),
+            max_retries,
+            invalid,
+        };
+        for invalid in [false, true] {
+            let task = flaky("task_retries", 3, invalid);
+            task.delete_data().unwrap();
+            task.run().expect("run failed despite retries");
+            assert_eq!(task.calls.load(Ordering::SeqCst), 3);
+            assert_eq!(task.get_data().unwrap(), "flaky data".as_bytes().to_vec());
+        }
+
+        // the last error is returned once the retries run out
+        let task = flaky("task_retries_exhausted", 1, false);
+        task.delete_data().unwrap();
+        let err = task.run().unwrap_err();
+        assert_eq!(err.to_string(), "call 2 failed");
+        assert_eq!(task.calls.load(Ordering::SeqCst), 2);
+        assert!(!task.get_target().unwrap().exists().unwrap());
+
+        // a task's own retry policy decides which errors are retried
+        #[derive(Debug)]
+        struct Picky(Flaky);
+        impl Task for Picky {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                self.0.get_target()
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                self.0.compute_output()
+            }
+
+            fn retry_policy(&self) -> RetryPolicy {
+                RetryPolicy::new(5, Duration::from_millis(1))
+                    .with_retryable(|e| e.to_string() != "call 2 failed")
+            }
+        }
+        let task = Picky(flaky("task_retries_picky", 0, false));
+        task.delete_data().unwrap();
+        let err = task.run().unwrap_err();
+        assert_eq!(err.to_string(), "call 2 failed");
+        assert_eq!(task.0.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn compute_with_target() {
+        /// Writes its data to a file named after the data's hash
+        #[derive(Debug)]
+        struct ContentNamed {
+            data: &'static str,
+        }
+        impl ContentNamed {
+            fn target_for(data: &[u8]) -> FileTarget {
+                let hash = crate::tasks::sha256_hex(data);
+                FileTarget::new("/tmp", &format!("test_computed_target_{}.txt", &hash[..12]))
+            }
+        }
+        impl Task for ContentNamed {
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok(self.data.as_bytes().to_vec())
+            }
+
+            fn compute_with_target(&self) -> Result<(Box<dyn Target>, Vec<u8>)> {
+                let data = self.compute_output()?;
+                Ok((Box::new(ContentNamed::target_for(&data)), data))
+            }
+
+            fn target_from_compute(&self) -> bool {
+                true
+            }
+        }
+
+        let first = ContentNamed { data: "first" };
+        let second = ContentNamed { data: "second" };
+        for task in [&first, &second] {
+            ContentNamed::target_for(task.data.as_bytes())
+                .delete()
+                .unwrap();
+        }
+        first.run().unwrap();
+        second.run_no_deps().unwrap();
+        for task in [&first, &second] {
+            let target = ContentNamed::target_for(task.data.as_bytes());
+            assert_eq!(target.read().unwrap(), task.data.as_bytes().to_vec());
+        }
+        // an existing computed target isn't rewritten
+        let target = ContentNamed::target_for("first".as_bytes());
+        let version = target.version().unwrap();
+        first.run().unwrap();
+        assert_eq!(target.version().unwrap(), version);
+    }
+
+    #[test]
+    fn compute_to_writer() {
+        #[derive(Debug)]
+        struct Lines {}
+        impl Task for Lines {
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                let mut data = Vec::new();
+                self.compute_to_writer(&mut data)?;
+                Ok(data)
+            }
+
+            fn compute_to_writer(&self, w: &mut dyn std::io::Write) -> Result<()> {
+                for i in 0..1000 {
+                    writeln!(w, "line {}", i)?;
+                }
+                Ok(())
+            }
+        }
+
+        /// Records the size of each write
+        #[derive(Default)]
+        struct Sink {
+            data: Vec<u8>,
+            writes: usize,
+        }
+        impl std::io::Write for Sink {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.writes += 1;
+                self.data.extend(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let task = Lines {};
+        let mut sink = Sink::default();
+        task.compute_to_writer(&mut sink).unwrap();
+        assert!(sink.writes >= 1000);
+        assert_eq!(sink.data, task.compute_output().unwrap());
+
+        // the default implementation writes compute_output
+        #[derive(Debug)]
+        struct Simple {}
+        impl Task for Simple {
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok("simple data".as_bytes().to_vec())
+            }
+        }
+        let mut data = Vec::new();
+        Simple {}.compute_to_writer(&mut data).unwrap();
+        assert_eq!(data, "simple data".as_bytes().to_vec());
+    }
+
+    #[test]
+    fn clean_sidecars() {
+        use crate::tasks::clean_sidecars;
+        use std::{
+            fs,
+            path::Path,
+            time::{Duration, SystemTime},
+        };
+
+        let dir = "/tmp/test_clean_sidecars";
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        for name in [
+            "data.txt",
+            "data.txt.sig",
+            "data.txt.deps",
+            "orphan.txt.sig",
+            "locked.txt",
+            "locked.txt.lock",
+            "crashed.txt",
+            "crashed.txt.lock",
+            "other.json",
+        ] {
+            fs::write(Path::new(dir).join(name), "x").unwrap();
+        }
+        let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(Path::new(dir).join("crashed.txt.lock"))
+            .unwrap()
+            .set_modified(hour_ago)
+            .unwrap();
+
+        let expected = vec![
+            Path::new(dir).join("crashed.txt.lock"),
+            Path::new(dir).join("orphan.txt.sig"),
+        ];
+        let stale = clean_sidecars(dir, Duration::from_secs(60), false).unwrap();
+        assert_eq!(stale, expected);
+        assert!(expected.iter().all(|path| path.exists()));
+
+        assert_eq!(
+            clean_sidecars(dir, Duration::from_secs(60), true).unwrap(),
+            expected
+        );
+        assert!(expected.iter().all(|path| !path.exists()));
+        assert!(clean_sidecars(dir, Duration::from_secs(60), false)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn should_skip() {
+        #[derive(Debug)]
+        struct Maintenance {
+            skip: bool,
+            calls: Arc<AtomicUsize>,
+        }
+        impl Task for Maintenance {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new("/tmp", "test_should_skip.txt")))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                Ok("maintenance data".as_bytes().to_vec())
+            }
+
+            fn should_skip(&self) -> Result<bool> {
+                Ok(self.skip)
+            }
+        }
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let skipped = Maintenance {
+            skip: true,
+            calls: calls.clone(),
+        };
+        skipped.delete_data().unwrap();
+        skipped.run().unwrap();
+        skipped.run_no_deps().unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert!(!skipped.get_target().unwrap().exists().unwrap());
+
+        let task = Maintenance {
+            skip: false,
+            calls: calls.clone(),
+        };
+        task.run().unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn verify_cache() {
+        use crate::tasks::CacheVerification;
+
+        #[derive(Debug)]
+        struct Counter {
+            filename: &'static str,
+            deterministic: bool,
+            calls: Arc<AtomicUsize>,
+        }
+        impl Task for Counter {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new("/tmp", self.filename)))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                let calls = self.calls.fetch_add(1, Ordering::SeqCst);
+                if self.deterministic {
+                    Ok("same".as_bytes().to_vec())
+                } else {
+                    Ok(calls.to_string().into_bytes())
+                }
+            }
+        }
+
+        let deterministic = Counter {
+            filename: "test_verify_cache_deterministic.txt",
+            deterministic: true,
+            calls: Arc::new(AtomicUsize::new(0)),
+        };
+        deterministic.delete_data().unwrap();
+        assert_eq!(
+            deterministic.verify_cache().unwrap(),
+            CacheVerification::Absent
+        );
+        deterministic.run().unwrap();
+        assert_eq!(
+            deterministic.verify_cache().unwrap(),
+            CacheVerification::Matches
+        );
+
+        let nondeterministic = Counter {
+            filename: "test_verify_cache_nondeterministic.txt",
+            deterministic: false,
+            calls: Arc::new(AtomicUsize::new(0)),
+        };
+        nondeterministic.delete_data().unwrap();
+        nondeterministic.run().unwrap();
+        match nondeterministic.verify_cache().unwrap() {
+            CacheVerification::Differs {
+                cached_hash,
+                fresh_hash,
+            } => assert_ne!(cached_hash, fresh_hash),
+            other => panic!("unexpected verification {:?}", other),
+        }
+        // the cache isn't overwritten
+        assert_eq!(
+            nondeterministic.get_data().unwrap(),
+            "0".as_bytes().to_vec()
+        );
+    }
+
+    #[test]
+    fn compute_only() {
+        #[derive(Debug)]
+        struct Dep {}
+        impl Task for Dep {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new(
+                    "/tmp",
+                    "test_compute_only_dep.txt",
+                )))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok("dep data".as_bytes().to_vec())
+            }
+        }
+
+        #[derive(Debug)]
+        struct Explore {}
+        impl Task for Explore {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new("/tmp", "test_compute_only.txt")))
+            }
+
+            fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                let mut result = HashMap::<String, Box<dyn Task>>::new();
+                result.insert("dep".to_string(), Box::new(Dep {}));
+                Ok(result)
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                let mut data = self.get_dep_targets()?["dep"].read()?;
+                data.extend(" - explored".as_bytes());
+                Ok(data)
+            }
+        }
+
+        let task = Explore {};
+        task.recursively_delete_data().unwrap();
+        assert_eq!(
+            task.compute_only().unwrap(),
+            "dep data - explored".as_bytes().to_vec()
+        );
+        assert!(!task.get_target().unwrap().exists().unwrap());
+        assert!(Dep {}.get_target().unwrap().exists().unwrap());
+
+        task.recursively_delete_data().unwrap();
+        task.run_deps_only().unwrap();
+        assert!(Dep {}.get_target().unwrap().exists().unwrap());
+        assert!(!task.get_target().unwrap().exists().unwrap());
+    }
+
+    #[test]
+    fn enforce_cache_limit() {
+        use crate::tasks::enforce_cache_limit;
+        use std::{
+            fs,
+            path::Path,
+            time::{Duration, SystemTime},
+        };
+
+        let dir = Path::new("/tmp/test_enforce_cache_limit");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        // file0 is the oldest, file4 the newest
+        let files = (0..5)
+            .map(|i| {
+                let subdir = if i % 2 == 0 { "" } else { "sub" };
+                dir.join(subdir).join(format!("file{}.bin", i))
+            })
+            .collect::<Vec<_>>();
+        for (i, file) in files.iter().enumerate() {
+            fs::write(file, [0u8; 100]).unwrap();
+            let used = SystemTime::now() - Duration::from_secs(3600 * (5 - i as u64));
+            fs::File::options()
+                .write(true)
+                .open(file)
+                .unwrap()
+                .set_times(fs::FileTimes::new().set_accessed(used).set_modified(used))
+                .unwrap();
+        }
+        // the oldest file is protected, and file1 has a signature
+        fs::write(dir.join("file0.bin.keep"), "").unwrap();
+        fs::write(dir.join("sub/file1.bin.sig"), [0u8; 10]).unwrap();
+
+        assert!(enforce_cache_limit(dir.to_str().unwrap(), 1000)
+            .unwrap()
+            .is_empty());
+        let evicted = enforce_cache_limit(dir.to_str().unwrap(), 300).unwrap();
+        assert_eq!(evicted, vec![files[1].clone(), files[2].clone()]);
+        assert!(!dir.join("sub/file1.bin.sig").exists());
+        for file in [&files[0], &files[3], &files[4]] {
+            assert!(file.exists());
+        }
+    }
+
+    #[test]
+    fn dated_file_target() {
+        let ft = DatedFileTarget::new(
+            "/tmp",
+            "dated_test_target.txt",
+            chrono::NaiveDate::from_ymd(2021, 9, 3),
+        );
+        ft.delete().unwrap();
+        assert!(!ft.exists().expect("exists failed"));
+        ft.write("test data".as_bytes()).unwrap();
+        assert!(ft.exists().expect("exists failed"));
+        assert_eq!(ft.read().unwrap(), "test data".as_bytes().to_vec());
+    }
+
+    #[test]
+    fn dated_file_target_delegates() {
+        let ft = DatedFileTarget::new(
+            "/tmp",
+            "dated_delegate_target.txt",
+            chrono::NaiveDate::from_ymd(2021, 9, 3),
+        );
+        let dated = std::path::Path::new("/tmp/20210903_dated_delegate_target.txt");
+        assert_eq!(ft.filename(), dated);
+        ft.write("test data".as_bytes()).unwrap();
+        assert_eq!(
+            std::fs::read(dated).unwrap(),
+            "test data".as_bytes().to_vec()
+        );
+        assert_eq!(ft.uri().unwrap(), format!("file://{}", dated.display()));
+        // the sidecar sits next to the dated file
+        let sidecar = ft.sidecar("sig").unwrap();
+        sidecar.write("sig".as_bytes()).unwrap();
+        assert!(std::path::Path::new("/tmp/20210903_dated_delegate_target.txt.sig").is_file());
+        sidecar.delete().unwrap();
+        ft.delete().unwrap();
+        assert!(!dated.exists());
+    }
+
+    #[test]
+    fn date_time_file_target() {
+        let date = chrono::NaiveDate::from_ymd(2021, 9, 3);
+        let morning = DateTimeFileTarget::new("/tmp", "intraday.txt", date.and_hms(9, 30, 0));
+        let evening = DateTimeFileTarget::new("/tmp", "intraday.txt", date.and_hms(17, 0, 5));
+        assert_eq!(
+            morning.filename(),
+            std::path::Path::new("/tmp/20210903T093000_intraday.txt")
+        );
+        morning.write("morning".as_bytes()).unwrap();
+        evening.write("evening".as_bytes()).unwrap();
+        // intraday runs don't clobber each other
+        assert_eq!(morning.read().unwrap(), "morning".as_bytes().to_vec());
+        assert_eq!(evening.read().unwrap(), "evening".as_bytes().to_vec());
+
+        morning.delete().unwrap();
+        assert!(!morning.exists().unwrap());
+        assert!(evening.exists().unwrap());
+    }
+
+    #[test]
+    fn retention() {
+        #[derive(Debug)]
+        struct Daily {
+            date: chrono::NaiveDate,
+        }
+        impl Task for Daily {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(DatedFileTarget::new(
+                    "/tmp/test_retention",
+                    "daily.txt",
+                    self.date,
+                )))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok(self.date.to_string().into_bytes())
+            }
+
+            fn retention(&self) -> Option<usize> {
+                Some(3)
+            }
+        }
+
+        let dir = "/tmp/test_retention";
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        // another task's output isn't touched
+        FileTarget::new(dir, "20210901_other.txt")
+            .write("other".as_bytes())
+            .unwrap();
+        for day in 1..=5 {
+            Daily {
+                date: chrono::NaiveDate::from_ymd(2021, 9, day),
+            }
+            .run()
+            .unwrap();
+        }
+        let mut files = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| !name.ends_with(".sig"))
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                "20210901_other.txt",
+                "20210903_daily.txt",
+                "20210904_daily.txt",
+                "20210905_daily.txt",
+            ]
+        );
+        // sidecars of deleted outputs are deleted too
+        assert!(!std::path::Path::new(dir)
+            .join("20210901_daily.txt.sig")
+            .exists());
+    }
+
+    #[test]
+    fn series_validator() {
+        let dir = "/tmp/test_series_validator";
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        let write_series = |counts: &[usize]| {
+            for (day, count) in counts.iter().enumerate() {
+                let date = chrono::NaiveDate::from_ymd(2021, 9, day as u32 + 1);
+                let records = vec![0; *count];
+                DatedFileTarget::new(dir, "records.json", date)
+                    .write(&serde_json::to_vec(&records).unwrap())
+                    .unwrap();
+            }
+        };
+        let record_count =
+            |data: &[u8]| -> Result<usize> { Ok(serde_json::from_slice::<Vec<u32>>(data)?.len()) };
+        let validator = SeriesValidator::new(dir, "records.json", move |previous, current| {
+            Ok(record_count(current)? >= record_count(previous)?)
+        });
+
+        write_series(&[1, 2, 2, 3]);
+        validator.validate().unwrap();
+
+        // the count drops from 2021-09-03 to 2021-09-04
+        write_series(&[1, 2, 3, 2, 4]);
+        let err = validator.validate().unwrap_err();
+        let violation = err.downcast_ref::<SeriesViolation>().unwrap();
+        assert_eq!(violation.previous, chrono::NaiveDate::from_ymd(2021, 9, 3));
+        assert_eq!(violation.date, chrono::NaiveDate::from_ymd(2021, 9, 4));
+    }
+
+    #[test]
+    fn write_creates_cache_dir() {
+        let dir = "/tmp/test_write_creates_cache_dir";
+        let _ = std::fs::remove_dir_all(dir);
+        let target = FileTarget::new(&format!("{}/nested/deeper", dir), "output.txt");
+        target.write(b"nested").unwrap();
+        assert_eq!(target.read().unwrap(), b"nested".to_vec());
 
-    use crate::tasks::{DatedFileTarget, FileTarget, Target, Task};
+        // DatedFileTarget writes through a FileTarget
+        let dated = DatedFileTarget::new(
+            &format!("{}/dated", dir),
+            "output.txt",
+            chrono::NaiveDate::from_ymd(2021, 9, 1),
+        );
+        dated.write(b"dated").unwrap();
+        assert!(dated.filename().is_file());
+    }
 
     #[test]
-    fn file_target() {
-        let ft = FileTarget::new("/tmp", "test_target.txt");
-        ft.delete().unwrap();
-        assert!(!ft.exists().expect("exists failed"));
-        ft.write("test data".as_bytes()).unwrap();
-        assert!(ft.exists().expect("exists failed"));
-        assert_eq!(ft.read().unwrap(), "test data".as_bytes().to_vec());
+    fn in_cache_dir() {
+        use crate::tasks::CACHE_DIR_ENV;
+
+        let dir = "/tmp/test_in_cache_dir";
+        let _ = std::fs::remove_dir_all(dir);
+        std::env::set_var(CACHE_DIR_ENV, dir);
+        let target = FileTarget::in_cache_dir("output.txt").unwrap();
+        std::env::remove_var(CACHE_DIR_ENV);
+        assert_eq!(
+            target.filename(),
+            std::path::Path::new(dir).join("output.txt")
+        );
+        assert!(std::path::Path::new(dir).is_dir());
+        target.write(b"cached").unwrap();
+        assert_eq!(target.read().unwrap(), b"cached".to_vec());
     }
 
     #[test]
-    fn dated_file_target() {
-        let ft = DatedFileTarget::new(
-            "/tmp",
-            "dated_test_target.txt",
-            chrono::NaiveDate::from_ymd(2021, 9, 3),
+    fn target_config() {
+        std::fs::create_dir_all("/tmp/test_target_config_scratch").unwrap();
+        std::fs::create_dir_all("/tmp/test_target_config_shared").unwrap();
+        std::env::set_var(
+            "RUST_TASKS_STORAGE_TESTCONFIGSHARED",
+            "/tmp/test_target_config_shared",
+        );
+        let config =
+            TargetConfig::from_env().with_class("scratch", "/tmp/test_target_config_scratch");
+        assert!(config.cache_dir("archive").is_err());
+
+        let scratch = config.file_target("scratch", "output.txt").unwrap();
+        let shared = config
+            .dated_file_target(
+                "testconfigshared",
+                "output.txt",
+                chrono::NaiveDate::from_ymd(2021, 9, 3),
+            )
+            .unwrap();
+        scratch.write("scratch data".as_bytes()).unwrap();
+        shared.write("shared data".as_bytes()).unwrap();
+        assert_eq!(
+            std::fs::read("/tmp/test_target_config_scratch/output.txt").unwrap(),
+            "scratch data".as_bytes().to_vec()
+        );
+        assert_eq!(
+            std::fs::read("/tmp/test_target_config_shared/20210903_output.txt").unwrap(),
+            "shared data".as_bytes().to_vec()
         );
-        ft.delete().unwrap();
-        assert!(!ft.exists().expect("exists failed"));
-        ft.write("test data".as_bytes()).unwrap();
-        assert!(ft.exists().expect("exists failed"));
-        assert_eq!(ft.read().unwrap(), "test data".as_bytes().to_vec());
     }
 
     #[test]
@@ -332,6 +3290,45 @@ mod tests {
     }
 
     #[test]
+    fn stale_relative_to() {
+        #[derive(Debug)]
+        struct Output {}
+        impl Task for Output {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new("/tmp", "test_stale_output.txt")))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok("output".as_bytes().to_vec())
+            }
+        }
+
+        let task = Output {};
+        let input = FileTarget::new("/tmp", "test_stale_input.txt");
+        let deps: Vec<Box<dyn Target>> = vec![
+            Box::new(FileTarget::new("/tmp", "test_stale_input.txt")),
+            Box::new(InMemoryTarget::new()),
+        ];
+        input.write("input".as_bytes()).unwrap();
+        task.get_target().unwrap().delete().unwrap();
+        assert_eq!(task.get_target().unwrap().last_modified().unwrap(), None);
+        assert!(!task.is_stale_relative_to(&deps).unwrap());
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        task.run().unwrap();
+        assert!(
+            task.get_target().unwrap().last_modified().unwrap() > input.last_modified().unwrap()
+        );
+        assert!(!task.is_stale_relative_to(&deps).unwrap());
+
+        // rewriting the input makes the output stale
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        input.write("new input".as_bytes()).unwrap();
+        assert!(task.is_stale_relative_to(&deps).unwrap());
+    }
+
+    #[test]
+    #[allow(clippy::nonminimal_bool)]
     fn validation() {
         #[derive(Debug)]
         struct FileTask {
@@ -369,14 +3366,92 @@ mod tests {
         assert!(!task.run().is_err());
     }
 
+    #[test]
+    fn delete_on_validation_failure() {
+        #[derive(Debug)]
+        struct Dep {
+            filename: &'static str,
+        }
+        impl Task for Dep {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new("/tmp", self.filename)))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok("good data".as_bytes().to_vec())
+            }
+        }
+
+        #[derive(Debug)]
+        struct Checked {
+            name: &'static str,
+            delete_on_failure: bool,
+        }
+        impl Task for Checked {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new(
+                    "/tmp",
+                    &format!("test_{}_target.txt", self.name),
+                )))
+            }
+
+            fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                let mut result = HashMap::<String, Box<dyn Task>>::new();
+                let filename = match self.delete_on_failure {
+                    true => "test_delete_on_failure_dep.txt",
+                    false => "test_keep_on_failure_dep.txt",
+                };
+                result.insert("dep".to_string(), Box::new(Dep { filename }));
+                Ok(result)
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                self.get_dep_targets()?.get("dep").unwrap().read()
+            }
+
+            fn validate(&self, data: &[u8]) -> Result<()> {
+                if data.starts_with("bad".as_bytes()) {
+                    Err(anyhow!("bad data!"))
+                } else {
+                    Ok(())
+                }
+            }
+
+            fn delete_on_validation_failure(&self) -> bool {
+                self.delete_on_failure
+            }
+        }
+
+        for delete_on_failure in [true, false] {
+            let task = Checked {
+                name: match delete_on_failure {
+                    true => "delete_on_failure",
+                    false => "keep_on_failure",
+                },
+                delete_on_failure,
+            };
+            task.recursively_delete_data().unwrap();
+            task.run().unwrap();
+            let target = task.get_target().unwrap();
+            assert!(target.exists().unwrap());
+
+            // the changed dependency triggers a recompute, which fails validation
+            let dep_target = task.get_dep_targets().unwrap().remove("dep").unwrap();
+            dep_target.write("bad data".as_bytes()).unwrap();
+            assert!(task.run().is_err());
+            assert_eq!(target.exists().unwrap(), !delete_on_failure);
+        }
+    }
+
     #[test]
     fn serde_task() {
         #[derive(Debug)]
-        struct FileTask {
+        struct ValueTask {
             value: f64,
+            target: InMemoryTarget,
         }
 
-        impl FileTask {
+        impl ValueTask {
             fn get_value(&self) -> f64 {
                 let v: f64 = serde_json::from_slice(
                     &self.get_target().expect("Can't get target").read().unwrap(),
@@ -386,12 +3461,9 @@ mod tests {
             }
         }
 
-        impl Task for FileTask {
+        impl Task for ValueTask {
             fn get_target(&self) -> Result<Box<dyn Target>> {
-                Ok(Box::new(FileTarget::new(
-                    "/tmp",
-                    "test_serde_task_target.txt",
-                )))
+                Ok(Box::new(self.target.clone()))
             }
 
             fn compute_output(&self) -> Result<Vec<u8>> {
@@ -400,15 +3472,35 @@ mod tests {
             }
         }
 
-        let task = FileTask { value: 1.23 };
-        let target = task.get_target().expect("Can't get target");
-        // test with no starting data
-        target.delete().unwrap();
+        // starts with no data, and nothing is left to clean up
+        let task = ValueTask {
+            value: 1.23,
+            target: InMemoryTarget::new(),
+        };
         // generate the data
         task.run().unwrap();
         assert_eq!(task.get_value(), 1.23);
     }
 
+    #[test]
+    fn in_memory_target() {
+        let target = InMemoryTarget::new();
+        let clone = target.clone();
+        assert!(!target.exists().unwrap());
+        assert!(target.read().is_err());
+
+        clone.write("shared".as_bytes()).unwrap();
+        assert!(target.exists().unwrap());
+        assert_eq!(target.read().unwrap(), "shared".as_bytes().to_vec());
+
+        target.delete().unwrap();
+        assert!(!clone.exists().unwrap());
+
+        // separate targets don't share data
+        clone.write("shared".as_bytes()).unwrap();
+        assert!(!InMemoryTarget::new().exists().unwrap());
+    }
+
     #[test]
     fn serde_struct_task() {
         // the thing we want to compute and cache
@@ -464,6 +3556,98 @@ mod tests {
         assert_eq!(value, read_value);
     }
 
+    #[test]
+    fn schema_version() {
+        use crate::tasks::{encode_typed, read_typed, SchemaMismatch};
+
+        #[derive(Debug)]
+        struct Versioned {
+            version: u32,
+            upgrade: bool,
+        }
+
+        impl Task for Versioned {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new("/tmp", "test_schema_version.txt")))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                encode_typed(self, &vec![1, 2, 3])
+            }
+
+            fn output_schema_version(&self) -> u32 {
+                self.version
+            }
+
+            fn upgrade_output(&self, _from_version: u32, data: Vec<u8>) -> Result<Vec<u8>> {
+                if !self.upgrade {
+                    return Err(anyhow!("no upgrade"));
+                }
+                // the old format is compatible
+                Ok(data)
+            }
+        }
+
+        let old = Versioned {
+            version: 1,
+            upgrade: false,
+        };
+        old.delete_data().unwrap();
+        old.run().unwrap();
+        assert_eq!(read_typed::<_, Vec<i32>>(&old).unwrap(), vec![1, 2, 3]);
+
+        // a newer task without an upgrade hook sees the mismatch
+        let err = read_typed::<_, Vec<i32>>(&Versioned {
+            version: 2,
+            upgrade: false,
+        })
+        .unwrap_err();
+        assert_eq!(err.to_string(), "no upgrade");
+
+        // the default hook reports the mismatch
+        #[derive(Debug)]
+        struct DefaultHook {}
+        impl Task for DefaultHook {
+            fn get_name(&self) -> String {
+                "DefaultHook".to_string()
+            }
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Versioned {
+                    version: 3,
+                    upgrade: false,
+                }
+                .get_target()
+            }
+            fn output_schema_version(&self) -> u32 {
+                3
+            }
+        }
+        let err = read_typed::<_, Vec<i32>>(&DefaultHook {}).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SchemaMismatch>(),
+            Some(&SchemaMismatch {
+                task: "DefaultHook".to_string(),
+                expected: 3,
+                found: 1,
+            })
+        );
+
+        // the upgrade hook can convert older outputs
+        let upgraded = Versioned {
+            version: 2,
+            upgrade: true,
+        };
+        assert_eq!(read_typed::<_, Vec<i32>>(&upgraded).unwrap(), vec![1, 2, 3]);
+
+        // outputs from newer code are always rejected
+        let err = read_typed::<_, Vec<i32>>(&Versioned {
+            version: 0,
+            upgrade: true,
+        })
+        .unwrap_err();
+        assert!(err.downcast_ref::<SchemaMismatch>().is_some());
+    }
+
     #[test]
     fn dependent_file_task() {
         #[derive(Debug)]
@@ -541,6 +3725,267 @@ mod tests {
             "dep1 data - dep2 data".as_bytes().to_vec()
         );
     }
+
+    #[test]
+    fn recursively_delete_shared_dep_once() {
+        /// Target counting delete calls
+        struct CountingTarget {
+            deletes: Arc<AtomicUsize>,
+        }
+        impl Target for CountingTarget {
+            fn read(&self) -> Result<Vec<u8>> {
+                Ok(Vec::new())
+            }
+
+            fn write(&self, _: &[u8]) -> Result<()> {
+                Ok(())
+            }
+
+            fn delete(&self) -> Result<()> {
+                self.deletes.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+
+            fn exists(&self) -> Result<bool> {
+                Ok(false)
+            }
+
+            fn uri(&self) -> Option<String> {
+                Some("test://shared".to_string())
+            }
+        }
+
+        #[derive(Debug)]
+        struct Shared {
+            deletes: Arc<AtomicUsize>,
+        }
+        impl Task for Shared {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(CountingTarget {
+                    deletes: self.deletes.clone(),
+                }))
+            }
+        }
+
+        /// Diamond: the head depends on left and right, which both depend on Shared
+        #[derive(Debug)]
+        struct Node {
+            name: &'static str,
+            deps: Vec<&'static str>,
+            deletes: Arc<AtomicUsize>,
+        }
+        impl Task for Node {
+            fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                let mut result = HashMap::<String, Box<dyn Task>>::new();
+                for dep in &self.deps {
+                    result.insert(
+                        dep.to_string(),
+                        Box::new(Node {
+                            name: dep,
+                            deps: Vec::new(),
+                            deletes: self.deletes.clone(),
+                        }),
+                    );
+                }
+                if self.name != "head" {
+                    result.insert(
+                        "shared".to_string(),
+                        Box::new(Shared {
+                            deletes: self.deletes.clone(),
+                        }),
+                    );
+                }
+                Ok(result)
+            }
+        }
+
+        let deletes = Arc::new(AtomicUsize::new(0));
+        let head = Node {
+            name: "head",
+            deps: vec!["left", "right"],
+            deletes: deletes.clone(),
+        };
+        head.recursively_delete_data().unwrap();
+        assert_eq!(deletes.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn unchanged_deps_skip_recompute() {
+        #[derive(Debug)]
+        struct Dep {}
+        impl Task for Dep {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new("/tmp", "test_versions_dep.txt")))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok("dep data".as_bytes().to_vec())
+            }
+        }
+
+        #[derive(Debug)]
+        struct Downstream {
+            computed: Arc<AtomicUsize>,
+        }
+        impl Task for Downstream {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new(
+                    "/tmp",
+                    "test_versions_downstream.txt",
+                )))
+            }
+
+            fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                let mut result = HashMap::<String, Box<dyn Task>>::new();
+                result.insert("dep".to_string(), Box::new(Dep {}));
+                Ok(result)
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                self.computed.fetch_add(1, Ordering::SeqCst);
+                self.get_dep_targets()?.get("dep").unwrap().read()
+            }
+        }
+
+        let computed = Arc::new(AtomicUsize::new(0));
+        let task = Downstream {
+            computed: computed.clone(),
+        };
+        task.recursively_delete_data().unwrap();
+        task.run().unwrap();
+        assert_eq!(computed.load(Ordering::SeqCst), 1);
+
+        // dependency versions are unchanged so the existing output is kept
+        task.run().unwrap();
+        assert_eq!(computed.load(Ordering::SeqCst), 1);
+
+        // rewriting the dependency changes its version, which forces a recompute
+        Dep {}
+            .get_target()
+            .unwrap()
+            .write("new dep data".as_bytes())
+            .unwrap();
+        task.run().unwrap();
+        assert_eq!(computed.load(Ordering::SeqCst), 2);
+        assert_eq!(task.get_data().unwrap(), "new dep data".as_bytes().to_vec());
+
+        // deleting the data deletes the recorded versions too
+        let sidecar = FileTarget::new("/tmp", "test_versions_downstream.txt.deps");
+        assert!(sidecar.exists().unwrap());
+        task.delete_data().unwrap();
+        assert!(!sidecar.exists().unwrap());
+        assert!(!FileTarget::new("/tmp", "test_versions_downstream.txt.sig")
+            .exists()
+            .unwrap());
+    }
+
+    #[test]
+    fn rebuild_policy() {
+        /// File without a version, so only timestamps tell that it changed
+        #[derive(Debug)]
+        struct Unversioned(FileTarget);
+        impl Target for Unversioned {
+            fn read(&self) -> Result<Vec<u8>> {
+                self.0.read()
+            }
+
+            fn write(&self, s: &[u8]) -> Result<()> {
+                self.0.write(s)
+            }
+
+            fn delete(&self) -> Result<()> {
+                self.0.delete()
+            }
+
+            fn exists(&self) -> Result<bool> {
+                self.0.exists()
+            }
+
+            fn last_modified(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+                self.0.last_modified()
+            }
+        }
+
+        #[derive(Debug)]
+        struct Dep {}
+        impl Task for Dep {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(Unversioned(FileTarget::new(
+                    "/tmp",
+                    "test_rebuild_policy_dep.txt",
+                ))))
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                Ok("dep data".as_bytes().to_vec())
+            }
+        }
+
+        #[derive(Debug)]
+        struct Downstream {
+            policy: RebuildPolicy,
+            computed: Arc<AtomicUsize>,
+        }
+        impl Task for Downstream {
+            fn get_target(&self) -> Result<Box<dyn Target>> {
+                Ok(Box::new(FileTarget::new(
+                    "/tmp",
+                    "test_rebuild_policy_downstream.txt",
+                )))
+            }
+
+            fn get_dep_tasks(&self) -> Result<HashMap<String, Box<dyn Task>>> {
+                let mut result = HashMap::<String, Box<dyn Task>>::new();
+                result.insert("dep".to_string(), Box::new(Dep {}));
+                Ok(result)
+            }
+
+            fn rebuild_policy(&self) -> RebuildPolicy {
+                self.policy
+            }
+
+            fn compute_output(&self) -> Result<Vec<u8>> {
+                self.computed.fetch_add(1, Ordering::SeqCst);
+                self.get_dep_targets()?.get("dep").unwrap().read()
+            }
+        }
+
+        let computed = Arc::new(AtomicUsize::new(0));
+        let task = |policy| Downstream {
+            policy,
+            computed: computed.clone(),
+        };
+        task(RebuildPolicy::ExistsOnly)
+            .recursively_delete_data()
+            .unwrap();
+        task(RebuildPolicy::Newer).run().unwrap();
+        task(RebuildPolicy::Newer).run().unwrap();
+        assert_eq!(computed.load(Ordering::SeqCst), 1);
+
+        // rewrite the dependency after the downstream target
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        Dep {}
+            .get_target()
+            .unwrap()
+            .write("new dep data".as_bytes())
+            .unwrap();
+        task(RebuildPolicy::ExistsOnly).run().unwrap();
+        assert_eq!(computed.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            task(RebuildPolicy::ExistsOnly).get_data().unwrap(),
+            "dep data".as_bytes().to_vec()
+        );
+
+        task(RebuildPolicy::Newer).run_no_deps().unwrap();
+        assert_eq!(computed.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            task(RebuildPolicy::Newer).get_data().unwrap(),
+            "new dep data".as_bytes().to_vec()
+        );
+        // now the target is newer again
+        task(RebuildPolicy::Newer).run().unwrap();
+        assert_eq!(computed.load(Ordering::SeqCst), 2);
+    }
 }
 
 #[cfg(test)]
@@ -584,7 +4029,7 @@ mod sqlite_tests {
         }
 
         fn get_conn() -> Result<Connection> {
-            Ok(Connection::open(&TestTarget::get_filename())?)
+            Ok(Connection::open(TestTarget::get_filename())?)
         }
     }
 
@@ -602,10 +4047,8 @@ mod sqlite_tests {
                 })
             })?;
             let mut records: Vec<Record> = Vec::new();
-            for record_res in record_iter {
-                if let Ok(record) = record_res {
-                    records.push(record);
-                }
+            for record in record_iter.flatten() {
+                records.push(record);
             }
             let bytes: Vec<u8> = serde_json::to_string::<Vec<Record>>(&records)?
                 .as_bytes()